@@ -1,6 +1,7 @@
 //! Abstract Syntax Tree definitions for H2 Language.
 
 use crate::token::Span;
+use serde::{Deserialize, Serialize};
 
 // =============================================================================
 // Directives and Limits
@@ -23,7 +24,7 @@ pub struct LimitConfig {
     /// Default: 1,000,000
     pub max_step: usize,
     /// Maximum recursion depth
-    /// Default: 100
+    /// Default: 10,000
     pub max_depth: usize,
     /// Maximum memory usage during expansion (in bytes)
     /// Default: 1,000,000
@@ -36,9 +37,9 @@ impl Default for LimitConfig {
     fn default() -> Self {
         Self {
             max_step: 1_000_000,
-            // Depth limit to prevent Rust stack overflow
-            // Spec default is 100; safe for deep nested calls
-            max_depth: 100,
+            // The expander keeps its call stack on the heap, so depth only
+            // guards against non-productive recursion such as `a:a a`
+            max_depth: 10_000,
             max_memory: 1_000_000,
             // HOJ compatibility: truncate by default instead of error
             on_limit: OnLimitBehavior::Truncate,
@@ -46,6 +47,54 @@ impl Default for LimitConfig {
     }
 }
 
+/// How collisions between placed agents are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnCollision {
+    /// Report collisions as warnings (default)
+    #[default]
+    Warn,
+    /// Fail compilation
+    Error,
+    /// Delay agents with waits until no collision remains
+    Wait,
+}
+
+/// Order in which agents get the right of way when waits are inserted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    /// Lower agent IDs go first (default)
+    #[default]
+    LowId,
+    /// Higher agent IDs go first
+    HighId,
+    /// Agents with more commands go first; ties go to the lower ID
+    Longest,
+}
+
+/// What the timeline shows for an agent whose commands have run out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnEnd {
+    /// The agent is left out of later steps (default)
+    #[default]
+    Omit,
+    /// The agent waits for the rest of the timeline
+    Stop,
+    /// The agent starts its commands again from the beginning
+    Loop,
+}
+
+/// Multi-agent scheduling configuration (derived from directives).
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleConfig {
+    /// Behavior when agents collide (`ON_COLLISION`)
+    pub on_collision: OnCollision,
+    /// Right of way with `ON_COLLISION=WAIT` (`PRIORITY`)
+    pub priority: Priority,
+    /// Timeline entries for finished agents (`ON_END`)
+    pub on_end: OnEnd,
+}
+
 /// A single directive (e.g., MAX_STEP=1000).
 #[derive(Debug, Clone)]
 pub struct Directive {
@@ -75,6 +124,8 @@ pub enum Primitive {
     Right,
     /// 'l' - rotate left (90° counter-clockwise)
     Left,
+    /// 'w' - stand still for one step (`EXTENSION=WAIT`)
+    Wait,
 }
 
 impl Primitive {
@@ -84,6 +135,7 @@ impl Primitive {
             Primitive::Straight => 's',
             Primitive::Right => 'r',
             Primitive::Left => 'l',
+            Primitive::Wait => 'w',
         }
     }
 }
@@ -199,6 +251,8 @@ pub struct FuncDef {
     pub body: Expr,
     /// Source location
     pub span: Span,
+    /// Defined in the shared block before the first agent line
+    pub shared: bool,
 }
 
 /// Definition (function only - macros are 0-arg functions).
@@ -243,6 +297,11 @@ pub struct Program {
     pub directives: Vec<Directive>,
     /// Execution limit configuration (derived from directives)
     pub limits: LimitConfig,
+    /// Scheduling configuration (derived from directives)
+    pub schedule: ScheduleConfig,
+    /// Shared definitions visible to every agent; each agent also holds a
+    /// copy of those it does not shadow in its own `definitions`
+    pub shared: Vec<Definition>,
     /// List of agents
     pub agents: Vec<Agent>,
 }
@@ -0,0 +1,201 @@
+//! Collision detection between agents.
+//!
+//! Agents that share a floor can run into each other. Given each agent's
+//! path (see [`CompiledProgram::agent_paths`](crate::output::CompiledProgram::agent_paths)),
+//! this module finds every step at which two agents end up in the same cell
+//! (a vertex collision) or swap cells (an edge collision).
+//!
+//! Agents that have run out of commands stay on their last cell and can
+//! still be hit.
+
+use crate::ast::OnCollision;
+use crate::error::{CompileError, ErrorCode, Severity};
+use crate::output::AgentPose;
+use crate::token::Span;
+use crate::world::Position;
+use serde::{Deserialize, Serialize};
+
+/// Kind of collision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionKind {
+    /// Both agents occupy the same cell after the step
+    Vertex,
+    /// The agents swap cells during the step
+    Edge,
+}
+
+/// Two agents colliding at one step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Collision {
+    /// Vertex or edge collision
+    pub kind: CollisionKind,
+    /// Step number (0-based, matches the timeline)
+    pub step: usize,
+    /// IDs of the two agents, in agent order
+    pub agents: [u32; 2],
+    /// The shared cell, or for a swap the cell the first agent moves into
+    pub position: Position,
+}
+
+impl Collision {
+    /// Human-readable description.
+    pub fn message(&self) -> String {
+        let [a, b] = self.agents;
+        let Position { x, y } = self.position;
+        match self.kind {
+            CollisionKind::Vertex => format!(
+                "Agents {} and {} collide at ({}, {}) at step {}",
+                a, b, x, y, self.step
+            ),
+            CollisionKind::Edge => format!(
+                "Agents {} and {} swap cells at ({}, {}) at step {}",
+                a, b, x, y, self.step
+            ),
+        }
+    }
+
+    /// Diagnostic for this collision, located at `span` (normally the first
+    /// agent's line): E013 with [`OnCollision::Error`], W005 otherwise.
+    pub fn to_compile_error(&self, on_collision: OnCollision, span: Span) -> CompileError {
+        let (code, severity) = match on_collision {
+            OnCollision::Error => (ErrorCode::E013, Severity::Error),
+            OnCollision::Warn | OnCollision::Wait => (ErrorCode::W005, Severity::Warning),
+        };
+        CompileError::coded(code, severity, self.message(), span)
+    }
+}
+
+/// Find every collision between the given paths.
+///
+/// Each path is an agent's start pose followed by its pose after each
+/// command. A pair of agents that stays on the same cell is reported once, at
+/// the step the overlap starts. Multi-cell moves are checked at their end
+/// cells only. Collisions are ordered by step, then by agent order.
+pub fn detect_collisions(paths: &[Vec<AgentPose>]) -> Vec<Collision> {
+    let steps = paths
+        .iter()
+        .map(|p| p.len().saturating_sub(1))
+        .max()
+        .unwrap_or(0);
+    let at = |path: &[AgentPose], index: usize| path[index.min(path.len() - 1)].position();
+
+    let mut collisions = Vec::new();
+    for step in 0..steps {
+        for (i, a) in paths.iter().enumerate() {
+            for b in &paths[i + 1..] {
+                let (a_before, a_after) = (at(a, step), at(a, step + 1));
+                let (b_before, b_after) = (at(b, step), at(b, step + 1));
+                let agents = [a[0].agent_id, b[0].agent_id];
+
+                // Report an overlap only when it begins
+                if a_after == b_after && (step == 0 || a_before != b_before) {
+                    collisions.push(Collision {
+                        kind: CollisionKind::Vertex,
+                        step,
+                        agents,
+                        position: a_after,
+                    });
+                } else if a_before != a_after && a_before == b_after && b_before == a_after {
+                    collisions.push(Collision {
+                        kind: CollisionKind::Edge,
+                        step,
+                        agents,
+                        position: a_after,
+                    });
+                }
+            }
+        }
+    }
+    collisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_program;
+
+    fn pose(agent_id: u32, x: i32, y: i32, heading: u8) -> AgentPose {
+        AgentPose {
+            agent_id,
+            x,
+            y,
+            heading,
+        }
+    }
+
+    fn collisions(source: &str, starts: &[AgentPose]) -> Vec<Collision> {
+        detect_collisions(&compile_program(source).agent_paths(starts).unwrap())
+    }
+
+    #[test]
+    fn test_no_collision() {
+        let starts = [pose(0, 0, 0, 0), pose(1, 2, 0, 0)];
+        assert!(collisions("0: ssss\n1: ssss", &starts).is_empty());
+    }
+
+    #[test]
+    fn test_vertex_collision() {
+        // Both move into (1, 0) at step 0
+        let starts = [pose(0, 0, 0, 1), pose(1, 2, 0, 3)];
+        let found = collisions("0: s\n1: s", &starts);
+        assert_eq!(
+            found,
+            vec![Collision {
+                kind: CollisionKind::Vertex,
+                step: 0,
+                agents: [0, 1],
+                position: Position::new(1, 0),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_edge_collision() {
+        let starts = [pose(0, 0, 0, 1), pose(1, 1, 0, 3)];
+        let found = collisions("0: rrs\n1: lls", &starts);
+        assert!(found.is_empty());
+        let found = collisions("0: s\n1: s", &starts);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, CollisionKind::Edge);
+        assert_eq!(found[0].position, Position::new(1, 0));
+    }
+
+    #[test]
+    fn test_finished_agent_can_be_hit() {
+        // Agent 1 has no commands and sits at (0, -3)
+        let starts = [pose(0, 0, 0, 0), pose(1, 0, -3, 0)];
+        let found = collisions("0: ssss\n1: ", &starts);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].step, found[0].kind), (2, CollisionKind::Vertex));
+    }
+
+    #[test]
+    fn test_overlap_reported_once() {
+        // Both start on the same cell and only turn
+        let found = collisions("0: rrr\n1: lll", &[]);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].step, 0);
+    }
+
+    #[test]
+    fn test_compile_error_codes() {
+        let collision = Collision {
+            kind: CollisionKind::Edge,
+            step: 4,
+            agents: [2, 5],
+            position: Position::new(1, -1),
+        };
+        let span = Span::new(10, 15, 2, 1);
+        let warning = collision.to_compile_error(OnCollision::Warn, span);
+        assert_eq!(warning.code, Some(ErrorCode::W005));
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(
+            warning.message,
+            "[W005] Agents 2 and 5 swap cells at (1, -1) at step 4"
+        );
+        let error = collision.to_compile_error(OnCollision::Error, span);
+        assert_eq!(error.code, Some(ErrorCode::E013));
+        assert_eq!((error.line, error.start), (2, 10));
+    }
+}
@@ -0,0 +1,227 @@
+//! Command compaction.
+//!
+//! The expander emits one [`ToioCommand`] per step, so `sss` becomes three
+//! one-step moves. [`compact`] merges runs of consecutive commands into the
+//! parameterized form a robot can execute in one go:
+//!
+//! - straight moves add up their `steps` (`sss` → one move of 3 steps);
+//! - rotations add up their `angle` and keep only the net turn (`rr` → 180°,
+//!   `rrr` → -90°, `rl` and `rrrr` → nothing);
+//! - waits are kept one per step.
+//!
+//! Every compacted command records the range of original step indices it
+//! replaces, so it can be lined up with the step-indexed timeline.
+
+use crate::output::{CommandType, ToioCommand};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A compacted command and the original steps it replaces.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactCommand {
+    /// Merged command
+    #[serde(flatten)]
+    pub command: ToioCommand,
+    /// First original step index (0-based, matches the timeline)
+    pub start_step: usize,
+    /// Original step index after the last merged one
+    pub end_step: usize,
+}
+
+impl CompactCommand {
+    /// Original step indices this command replaces.
+    pub fn steps(&self) -> Range<usize> {
+        self.start_step..self.end_step
+    }
+}
+
+/// Merge runs of straight moves and rotations in `commands`.
+///
+/// Rotation runs that cancel out produce no command; their steps are covered
+/// by no [`CompactCommand`]. Net rotations are normalized to -90°, 90° or
+/// 180° (`rotate_right` for 90° and 180°, `rotate_left` for -90°).
+pub fn compact(commands: &[ToioCommand]) -> Vec<CompactCommand> {
+    let mut compacted = Vec::new();
+    let mut start = 0;
+    while start < commands.len() {
+        let kind = run_kind(&commands[start]);
+        let end = match kind {
+            CommandType::Wait => start + 1,
+            _ => {
+                start
+                    + commands[start..]
+                        .iter()
+                        .take_while(|c| run_kind(c) == kind)
+                        .count()
+            }
+        };
+        let run = &commands[start..end];
+        let command = match kind {
+            CommandType::Straight => Some(ToioCommand {
+                steps: Some(run.iter().map(|c| c.steps.unwrap_or(1)).sum()),
+                ..ToioCommand::straight()
+            }),
+            CommandType::Wait => Some(ToioCommand::wait()),
+            _ => rotation(run.iter().map(ToioCommand::turn_degrees).sum()),
+        };
+        if let Some(command) = command {
+            compacted.push(CompactCommand {
+                command,
+                start_step: start,
+                end_step: end,
+            });
+        }
+        start = end;
+    }
+    compacted
+}
+
+/// Index of the compacted command that covers original step `step`, or
+/// `None` if the step was folded away or lies past the end.
+pub fn command_at(compacted: &[CompactCommand], step: usize) -> Option<usize> {
+    let index = compacted.partition_point(|c| c.end_step <= step);
+    compacted
+        .get(index)
+        .filter(|c| c.steps().contains(&step))
+        .map(|_| index)
+}
+
+/// Kind of run a command belongs to; both turn directions form one run.
+fn run_kind(command: &ToioCommand) -> CommandType {
+    match command.command_type {
+        CommandType::RotateLeft => CommandType::RotateRight,
+        kind => kind,
+    }
+}
+
+/// Single rotation by the net `angle`, or `None` for a full turn.
+fn rotation(angle: i32) -> Option<ToioCommand> {
+    match angle.rem_euclid(360) {
+        0 => None,
+        270 => Some(ToioCommand::rotate_left()),
+        net => Some(ToioCommand {
+            angle: Some(net),
+            ..ToioCommand::rotate_right()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compact one command per letter (`s`, `r`, `l`, `w`).
+    fn compact_str(letters: &str) -> Vec<CompactCommand> {
+        let commands: Vec<ToioCommand> = letters
+            .chars()
+            .map(|c| match c {
+                's' => ToioCommand::straight(),
+                'r' => ToioCommand::rotate_right(),
+                'l' => ToioCommand::rotate_left(),
+                _ => ToioCommand::wait(),
+            })
+            .collect();
+        compact(&commands)
+    }
+
+    /// `(type, steps, angle, start..end)` of a compacted command.
+    type Summary = (CommandType, Option<u32>, Option<i32>, Range<usize>);
+
+    fn summary(compacted: &[CompactCommand]) -> Vec<Summary> {
+        compacted
+            .iter()
+            .map(|c| {
+                (
+                    c.command.command_type,
+                    c.command.steps,
+                    c.command.angle,
+                    c.steps(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_straight() {
+        assert_eq!(
+            summary(&compact_str("sssrs")),
+            vec![
+                (CommandType::Straight, Some(3), None, 0..3),
+                (CommandType::RotateRight, None, Some(90), 3..4),
+                (CommandType::Straight, Some(1), None, 4..5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_net_rotation() {
+        assert_eq!(
+            summary(&compact_str("rr")),
+            vec![(CommandType::RotateRight, None, Some(180), 0..2)]
+        );
+        assert_eq!(
+            summary(&compact_str("rrr")),
+            vec![(CommandType::RotateLeft, None, Some(-90), 0..3)]
+        );
+        assert_eq!(
+            summary(&compact_str("lrl")),
+            vec![(CommandType::RotateLeft, None, Some(-90), 0..3)]
+        );
+    }
+
+    #[test]
+    fn test_cancelling_rotations_removed() {
+        assert_eq!(
+            summary(&compact_str("srlsrrrrs")),
+            vec![
+                (CommandType::Straight, Some(1), None, 0..1),
+                (CommandType::Straight, Some(1), None, 3..4),
+                (CommandType::Straight, Some(1), None, 8..9),
+            ]
+        );
+        assert!(compact_str("rl").is_empty());
+    }
+
+    #[test]
+    fn test_waits_kept_per_step() {
+        assert_eq!(
+            summary(&compact_str("ssww")),
+            vec![
+                (CommandType::Straight, Some(2), None, 0..2),
+                (CommandType::Wait, None, None, 2..3),
+                (CommandType::Wait, None, None, 3..4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_command_at() {
+        // s s | r l | s
+        let compacted = compact_str("ssrls");
+        let found: Vec<_> = (0..6).map(|step| command_at(&compacted, step)).collect();
+        assert_eq!(found, vec![Some(0), Some(0), None, None, Some(1), None]);
+    }
+
+    #[test]
+    fn test_already_compacted_input() {
+        let commands = [
+            ToioCommand {
+                steps: Some(4),
+                ..ToioCommand::straight()
+            },
+            ToioCommand::straight(),
+            ToioCommand {
+                angle: Some(180),
+                ..ToioCommand::rotate_right()
+            },
+            ToioCommand::rotate_left(),
+        ];
+        assert_eq!(
+            summary(&compact(&commands)),
+            vec![
+                (CommandType::Straight, Some(5), None, 0..2),
+                (CommandType::RotateRight, None, Some(90), 2..4),
+            ]
+        );
+    }
+}
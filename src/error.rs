@@ -4,12 +4,151 @@ use crate::token::Span;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+/// Error and warning codes defined by the language specification (SPEC §7).
+///
+/// Serialized as the code itself, e.g. `"E004"`. `W` codes are lint warnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// Undefined function (0-arg call)
+    E001,
+    /// Undefined function (N-arg call)
+    E002,
+    /// Argument count mismatch
+    E003,
+    /// MAX_STEP exceeded
+    E004,
+    /// MAX_DEPTH exceeded
+    E005,
+    /// MAX_MEMORY exceeded
+    E006,
+    /// Numeric value out of range
+    E007,
+    /// Type error at call site
+    E008,
+    /// Invalid directive
+    E009,
+    /// Parameter type conflict
+    E010,
+    /// Duplicate function definition
+    E011,
+    /// Duplicate agent ID
+    E012,
+    /// Agents collide (with `ON_COLLISION=ERROR`)
+    E013,
+    /// Collision that waiting cannot avoid (with `ON_COLLISION=WAIT`)
+    E014,
+    /// H2 extension used in strict HOJ mode
+    E015,
+    /// toio target position outside the cube's coordinates
+    E016,
+    /// Expression in the shared definition block
+    E017,
+    /// Agent pose outside the grid's coordinate range
+    E018,
+    /// Unused definition (warning)
+    W001,
+    /// Unused parameter (warning)
+    W002,
+    /// Call that always returns empty (warning)
+    W003,
+    /// Recursion without a numeric decrease (warning)
+    W004,
+    /// Agents collide (warning)
+    W005,
+    /// Agent without a start pose left out of collision checks (warning)
+    W006,
+}
+
+impl ErrorCode {
+    /// The code as it appears in messages and JSON, e.g. `"E004"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::E001 => "E001",
+            ErrorCode::E002 => "E002",
+            ErrorCode::E003 => "E003",
+            ErrorCode::E004 => "E004",
+            ErrorCode::E005 => "E005",
+            ErrorCode::E006 => "E006",
+            ErrorCode::E007 => "E007",
+            ErrorCode::E008 => "E008",
+            ErrorCode::E009 => "E009",
+            ErrorCode::E010 => "E010",
+            ErrorCode::E011 => "E011",
+            ErrorCode::E012 => "E012",
+            ErrorCode::E013 => "E013",
+            ErrorCode::E014 => "E014",
+            ErrorCode::E015 => "E015",
+            ErrorCode::E016 => "E016",
+            ErrorCode::E017 => "E017",
+            ErrorCode::E018 => "E018",
+            ErrorCode::W001 => "W001",
+            ErrorCode::W002 => "W002",
+            ErrorCode::W003 => "W003",
+            ErrorCode::W004 => "W004",
+            ErrorCode::W005 => "W005",
+            ErrorCode::W006 => "W006",
+        }
+    }
+
+    /// Prefix `message` with this code, e.g. `[E004] MAX_STEP limit (10) exceeded`.
+    fn tag(self, message: impl Into<String>) -> String {
+        format!("[{}] {}", self.as_str(), message.into())
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Diagnostic severity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Compilation fails
+    #[default]
+    Error,
+    /// Reported, but compilation succeeds
+    Warning,
+}
+
+/// A secondary source location attached to an error, such as the first
+/// definition in a duplicate-definition error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelatedSpan {
+    /// Line number (1-based)
+    pub line: usize,
+    /// Column number (1-based)
+    pub column: usize,
+    /// Start byte offset from the beginning of the source
+    pub start: usize,
+    /// End byte offset (exclusive)
+    pub end: usize,
+}
+
+impl From<Span> for RelatedSpan {
+    fn from(span: Span) -> Self {
+        Self {
+            line: span.line,
+            column: span.column,
+            start: span.start,
+            end: span.end,
+        }
+    }
+}
+
 /// Lexer error.
 #[derive(Debug, Clone)]
 pub struct LexerError {
     pub message: String,
     pub line: usize,
     pub column: usize,
+    /// Error code, if the error is one defined by the specification
+    pub code: Option<ErrorCode>,
+    /// Byte range of the offending input
+    pub start: usize,
+    pub end: usize,
 }
 
 impl LexerError {
@@ -18,6 +157,29 @@ impl LexerError {
             message: message.into(),
             line,
             column,
+            code: None,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Create an error covering `span`.
+    pub fn at(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            line: span.line,
+            column: span.column,
+            code: None,
+            start: span.start,
+            end: span.end,
+        }
+    }
+
+    /// E009: Unknown directive name or value
+    pub fn invalid_directive(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            code: Some(ErrorCode::E009),
+            ..Self::at(ErrorCode::E009.tag(message), span)
         }
     }
 }
@@ -42,6 +204,13 @@ pub struct ParseError {
     pub column: usize,
     pub expected: Option<String>,
     pub found: Option<String>,
+    /// Error code, if the error is one defined by the specification
+    pub code: Option<ErrorCode>,
+    /// Byte range of the offending input
+    pub start: usize,
+    pub end: usize,
+    /// Earlier location the error refers to (e.g. the first definition)
+    pub related: Option<Box<Span>>,
 }
 
 impl ParseError {
@@ -52,6 +221,10 @@ impl ParseError {
             column: span.column,
             expected: None,
             found: None,
+            code: None,
+            start: span.start,
+            end: span.end,
+            related: None,
         }
     }
 
@@ -66,22 +239,95 @@ impl ParseError {
     }
 
     pub fn unexpected_token(expected: &str, found: &str, span: Span) -> Self {
+        Self::new("Unexpected token", span)
+            .with_expected(expected)
+            .with_found(found)
+    }
+
+    pub fn unexpected_eof(expected: &str, span: Span) -> Self {
+        Self::new("Unexpected end of input", span)
+            .with_expected(expected)
+            .with_found("end of input")
+    }
+
+    /// E009: Invalid directive name or value
+    pub fn invalid_directive(message: impl Into<String>, span: Span) -> Self {
         Self {
-            message: "Unexpected token".to_string(),
-            line: span.line,
-            column: span.column,
-            expected: Some(expected.to_string()),
-            found: Some(found.to_string()),
+            code: Some(ErrorCode::E009),
+            ..Self::new(ErrorCode::E009.tag(message), span)
         }
     }
 
-    pub fn unexpected_eof(expected: &str, span: Span) -> Self {
+    /// E010: Parameter used as both CmdSeq and Int
+    pub fn type_conflict(param: char, span: Span) -> Self {
         Self {
-            message: "Unexpected end of input".to_string(),
-            line: span.line,
-            column: span.column,
-            expected: Some(expected.to_string()),
-            found: Some("end of input".to_string()),
+            code: Some(ErrorCode::E010),
+            ..Self::new(
+                ErrorCode::E010.tag(format!(
+                    "Type conflict for parameter '{}': used as both CmdSeq and Int",
+                    param
+                )),
+                span,
+            )
+        }
+    }
+
+    /// E011: Function defined twice in the same scope
+    pub fn duplicate_function(name: char, span: Span, first: Span) -> Self {
+        Self {
+            code: Some(ErrorCode::E011),
+            related: Some(Box::new(first)),
+            ..Self::new(
+                ErrorCode::E011.tag(format!(
+                    "Function '{}' is already defined at line {}, column {}",
+                    name, first.line, first.column
+                )),
+                span,
+            )
+        }
+    }
+
+    /// E012: Agent ID used by more than one agent line
+    pub fn duplicate_agent(id: u32, span: Span, first: Span) -> Self {
+        Self {
+            code: Some(ErrorCode::E012),
+            related: Some(Box::new(first)),
+            ..Self::new(
+                ErrorCode::E012.tag(format!(
+                    "Agent {} is already defined at line {}, column {}",
+                    id, first.line, first.column
+                )),
+                span,
+            )
+        }
+    }
+
+    /// E017: Expression before the first agent line, where only shared
+    /// definitions may appear
+    pub fn shared_expression(span: Span) -> Self {
+        Self {
+            code: Some(ErrorCode::E017),
+            ..Self::new(
+                ErrorCode::E017
+                    .tag("Only definitions may appear before the first agent line".to_string()),
+                span,
+            )
+        }
+    }
+}
+
+impl From<LexerError> for ParseError {
+    fn from(e: LexerError) -> Self {
+        Self {
+            message: e.message,
+            line: e.line,
+            column: e.column,
+            expected: None,
+            found: None,
+            code: e.code,
+            start: e.start,
+            end: e.end,
+            related: None,
         }
     }
 }
@@ -111,6 +357,11 @@ pub struct ExpandError {
     pub message: String,
     pub line: usize,
     pub column: usize,
+    /// Error code, if the error is one defined by the specification
+    pub code: Option<ErrorCode>,
+    /// Byte range of the offending expression
+    pub start: usize,
+    pub end: usize,
 }
 
 impl ExpandError {
@@ -119,70 +370,91 @@ impl ExpandError {
             message: message.into(),
             line: span.line,
             column: span.column,
+            code: None,
+            start: span.start,
+            end: span.end,
         }
     }
 
-    pub fn undefined_macro(name: char, span: Span) -> Self {
+    /// Create an error with a specification code, prefixed to the message.
+    fn coded(code: ErrorCode, message: impl Into<String>, span: Span) -> Self {
         Self {
-            message: format!("Undefined macro '{}'", name),
-            line: span.line,
-            column: span.column,
+            code: Some(code),
+            ..Self::new(code.tag(message), span)
         }
     }
 
-    pub fn undefined_function(name: char, span: Span) -> Self {
-        Self {
-            message: format!("Undefined function '{}'", name),
-            line: span.line,
-            column: span.column,
-        }
+    /// E001: Undefined 0-arg function (formerly macro)
+    pub fn undefined_macro(name: char, span: Span) -> Self {
+        Self::coded(ErrorCode::E001, format!("Undefined macro '{}'", name), span)
     }
 
-    pub fn max_recursion_depth(span: Span) -> Self {
-        Self {
-            message: "Maximum recursion depth exceeded".to_string(),
-            line: span.line,
-            column: span.column,
-        }
+    /// E001/E002: Undefined function, called with `arg_count` arguments
+    pub fn undefined_function(name: char, arg_count: usize, span: Span) -> Self {
+        let code = if arg_count == 0 {
+            ErrorCode::E001
+        } else {
+            ErrorCode::E002
+        };
+        Self::coded(code, format!("Undefined function '{}'", name), span)
+    }
+
+    /// E005: MAX_DEPTH limit exceeded
+    pub fn max_recursion_depth(limit: usize, span: Span) -> Self {
+        Self::coded(
+            ErrorCode::E005,
+            format!("Maximum recursion depth (MAX_DEPTH {}) exceeded", limit),
+            span,
+        )
+    }
+
+    /// Parameter referenced where no call binds it
+    pub fn undefined_parameter(name: char, span: Span) -> Self {
+        Self::new(format!("Undefined parameter '{}'", name), span)
     }
 
     /// E003: Argument count mismatch
     pub fn argument_count_mismatch(name: char, expected: usize, actual: usize, span: Span) -> Self {
-        Self {
-            message: format!(
-                "[E003] Function '{}' expects {} argument(s), but {} provided",
+        Self::coded(
+            ErrorCode::E003,
+            format!(
+                "Function '{}' expects {} argument(s), but {} provided",
                 name, expected, actual
             ),
-            line: span.line,
-            column: span.column,
-        }
+            span,
+        )
     }
 
     /// E004: MAX_STEP limit exceeded
     pub fn max_step_exceeded(limit: usize, span: Span) -> Self {
-        Self {
-            message: format!("[E004] MAX_STEP limit ({}) exceeded", limit),
-            line: span.line,
-            column: span.column,
-        }
+        Self::coded(
+            ErrorCode::E004,
+            format!("MAX_STEP limit ({}) exceeded", limit),
+            span,
+        )
+    }
+
+    /// E006: MAX_MEMORY limit exceeded
+    pub fn max_memory_exceeded(limit: usize, span: Span) -> Self {
+        Self::coded(
+            ErrorCode::E006,
+            format!("MAX_MEMORY limit ({} bytes) exceeded", limit),
+            span,
+        )
     }
 
     /// E007: Numeric value out of range (-255..255)
     pub fn numeric_out_of_range(value: i32, span: Span) -> Self {
-        Self {
-            message: format!("[E007] Numeric value {} is out of range (-255..255)", value),
-            line: span.line,
-            column: span.column,
-        }
+        Self::coded(
+            ErrorCode::E007,
+            format!("Numeric value {} is out of range (-255..255)", value),
+            span,
+        )
     }
 
     /// E008: Type error (Int used as CmdSeq or CmdSeq used in num_expr)
     pub fn type_error(message: impl Into<String>, span: Span) -> Self {
-        Self {
-            message: format!("[E008] {}", message.into()),
-            line: span.line,
-            column: span.column,
-        }
+        Self::coded(ErrorCode::E008, message, span)
     }
 }
 
@@ -198,20 +470,232 @@ impl fmt::Display for ExpandError {
 
 impl std::error::Error for ExpandError {}
 
+/// Level file error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl LevelError {
+    pub fn new(message: impl Into<String>, line: usize, column: usize) -> Self {
+        Self {
+            message: message.into(),
+            line,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Level error at line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+/// Error while encoding toio payloads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToioError {
+    /// An option is outside the range the cube accepts
+    InvalidOption(String),
+    /// A grid pose maps to a mat position outside the mat bounds
+    OutOfRange {
+        /// Agent ID
+        agent_id: u32,
+        /// Index of the command that moves there (`None` for the start pose)
+        step: Option<usize>,
+        /// Mat X coordinate
+        x: i64,
+        /// Mat Y coordinate
+        y: i64,
+    },
+}
+
+impl fmt::Display for ToioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToioError::InvalidOption(message) => write!(f, "Invalid toio option: {}", message),
+            ToioError::OutOfRange {
+                agent_id,
+                step,
+                x,
+                y,
+            } => match step {
+                Some(step) => write!(
+                    f,
+                    "[{}] Agent {} moves to mat position ({}, {}) at step {}, outside the mat",
+                    ErrorCode::E016,
+                    agent_id,
+                    x,
+                    y,
+                    step
+                ),
+                None => write!(
+                    f,
+                    "[{}] Agent {} starts at mat position ({}, {}), outside the mat",
+                    ErrorCode::E016,
+                    agent_id,
+                    x,
+                    y
+                ),
+            },
+        }
+    }
+}
+
+impl std::error::Error for ToioError {}
+
+/// An agent moves to a cell whose coordinates do not fit in an `i32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoseOverflow {
+    /// Agent ID
+    pub agent_id: u32,
+    /// Index of the command (or timeline step) that moves there
+    pub step: usize,
+    /// Column it would reach
+    pub x: i64,
+    /// Row it would reach
+    pub y: i64,
+}
+
+impl PoseOverflow {
+    /// Human-readable description.
+    pub fn message(&self) -> String {
+        format!(
+            "Agent {} moves to ({}, {}) at step {}, outside the grid",
+            self.agent_id, self.x, self.y, self.step
+        )
+    }
+
+    /// E018 error located at `span` (normally the agent's line).
+    pub fn to_compile_error(&self, span: Span) -> CompileError {
+        CompileError::coded(ErrorCode::E018, Severity::Error, self.message(), span)
+    }
+}
+
+impl fmt::Display for PoseOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", ErrorCode::E018, self.message())
+    }
+}
+
+impl std::error::Error for PoseOverflow {}
+
+/// Error while rendering a compiled program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderError {
+    /// The text grid would have more cells than `max_cells`
+    GridTooLarge {
+        width: u64,
+        height: u64,
+        max_cells: u64,
+    },
+    /// An agent's path leaves the grid
+    PoseOverflow(PoseOverflow),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::GridTooLarge {
+                width,
+                height,
+                max_cells,
+            } => write!(
+                f,
+                "Text grid of {}×{} cells exceeds the limit of {} cells",
+                width, height, max_cells
+            ),
+            RenderError::PoseOverflow(overflow) => overflow.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<PoseOverflow> for RenderError {
+    fn from(overflow: PoseOverflow) -> Self {
+        RenderError::PoseOverflow(overflow)
+    }
+}
+
 /// Compile error for output (JSON serializable).
+///
+/// `code` is `null` for syntax errors that have no specification code.
+/// `start`/`end` are the byte range of the offending source, as in [`Span`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompileError {
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
+    #[serde(default)]
+    pub severity: Severity,
     pub line: usize,
     pub column: usize,
+    #[serde(default)]
+    pub start: usize,
+    #[serde(default)]
+    pub end: usize,
     pub message: String,
+    /// Expected token(s), for parse errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    /// Token actually found, for parse errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub found: Option<String>,
+    /// Earlier location the error refers to (e.g. the first definition)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub related: Option<RelatedSpan>,
+}
+
+impl CompileError {
+    /// Create an error without a code or source range.
+    pub fn new(message: impl Into<String>, line: usize, column: usize) -> Self {
+        Self {
+            code: None,
+            severity: Severity::Error,
+            line,
+            column,
+            start: 0,
+            end: 0,
+            message: message.into(),
+            expected: None,
+            found: None,
+            related: None,
+        }
+    }
+
+    /// Create a diagnostic with a specification code, prefixed to the
+    /// message, covering `span`.
+    pub fn coded(
+        code: ErrorCode,
+        severity: Severity,
+        message: impl Into<String>,
+        span: Span,
+    ) -> Self {
+        Self {
+            code: Some(code),
+            severity,
+            start: span.start,
+            end: span.end,
+            ..Self::new(code.tag(message), span.line, span.column)
+        }
+    }
 }
 
 impl From<LexerError> for CompileError {
     fn from(e: LexerError) -> Self {
         Self {
-            line: e.line,
-            column: e.column,
-            message: e.message,
+            code: e.code,
+            start: e.start,
+            end: e.end,
+            ..Self::new(e.message, e.line, e.column)
         }
     }
 }
@@ -226,9 +710,13 @@ impl From<ParseError> for CompileError {
             message.push_str(&format!(" (found: {})", found));
         }
         Self {
-            line: e.line,
-            column: e.column,
-            message,
+            code: e.code,
+            start: e.start,
+            end: e.end,
+            expected: e.expected,
+            found: e.found,
+            related: e.related.map(|span| RelatedSpan::from(*span)),
+            ..Self::new(message, e.line, e.column)
         }
     }
 }
@@ -236,9 +724,10 @@ impl From<ParseError> for CompileError {
 impl From<ExpandError> for CompileError {
     fn from(e: ExpandError) -> Self {
         Self {
-            line: e.line,
-            column: e.column,
-            message: e.message,
+            code: e.code,
+            start: e.start,
+            end: e.end,
+            ..Self::new(e.message, e.line, e.column)
         }
     }
 }
@@ -261,5 +750,70 @@ mod tests {
         let compile_err: CompileError = err.into();
         assert_eq!(compile_err.line, 2);
         assert_eq!(compile_err.column, 3);
+        assert_eq!((compile_err.start, compile_err.end), (0, 1));
+        assert_eq!(compile_err.expected.as_deref(), Some("'s'"));
+        assert_eq!(compile_err.found.as_deref(), Some("'x'"));
+        assert_eq!(compile_err.code, None);
+    }
+
+    #[test]
+    fn test_expand_error_code() {
+        let err = ExpandError::max_step_exceeded(10, Span::new(4, 6, 1, 5));
+        assert_eq!(err.message, "[E004] MAX_STEP limit (10) exceeded");
+        let compile_err: CompileError = err.into();
+        assert_eq!(compile_err.code, Some(ErrorCode::E004));
+        assert_eq!(compile_err.severity, Severity::Error);
+        assert_eq!((compile_err.start, compile_err.end), (4, 6));
+    }
+
+    #[test]
+    fn test_undefined_function_code_depends_on_arity() {
+        let span = Span::new(0, 1, 1, 1);
+        assert_eq!(
+            ExpandError::undefined_function('a', 0, span).code,
+            Some(ErrorCode::E001)
+        );
+        assert_eq!(
+            ExpandError::undefined_function('a', 2, span).code,
+            Some(ErrorCode::E002)
+        );
+    }
+
+    #[test]
+    fn test_compile_error_json() {
+        let err: CompileError =
+            ParseError::invalid_directive("Unknown directive 'FOO'", Span::new(0, 3, 1, 1)).into();
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["code"], "E009");
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["message"], "[E009] Unknown directive 'FOO'");
+        assert_eq!(json["end"], 3);
+        assert!(json.get("expected").is_none());
+    }
+
+    #[test]
+    fn test_coded_compile_error() {
+        let err = CompileError::coded(
+            ErrorCode::W001,
+            Severity::Warning,
+            "Function 'a' is never called",
+            Span::new(5, 9, 2, 3),
+        );
+        assert_eq!(err.code, Some(ErrorCode::W001));
+        assert_eq!(err.severity, Severity::Warning);
+        assert_eq!(err.message, "[W001] Function 'a' is never called");
+        assert_eq!((err.line, err.column, err.start, err.end), (2, 3, 5, 9));
+    }
+
+    #[test]
+    fn test_duplicate_function_points_to_first() {
+        let first = Span::new(3, 7, 1, 4);
+        let err: CompileError =
+            ParseError::duplicate_function('a', Span::new(8, 12, 1, 9), first).into();
+        assert_eq!(err.code, Some(ErrorCode::E011));
+        assert_eq!(err.related, Some(RelatedSpan::from(first)));
+        assert!(err.message.contains("line 1, column 4"));
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["related"]["column"], 4);
     }
 }
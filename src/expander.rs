@@ -1,19 +1,21 @@
 //! Macro and function expansion for H2 Language.
 
 use crate::ast::{
-    Agent, Arg, Definition, Expr, LimitConfig, NumAtom, NumOp, OnLimitBehavior, ParamType,
+    Agent, Arg, Definition, Expr, FuncDef, LimitConfig, NumAtom, NumOp, OnLimitBehavior, ParamType,
     Primitive,
 };
 use crate::error::ExpandError;
+use crate::rope::CommandRope;
 use crate::token::Span;
-use std::cell::Cell;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
 /// Parameter value (command sequence or number).
 #[derive(Debug, Clone)]
 pub enum ParamValue {
     /// Sequence of commands (for command arguments)
-    Commands(Vec<Command>),
+    Commands(CommandRope),
     /// Numeric value (for numeric arguments)
     Number(i32),
 }
@@ -27,6 +29,8 @@ pub enum Command {
     Right,
     /// Rotate left (90° counter-clockwise)
     Left,
+    /// Stand still for one step
+    Wait,
 }
 
 impl Command {
@@ -36,6 +40,7 @@ impl Command {
             Command::Straight => 's',
             Command::Right => 'r',
             Command::Left => 'l',
+            Command::Wait => 'w',
         }
     }
 }
@@ -46,355 +51,1171 @@ impl From<Primitive> for Command {
             Primitive::Straight => Command::Straight,
             Primitive::Right => Command::Right,
             Primitive::Left => Command::Left,
+            Primitive::Wait => Command::Wait,
         }
     }
 }
 
-/// Expansion context.
-struct ExpandContext<'a> {
-    /// Function definitions: name -> (param_names, param_types, body)
-    /// Note: 0-arg functions (formerly macros) have empty param_names
-    functions: HashMap<char, (Vec<char>, HashMap<char, ParamType>, Expr)>,
-    /// Current recursion depth
-    depth: usize,
-    /// Limit configuration
-    limits: &'a LimitConfig,
-    /// Current step count (shared across all recursive calls)
-    step_count: &'a Cell<usize>,
-    /// Whether truncation occurred
-    truncated: &'a Cell<bool>,
+/// A limit from [`LimitConfig`] that can cut an expansion short.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// `MAX_STEP` (E004)
+    MaxStep,
+    /// `MAX_DEPTH` (E005)
+    MaxDepth,
+    /// `MAX_MEMORY` (E006)
+    MaxMemory,
 }
 
-/// Expander for macro and function expansion.
-pub struct Expander {
-    /// Limit configuration
-    limits: LimitConfig,
+impl LimitKind {
+    /// Build the error reported for this limit under `ON_LIMIT=ERROR`.
+    fn error(self, limits: &LimitConfig, span: Span) -> ExpandError {
+        match self {
+            LimitKind::MaxStep => ExpandError::max_step_exceeded(limits.max_step, span),
+            LimitKind::MaxDepth => ExpandError::max_recursion_depth(limits.max_depth, span),
+            LimitKind::MaxMemory => ExpandError::max_memory_exceeded(limits.max_memory, span),
+        }
+    }
 }
 
-impl Default for Expander {
-    fn default() -> Self {
-        Self::new()
+/// Summary of an agent's expansion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExpandStats {
+    /// Commands generated, including argument evaluation
+    pub steps: usize,
+    /// Deepest call level reached (0 for the agent's main expression)
+    pub peak_depth: usize,
+    /// Limit that truncated the expansion, and where in the source it fired
+    pub limit: Option<(LimitKind, Span)>,
+}
+
+impl ExpandStats {
+    /// Whether the expansion was cut short under `ON_LIMIT=TRUNCATE`.
+    pub fn truncated(&self) -> bool {
+        self.limit.is_some()
     }
 }
 
-impl Expander {
-    /// Create a new expander with default settings.
-    pub fn new() -> Self {
-        Self {
-            limits: LimitConfig::default(),
+/// Parameter bindings visible while expanding a function body.
+type Env = HashMap<char, ParamValue>;
+
+/// Bytes charged against `MAX_MEMORY` for each command held in memory.
+const COMMAND_BYTES: usize = 1;
+
+/// Bytes charged against `MAX_MEMORY` for each reference to a shared rope node.
+const ROPE_REF_BYTES: usize = 8;
+
+/// Bytes charged against `MAX_MEMORY` for each entry on the work stack.
+const TASK_BYTES: usize = 8;
+
+/// The function body an expression belongs to.
+#[derive(Clone, Copy)]
+struct Level {
+    /// Call depth of the body
+    depth: usize,
+    /// Steps generated before the body was entered
+    entry: u64,
+}
+
+impl Level {
+    /// The agent's main expression.
+    const MAIN: Level = Level { depth: 0, entry: 0 };
+
+    /// Level of a body called from this one once `steps` have been generated.
+    ///
+    /// A tail call (`tail`) replaces its caller, so it stays at the caller's
+    /// depth, unless the caller has generated nothing yet: non-productive
+    /// recursion such as `a:a a` still counts toward `MAX_DEPTH`.
+    fn callee(self, tail: bool, steps: u64) -> Level {
+        let productive = steps > self.entry;
+        Level {
+            depth: if tail && productive {
+                self.depth
+            } else {
+                self.depth + 1
+            },
+            entry: steps,
         }
     }
+}
 
-    /// Create a new expander with custom limits.
-    pub fn with_limits(limits: LimitConfig) -> Self {
-        Self { limits }
+/// Whether a call at `depth` is the last thing its body does, given the
+/// depth of the next pending work that has one (`None` if nothing is left).
+fn is_tail(depth: usize, next: Option<usize>) -> bool {
+    next.map_or(true, |next| next < depth)
+}
+
+/// A unit of pending work on the expansion stack.
+///
+/// The expander never recurses on the Rust stack: every nested expression,
+/// function body and argument is pushed here instead, so recursion depth is
+/// bounded only by `MAX_DEPTH` and `MAX_STEP`.
+enum Task<'a> {
+    /// Expand an expression in the given body.
+    Expand {
+        expr: &'a Expr,
+        env: Rc<Env>,
+        level: Level,
+    },
+    /// Replay a bound CmdSeq parameter, starting at `index`.
+    Replay {
+        env: Rc<Env>,
+        name: char,
+        index: usize,
+        span: Span,
+    },
+    /// Bind the remaining arguments of a call, then expand its body.
+    Call(Box<PendingCall<'a>>),
+    /// Bind the argument collected in the topmost buffer, then resume the call.
+    BindArg(Box<PendingCall<'a>>),
+    /// Release the memory held by a finished call's arguments.
+    Release(usize),
+}
+
+/// A function call whose arguments are being evaluated.
+struct PendingCall<'a> {
+    /// Callee definition
+    def: &'a FuncDef,
+    /// Call-site arguments
+    args: &'a [Arg],
+    /// Environment the arguments are evaluated in
+    caller: Rc<Env>,
+    /// Bindings for the callee (starts as a copy of the caller's bindings)
+    params: Env,
+    /// Index of the next argument to evaluate
+    next: usize,
+    /// Body the call appears in
+    level: Level,
+    /// Bytes held by arguments expanded for this call
+    owned: usize,
+}
+
+/// Progress of [`PendingCall::bind_ready_args`].
+enum ArgBinding<'a> {
+    /// Every argument is bound; the body can be expanded
+    Done,
+    /// A numeric argument was ≤0, so the call produces nothing
+    Terminated,
+    /// The next argument is a command sequence that must be expanded first
+    Expand(&'a Expr),
+}
+
+impl<'a> PendingCall<'a> {
+    /// Bind the value of the next argument.
+    fn bind(&mut self, value: ParamValue) {
+        self.params.insert(self.def.params[self.next], value);
+        self.next += 1;
     }
 
-    /// Create a new expander with custom max depth (for backwards compatibility).
-    pub fn with_max_depth(max_depth: usize) -> Self {
-        Self {
-            limits: LimitConfig {
-                max_depth,
-                ..LimitConfig::default()
-            },
+    /// Bind arguments in order until one needs a nested expansion.
+    fn bind_ready_args(&mut self) -> Result<ArgBinding<'a>, ExpandError> {
+        while self.next < self.args.len() {
+            let value = match &self.args[self.next] {
+                Arg::Command(expr) => {
+                    // Special case: if the expression is a single Param reference,
+                    // pass its value through unchanged (Number or Commands).
+                    // This handles HOJ patterns like: a(X,Y):sra(X-1,Y) a(3,2)
+                    // where Y is passed through to recursive calls.
+                    match expr {
+                        Expr::Param(p, _) if self.caller.contains_key(p) => self.caller[p].clone(),
+                        _ => return Ok(ArgBinding::Expand(expr)),
+                    }
+                }
+                arg => eval_num_arg(arg, &self.caller)?,
+            };
+
+            // Numeric termination: if numeric arg <= 0, return empty
+            if let ParamValue::Number(n) = value {
+                if n <= 0 {
+                    return Ok(ArgBinding::Terminated);
+                }
+            }
+
+            self.bind(value);
         }
+
+        Ok(ArgBinding::Done)
     }
+}
 
-    /// Expand an agent's expression to a list of commands.
-    pub fn expand_agent(&self, agent: &Agent) -> Result<Vec<Command>, ExpandError> {
-        let step_count = Cell::new(0usize);
-        let truncated = Cell::new(false);
+/// How a function call proceeds once its definition has been looked up.
+enum CallStart<'a> {
+    /// The call produces nothing (an Int parameter defaulted to 0)
+    Empty,
+    /// Parameters are bound to defaults; the body can be expanded
+    Body(&'a FuncDef, Env),
+    /// Arguments must be bound first
+    Args(Box<PendingCall<'a>>),
+}
 
-        let mut ctx = ExpandContext {
-            functions: HashMap::new(),
-            depth: 0,
-            limits: &self.limits,
-            step_count: &step_count,
-            truncated: &truncated,
-        };
+/// Look up a called function and prepare its parameter bindings.
+fn start_call<'a>(
+    functions: &HashMap<char, &'a FuncDef>,
+    name: char,
+    args: &'a [Arg],
+    span: Span,
+    env: Rc<Env>,
+    level: Level,
+) -> Result<CallStart<'a>, ExpandError> {
+    // Unified function call handling (v0.5.0)
+    // Look up function (includes 0-arg functions, formerly macros)
+    let Some(&def) = functions.get(&name) else {
+        // E001 for 0-arg, E002 for n-arg
+        return Err(ExpandError::undefined_function(name, args.len(), span));
+    };
+
+    let mut params = (*env).clone();
+
+    if args.is_empty() && !def.params.is_empty() {
+        // HOJ compatibility: f() with params binds default values
+        // CmdSeq → empty, Int → 0 (triggers ≤0 termination)
+        for param_name in &def.params {
+            match def
+                .param_types
+                .get(param_name)
+                .unwrap_or(&ParamType::CmdSeq)
+            {
+                ParamType::CmdSeq => {
+                    params.insert(*param_name, ParamValue::Commands(CommandRope::empty()));
+                }
+                // Int=0 triggers termination
+                ParamType::Int => return Ok(CallStart::Empty),
+            }
+        }
+
+        return Ok(CallStart::Body(def, params));
+    }
+
+    // Normal case: check arity
+    if args.len() != def.params.len() {
+        return Err(ExpandError::argument_count_mismatch(
+            name,
+            def.params.len(),
+            args.len(),
+            span,
+        ));
+    }
+
+    Ok(CallStart::Args(Box::new(PendingCall {
+        def,
+        args,
+        caller: env,
+        params,
+        next: 0,
+        level,
+        owned: 0,
+    })))
+}
+
+/// Iterative expansion state for a single agent.
+///
+/// Commands are produced one at a time by [`Expansion::next_command`], which
+/// backs both [`Expander::expand_agent`] and [`Expander::expand_agent_iter`].
+/// Arguments are still evaluated eagerly: while an argument is being expanded,
+/// its commands are collected into a buffer instead of the output.
+///
+/// `MAX_MEMORY` is charged only for what is still live: open argument
+/// buffers, argument sequences bound by calls that have not finished yet, and
+/// the work stack. Output commands are released as soon as they are yielded.
+struct Expansion<'a> {
+    /// Function definitions by name
+    functions: HashMap<char, &'a FuncDef>,
+    /// Limit configuration
+    limits: &'a LimitConfig,
+    /// Pending work (top of stack is processed next)
+    stack: Vec<Task<'a>>,
+    /// Buffers for arguments currently being evaluated
+    buffers: Vec<Vec<Command>>,
+    /// Number of commands generated so far (including argument evaluation)
+    step_count: usize,
+    /// Bytes held by argument buffers and bound arguments
+    memory: usize,
+    /// Deepest call level reached
+    peak_depth: usize,
+    /// Limit that caused truncation, if any
+    limit: Option<(LimitKind, Span)>,
+}
 
+impl<'a> Expansion<'a> {
+    /// Set up the expansion of an agent's main expression.
+    fn new(agent: &'a Agent, limits: &'a LimitConfig) -> Self {
         // Register all definitions as functions (unified model)
+        let mut functions = HashMap::new();
         for def in &agent.definitions {
             match def {
                 Definition::Function(f) => {
-                    ctx.functions.insert(
-                        f.name,
-                        (f.params.clone(), f.param_types.clone(), f.body.clone()),
-                    );
+                    functions.insert(f.name, f);
                 }
             }
         }
 
-        // Expand the expression
-        self.expand_expr(&agent.expression, &ctx, &HashMap::new())
+        Self {
+            functions,
+            limits,
+            stack: vec![Task::Expand {
+                expr: &agent.expression,
+                env: Rc::new(Env::new()),
+                level: Level::MAIN,
+            }],
+            buffers: Vec::new(),
+            step_count: 0,
+            memory: 0,
+            peak_depth: 0,
+            limit: None,
+        }
     }
 
-    /// Expand an expression to a list of commands.
-    fn expand_expr(
-        &self,
-        expr: &Expr,
-        ctx: &ExpandContext,
-        params: &HashMap<char, ParamValue>,
-    ) -> Result<Vec<Command>, ExpandError> {
-        // Check recursion depth (respects ON_LIMIT)
-        if ctx.depth > ctx.limits.max_depth {
-            match ctx.limits.on_limit {
-                OnLimitBehavior::Error => {
-                    return Err(ExpandError::max_recursion_depth(expr.span()));
+    /// Run until the next output command is produced.
+    ///
+    /// Returns `Ok(None)` once the expansion is complete or truncated.
+    fn next_command(&mut self) -> Result<Option<Command>, ExpandError> {
+        while let Some(task) = self.stack.pop() {
+            let emitted = match task {
+                Task::Expand { expr, env, level } => self.expand(expr, env, level)?,
+                Task::Replay {
+                    env,
+                    name,
+                    index,
+                    span,
+                } => {
+                    let Some(ParamValue::Commands(cmds)) = env.get(&name) else {
+                        unreachable!("replayed parameter must be bound to commands");
+                    };
+
+                    // Inside an argument: copy as much as MAX_STEP and
+                    // MAX_MEMORY allow at once
+                    let budget =
+                        self.limits.max_step.saturating_sub(self.step_count).min(
+                            self.limits.max_memory.saturating_sub(self.held()) / COMMAND_BYTES,
+                        );
+                    if let Some(buffer) = self.buffers.last_mut() {
+                        let end = cmds.len().min((index + budget) as u64);
+                        buffer.extend(cmds.slice(index as u64..end).iter());
+                        self.step_count += end as usize - index;
+                        self.memory += (end as usize - index) * COMMAND_BYTES;
+
+                        if let Some(cmd) = cmds.get(end) {
+                            // The next command exceeds a limit
+                            self.emit(cmd, span)?;
+                        }
+                        None
+                    } else {
+                        let cmd = cmds.get(index as u64).expect("replay index within bounds");
+                        if index as u64 + 1 < cmds.len() {
+                            self.stack.push(Task::Replay {
+                                env: env.clone(),
+                                name,
+                                index: index + 1,
+                                span,
+                            });
+                        }
+                        self.emit(cmd, span)?
+                    }
                 }
-                OnLimitBehavior::Truncate => {
-                    ctx.truncated.set(true);
-                    return Ok(vec![]);
+                Task::Call(call) => {
+                    self.call(call)?;
+                    None
                 }
+                Task::BindArg(mut call) => {
+                    let cmds = self.buffers.pop().unwrap_or_default();
+                    // The buffer's bytes stay held by the binding until the call ends
+                    call.owned += cmds.len() * COMMAND_BYTES;
+                    call.bind(ParamValue::Commands(CommandRope::from(cmds)));
+                    self.stack.push(Task::Call(call));
+                    None
+                }
+                Task::Release(bytes) => {
+                    self.memory -= bytes;
+                    None
+                }
+            };
+
+            if emitted.is_some() {
+                return Ok(emitted);
             }
         }
 
-        // Check if already truncated
-        if ctx.truncated.get() {
-            return Ok(vec![]);
-        }
+        Ok(None)
+    }
 
-        match expr {
-            Expr::Primitive(p, span) => self.add_command_with_limit(Command::from(*p), ctx, *span),
-
-            Expr::Param(name, span) => {
-                // Look up parameter value
-                if let Some(value) = params.get(name) {
-                    match value {
-                        ParamValue::Commands(cmds) => {
-                            // Add each command with limit checking
-                            let mut result = Vec::new();
-                            for cmd in cmds {
-                                let added = self.add_command_with_limit(*cmd, ctx, *span)?;
-                                result.extend(added);
-                                if ctx.truncated.get() {
-                                    break;
-                                }
-                            }
-                            Ok(result)
-                        }
-                        ParamValue::Number(_) => {
-                            // E008: Int type parameter used as term (command)
-                            Err(ExpandError::type_error(
-                                format!(
-                                    "Parameter '{}' is Int type but used as command sequence",
-                                    name
-                                ),
-                                *span,
-                            ))
-                        }
-                    }
-                } else {
-                    Err(ExpandError::new(
-                        format!("Undefined parameter '{}'", name),
-                        *span,
-                    ))
-                }
+    /// Stop the expansion, keeping what was generated so far.
+    fn truncate(&mut self, kind: LimitKind, span: Span) {
+        self.limit = Some((kind, span));
+        self.stack.clear();
+        self.buffers.clear();
+    }
+
+    /// Handle a limit being hit, per `ON_LIMIT`.
+    fn limit_hit(&mut self, kind: LimitKind, span: Span) -> Result<Option<Command>, ExpandError> {
+        match self.limits.on_limit {
+            OnLimitBehavior::Error => Err(kind.error(self.limits, span)),
+            OnLimitBehavior::Truncate => {
+                self.truncate(kind, span);
+                Ok(None)
             }
+        }
+    }
 
-            Expr::FuncCall { name, args, span } => {
-                // Unified function call handling (v0.5.0)
-                // Look up function (includes 0-arg functions, formerly macros)
-                if let Some((param_names, param_types, body)) = ctx.functions.get(name) {
-                    // Evaluate arguments and bind to parameters
-                    let mut new_params = params.clone();
-
-                    if args.is_empty() && !param_names.is_empty() {
-                        // HOJ compatibility: f() with params binds default values
-                        // CmdSeq → empty, Int → 0 (triggers ≤0 termination)
-                        for param_name in param_names {
-                            let default_value =
-                                match param_types.get(param_name).unwrap_or(&ParamType::CmdSeq) {
-                                    ParamType::CmdSeq => ParamValue::Commands(vec![]),
-                                    ParamType::Int => ParamValue::Number(0),
-                                };
-
-                            // Int=0 triggers termination
-                            if let ParamValue::Number(0) = &default_value {
-                                return Ok(vec![]);
-                            }
-
-                            new_params.insert(*param_name, default_value);
-                        }
-                    } else {
-                        // Normal case: check arity
-                        if args.len() != param_names.len() {
-                            return Err(ExpandError::argument_count_mismatch(
-                                *name,
-                                param_names.len(),
-                                args.len(),
-                                *span,
-                            ));
-                        }
+    /// Bytes currently held, including the work stack.
+    fn held(&self) -> usize {
+        self.memory + self.stack.len() * TASK_BYTES
+    }
 
-                        for (i, arg) in args.iter().enumerate() {
-                            if let Some(param_name) = param_names.get(i) {
-                                let param_value = self.eval_arg(arg, ctx, params)?;
+    /// Summary of the expansion so far.
+    fn stats(&self) -> ExpandStats {
+        ExpandStats {
+            steps: self.step_count,
+            peak_depth: self.peak_depth,
+            limit: self.limit,
+        }
+    }
 
-                                // Numeric termination: if numeric arg <= 0, return empty
-                                if let ParamValue::Number(n) = &param_value {
-                                    if *n <= 0 {
-                                        return Ok(vec![]);
-                                    }
-                                }
+    /// Expand one expression, pushing any nested work onto the stack.
+    ///
+    /// Returns the command to output, if the expression produced one directly.
+    fn expand(
+        &mut self,
+        expr: &'a Expr,
+        env: Rc<Env>,
+        level: Level,
+    ) -> Result<Option<Command>, ExpandError> {
+        // Check recursion depth (respects ON_LIMIT)
+        if level.depth > self.limits.max_depth {
+            return self.limit_hit(LimitKind::MaxDepth, expr.span());
+        }
+        if self.held() > self.limits.max_memory {
+            return self.limit_hit(LimitKind::MaxMemory, expr.span());
+        }
+        self.peak_depth = self.peak_depth.max(level.depth);
 
-                                new_params.insert(*param_name, param_value);
-                            }
-                        }
+        match expr {
+            Expr::Primitive(p, span) => self.emit(Command::from(*p), *span),
+
+            Expr::Param(name, span) => match env.get(name) {
+                Some(ParamValue::Commands(cmds)) => {
+                    if !cmds.is_empty() {
+                        self.stack.push(Task::Replay {
+                            env: env.clone(),
+                            name: *name,
+                            index: 0,
+                            span: *span,
+                        });
                     }
+                    Ok(None)
+                }
+                // E008: Int type parameter used as term (command)
+                Some(ParamValue::Number(_)) => Err(ExpandError::type_error(
+                    format!(
+                        "Parameter '{}' is Int type but used as command sequence",
+                        name
+                    ),
+                    *span,
+                )),
+                None => Err(ExpandError::undefined_parameter(*name, *span)),
+            },
 
-                    // Expand the function body with the new parameters
-                    let new_ctx = ExpandContext {
-                        functions: ctx.functions.clone(),
-                        depth: ctx.depth + 1,
-                        limits: ctx.limits,
-                        step_count: ctx.step_count,
-                        truncated: ctx.truncated,
-                    };
-                    self.expand_expr(body, &new_ctx, &new_params)
-                } else {
-                    // E001 for 0-arg, E002 for n-arg (both use same error now)
-                    Err(ExpandError::undefined_function(*name, *span))
+            Expr::FuncCall { name, args, span } => {
+                match start_call(&self.functions, *name, args, *span, env, level)? {
+                    CallStart::Empty => {}
+                    CallStart::Body(def, params) => {
+                        let level = self.callee(level);
+                        self.stack.push(Task::Expand {
+                            expr: &def.body,
+                            env: Rc::new(params),
+                            level,
+                        });
+                    }
+                    CallStart::Args(call) => self.stack.push(Task::Call(call)),
                 }
+                Ok(None)
             }
 
             Expr::Sequence(exprs) => {
-                let mut result = Vec::new();
-                for e in exprs {
-                    if ctx.truncated.get() {
-                        break;
-                    }
-                    let cmds = self.expand_expr(e, ctx, params)?;
-                    result.extend(cmds);
+                // Push in reverse so the first expression is expanded first
+                for e in exprs.iter().rev() {
+                    self.stack.push(Task::Expand {
+                        expr: e,
+                        env: env.clone(),
+                        level,
+                    });
                 }
-                Ok(result)
+                Ok(None)
             }
         }
     }
 
-    /// Add a command with step limit checking.
-    /// Returns the command in a vec if successful, or error if limit exceeded.
-    fn add_command_with_limit(
-        &self,
-        cmd: Command,
-        ctx: &ExpandContext,
-        span: Span,
-    ) -> Result<Vec<Command>, ExpandError> {
-        let current = ctx.step_count.get();
-
-        if current >= ctx.limits.max_step {
-            match ctx.limits.on_limit {
-                OnLimitBehavior::Error => {
-                    return Err(ExpandError::max_step_exceeded(ctx.limits.max_step, span));
+    /// Bind call arguments in order until one needs a nested expansion.
+    ///
+    /// Once every argument is bound, the function body is scheduled.
+    fn call(&mut self, mut call: Box<PendingCall<'a>>) -> Result<(), ExpandError> {
+        match call.bind_ready_args()? {
+            ArgBinding::Terminated => self.memory -= call.owned,
+            ArgBinding::Expand(expr) => {
+                // Collect the argument's commands into a fresh buffer
+                let env = call.caller.clone();
+                let level = call.level;
+                self.buffers.push(Vec::new());
+                self.stack.push(Task::BindArg(call));
+                self.stack.push(Task::Expand { expr, env, level });
+            }
+            ArgBinding::Done => {
+                let level = self.callee(call.level);
+                // Arguments stay alive until the body has been expanded; a
+                // chain of tail calls shares one release
+                if let Some(Task::Release(bytes)) = self.stack.last_mut() {
+                    *bytes += call.owned;
+                } else if call.owned > 0 {
+                    self.stack.push(Task::Release(call.owned));
                 }
-                OnLimitBehavior::Truncate => {
-                    ctx.truncated.set(true);
-                    return Ok(vec![]);
+                // Expand the function body with the new parameters
+                self.stack.push(Task::Expand {
+                    expr: &call.def.body,
+                    env: Rc::new(call.params),
+                    level,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Level of a body called from `caller`, with the pending work on the stack.
+    fn callee(&self, caller: Level) -> Level {
+        let next = self.stack.iter().rev().find_map(|task| match task {
+            Task::Expand { level, .. } => Some(level.depth),
+            Task::Call(call) | Task::BindArg(call) => Some(call.level.depth),
+            Task::Replay { .. } | Task::Release(_) => None,
+        });
+        caller.callee(is_tail(caller.depth, next), self.step_count as u64)
+    }
+
+    /// Produce a command with step and memory limit checking.
+    ///
+    /// Commands go to the innermost argument buffer if one is open; otherwise
+    /// the command is returned as output and released once yielded.
+    fn emit(&mut self, cmd: Command, span: Span) -> Result<Option<Command>, ExpandError> {
+        if self.step_count >= self.limits.max_step {
+            return self.limit_hit(LimitKind::MaxStep, span);
+        }
+        if self.held() + COMMAND_BYTES > self.limits.max_memory {
+            return self.limit_hit(LimitKind::MaxMemory, span);
+        }
+
+        self.step_count += 1;
+
+        match self.buffers.last_mut() {
+            Some(buffer) => {
+                buffer.push(cmd);
+                self.memory += COMMAND_BYTES;
+                Ok(None)
+            }
+            None => Ok(Some(cmd)),
+        }
+    }
+}
+
+/// Memoization key: a function together with every binding its body can see.
+///
+/// Bound command sequences are compared by node identity, so two calls share
+/// a result only when they received the very same (interned) argument.
+#[derive(PartialEq, Eq, Hash)]
+struct MemoKey {
+    name: char,
+    env: Vec<(char, BoundKey)>,
+}
+
+impl MemoKey {
+    fn new(name: char, env: &Env) -> Self {
+        let mut env: Vec<(char, BoundKey)> = env
+            .iter()
+            .map(|(param, value)| {
+                let key = match value {
+                    ParamValue::Number(n) => BoundKey::Number(*n),
+                    ParamValue::Commands(rope) => BoundKey::Commands(rope.clone()),
+                };
+                (*param, key)
+            })
+            .collect();
+        env.sort_unstable_by_key(|(param, _)| *param);
+        Self { name, env }
+    }
+}
+
+/// A bound parameter value inside a [`MemoKey`].
+enum BoundKey {
+    Number(i32),
+    Commands(CommandRope),
+}
+
+impl PartialEq for BoundKey {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BoundKey::Number(a), BoundKey::Number(b)) => a == b,
+            (BoundKey::Commands(a), BoundKey::Commands(b)) => a.ptr_eq(b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for BoundKey {}
+
+impl Hash for BoundKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            BoundKey::Number(n) => (0u8, *n as i64).hash(state),
+            BoundKey::Commands(rope) => (1u8, rope.node_addr() as i64).hash(state),
+        }
+    }
+}
+
+/// A memoized function body expansion.
+struct MemoEntry {
+    /// The body's output
+    rope: CommandRope,
+    /// Steps the flat expander would count for the body (including arguments)
+    steps: u64,
+    /// Deepest call level reached, relative to the body's own depth
+    height: usize,
+}
+
+/// Output collected for one call body, argument, or the agent itself.
+struct Frame {
+    /// Finished pieces, in order
+    parts: Vec<CommandRope>,
+    /// Primitive commands not yet packed into a piece
+    run: Vec<Command>,
+    /// Deepest call level reached while this frame was open
+    peak: usize,
+    /// Whether this frame collects an argument (rather than output)
+    argument: bool,
+}
+
+impl Frame {
+    fn new(depth: usize, argument: bool) -> Self {
+        Self {
+            parts: Vec::new(),
+            run: Vec::new(),
+            peak: depth,
+            argument,
+        }
+    }
+
+    fn push_rope(&mut self, rope: CommandRope) {
+        if rope.is_empty() {
+            return;
+        }
+        if !self.run.is_empty() {
+            self.parts
+                .push(CommandRope::from(std::mem::take(&mut self.run)));
+        }
+        self.parts.push(rope);
+    }
+
+    fn finish(mut self) -> CommandRope {
+        if !self.run.is_empty() {
+            self.parts.push(CommandRope::from(self.run));
+        }
+        CommandRope::concat(self.parts)
+    }
+}
+
+/// A unit of pending work for [`Compression`].
+enum RopeTask<'a> {
+    /// Expand an expression in the given body.
+    Expand {
+        expr: &'a Expr,
+        env: Rc<Env>,
+        level: Level,
+    },
+    /// Bind the remaining arguments of a call, then expand its body.
+    Call(Box<PendingCall<'a>>),
+    /// Bind the argument collected in the topmost frame, then resume the call.
+    BindArg(Box<PendingCall<'a>>),
+    /// Close the topmost (body) frame and memoize it.
+    Finish {
+        key: MemoKey,
+        depth: usize,
+        steps: u64,
+    },
+}
+
+/// Compressed expansion state for a single agent.
+///
+/// Mirrors [`Expansion`], but builds a [`CommandRope`] instead of a flat list:
+/// every function body is memoized on its bindings and reused as a shared
+/// node. Step counting, `MAX_DEPTH` and `ON_LIMIT` behave exactly as in the
+/// flat expander; a memoized body is only reused when it fits in the remaining
+/// step budget and depth, otherwise it is expanded again so limits fire at the
+/// same command.
+///
+/// Nothing is freed while memoized nodes may still be reused, so `MAX_MEMORY`
+/// is charged cumulatively: for every command stored in a rope leaf and every
+/// reference to a shared node.
+struct Compression<'a> {
+    /// Function definitions by name
+    functions: HashMap<char, &'a FuncDef>,
+    /// Limit configuration
+    limits: &'a LimitConfig,
+    /// Pending work (top of stack is processed next)
+    stack: Vec<RopeTask<'a>>,
+    /// Open frames; the first one is the agent's output
+    frames: Vec<Frame>,
+    /// Memoized body expansions
+    memo: HashMap<MemoKey, MemoEntry>,
+    /// Number of commands generated so far (including argument evaluation)
+    step_count: u64,
+    /// Bytes allocated for rope leaves and node references
+    memory: usize,
+}
+
+impl<'a> Compression<'a> {
+    fn new(agent: &'a Agent, limits: &'a LimitConfig) -> Self {
+        let mut functions = HashMap::new();
+        for def in &agent.definitions {
+            match def {
+                Definition::Function(f) => {
+                    functions.insert(f.name, f);
                 }
             }
         }
 
-        ctx.step_count.set(current + 1);
-        Ok(vec![cmd])
-    }
-
-    /// Evaluate a function argument to a ParamValue.
-    fn eval_arg(
-        &self,
-        arg: &Arg,
-        ctx: &ExpandContext,
-        params: &HashMap<char, ParamValue>,
-    ) -> Result<ParamValue, ExpandError> {
-        match arg {
-            Arg::Command(expr) => {
-                // Special case: if the expression is a single Param reference
-                // and that param is bound to a Number, pass through the Number.
-                // This handles HOJ patterns like: a(X,Y):sra(X-1,Y) a(3,2)
-                // where Y is passed through to recursive calls.
-                if let Expr::Param(p, _) = expr {
-                    if let Some(value) = params.get(p) {
-                        return Ok(value.clone());
-                    }
+        Self {
+            functions,
+            limits,
+            stack: vec![RopeTask::Expand {
+                expr: &agent.expression,
+                env: Rc::new(Env::new()),
+                level: Level::MAIN,
+            }],
+            frames: vec![Frame::new(0, false)],
+            memo: HashMap::new(),
+            step_count: 0,
+            memory: 0,
+        }
+    }
+
+    /// Run the expansion to completion.
+    fn run(mut self) -> Result<CommandRope, ExpandError> {
+        while let Some(task) = self.stack.pop() {
+            match task {
+                RopeTask::Expand { expr, env, level } => self.expand(expr, env, level)?,
+                RopeTask::Call(call) => self.call(call)?,
+                RopeTask::BindArg(mut call) => {
+                    let rope = self.close_frame();
+                    call.bind(ParamValue::Commands(rope));
+                    self.call(call)?;
                 }
+                RopeTask::Finish { key, depth, steps } => {
+                    let height = self.top().peak - depth;
+                    let rope = self.close_frame();
+                    self.memo.insert(
+                        key,
+                        MemoEntry {
+                            rope: rope.clone(),
+                            steps: self.step_count - steps,
+                            height,
+                        },
+                    );
+                    self.top().push_rope(rope);
+                }
+            }
+        }
+
+        Ok(self.frames.pop().map(Frame::finish).unwrap_or_default())
+    }
 
-                let cmds = self.expand_expr(expr, ctx, params)?;
-                Ok(ParamValue::Commands(cmds))
+    /// The innermost open frame.
+    fn top(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("output frame is always open")
+    }
+
+    /// Close the innermost frame, passing its peak depth on to its parent.
+    fn close_frame(&mut self) -> CommandRope {
+        let frame = self.frames.pop().expect("frame to close");
+        let parent = self.top();
+        parent.peak = parent.peak.max(frame.peak);
+        frame.finish()
+    }
+
+    /// Stop the expansion, keeping the output generated so far.
+    ///
+    /// Open body frames hold output that the flat expander would already have
+    /// emitted; argument frames (and everything inside them) are discarded.
+    fn truncate(&mut self) {
+        self.stack.clear();
+        let output: Vec<CommandRope> = std::mem::take(&mut self.frames)
+            .into_iter()
+            .take_while(|frame| !frame.argument)
+            .map(Frame::finish)
+            .collect();
+        let mut frame = Frame::new(0, false);
+        frame.parts.push(CommandRope::concat(output));
+        self.frames.push(frame);
+    }
+
+    /// Handle a limit being hit, per `ON_LIMIT`.
+    fn limit_hit(&mut self, kind: LimitKind, span: Span) -> Result<(), ExpandError> {
+        match self.limits.on_limit {
+            OnLimitBehavior::Error => Err(kind.error(self.limits, span)),
+            OnLimitBehavior::Truncate => {
+                self.truncate();
+                Ok(())
             }
-            Arg::Number(n, span) => {
-                // E007: Check numeric range (-255..=255)
-                if *n < -255 || *n > 255 {
-                    return Err(ExpandError::numeric_out_of_range(*n, *span));
+        }
+    }
+
+    /// Steps left before `MAX_STEP`.
+    fn budget(&self) -> u64 {
+        (self.limits.max_step as u64).saturating_sub(self.step_count)
+    }
+
+    /// Charge `bytes` against `MAX_MEMORY`.
+    ///
+    /// Returns `false` if the limit was hit and the expansion truncated.
+    fn reserve(&mut self, bytes: usize, span: Span) -> Result<bool, ExpandError> {
+        if self.memory + bytes > self.limits.max_memory {
+            self.limit_hit(LimitKind::MaxMemory, span)?;
+            return Ok(false);
+        }
+        self.memory += bytes;
+        Ok(true)
+    }
+
+    /// Append a command sequence, cutting it off at `MAX_STEP`.
+    fn append(&mut self, rope: CommandRope, span: Span) -> Result<(), ExpandError> {
+        if !self.reserve(ROPE_REF_BYTES, span)? {
+            return Ok(());
+        }
+
+        let budget = self.budget();
+        if rope.len() <= budget {
+            self.step_count += rope.len();
+            self.top().push_rope(rope);
+            return Ok(());
+        }
+
+        self.step_count += budget;
+        self.top().push_rope(rope.slice(0..budget));
+        self.limit_hit(LimitKind::MaxStep, span)
+    }
+
+    /// Expand one expression, pushing any nested work onto the stack.
+    fn expand(&mut self, expr: &'a Expr, env: Rc<Env>, level: Level) -> Result<(), ExpandError> {
+        if level.depth > self.limits.max_depth {
+            return self.limit_hit(LimitKind::MaxDepth, expr.span());
+        }
+        let frame = self.top();
+        frame.peak = frame.peak.max(level.depth);
+
+        match expr {
+            Expr::Primitive(p, span) => {
+                if self.budget() == 0 {
+                    return self.limit_hit(LimitKind::MaxStep, *span);
+                }
+                if !self.reserve(COMMAND_BYTES, *span)? {
+                    return Ok(());
                 }
-                Ok(ParamValue::Number(*n))
+                self.step_count += 1;
+                self.top().run.push(Command::from(*p));
+                Ok(())
             }
-            Arg::NumExpr { first, rest, span } => {
-                // Evaluate extended num_expr: first ((op atom)*)
-                // Examples: X-1, 10-3+1, X+Y-2
-
-                // Evaluate the first atom
-                let mut result = self.eval_num_atom(first, params, *span)?;
-
-                // Apply each operation in sequence (left-to-right)
-                for (op, atom) in rest {
-                    let atom_value = self.eval_num_atom(atom, params, *span)?;
-                    result = match op {
-                        NumOp::Add => result + atom_value,
-                        NumOp::Sub => result - atom_value,
-                    };
 
-                    // E007: Check intermediate result range
-                    if !(-255..=255).contains(&result) {
-                        return Err(ExpandError::numeric_out_of_range(result, *span));
+            Expr::Param(name, span) => match env.get(name) {
+                Some(ParamValue::Commands(rope)) => self.append(rope.clone(), *span),
+                // E008: Int type parameter used as term (command)
+                Some(ParamValue::Number(_)) => Err(ExpandError::type_error(
+                    format!(
+                        "Parameter '{}' is Int type but used as command sequence",
+                        name
+                    ),
+                    *span,
+                )),
+                None => Err(ExpandError::undefined_parameter(*name, *span)),
+            },
+
+            Expr::FuncCall { name, args, span } => {
+                match start_call(&self.functions, *name, args, *span, env, level)? {
+                    CallStart::Empty => {}
+                    CallStart::Body(def, params) => {
+                        let level = self.callee(level);
+                        self.enter_body(def, params, level)?;
                     }
+                    CallStart::Args(call) => self.stack.push(RopeTask::Call(call)),
                 }
+                Ok(())
+            }
 
-                Ok(ParamValue::Number(result))
+            Expr::Sequence(exprs) => {
+                // Push in reverse so the first expression is expanded first
+                for e in exprs.iter().rev() {
+                    self.stack.push(RopeTask::Expand {
+                        expr: e,
+                        env: env.clone(),
+                        level,
+                    });
+                }
+                Ok(())
             }
         }
     }
 
-    /// Evaluate a numeric atom to an i32 value.
-    fn eval_num_atom(
-        &self,
-        atom: &NumAtom,
-        params: &HashMap<char, ParamValue>,
-        span: Span,
-    ) -> Result<i32, ExpandError> {
-        match atom {
-            NumAtom::Number(n) => {
-                // E007: Check range
-                if *n < -255 || *n > 255 {
-                    return Err(ExpandError::numeric_out_of_range(*n, span));
+    /// Bind call arguments in order until one needs a nested expansion.
+    fn call(&mut self, mut call: Box<PendingCall<'a>>) -> Result<(), ExpandError> {
+        match call.bind_ready_args()? {
+            ArgBinding::Terminated => {}
+            ArgBinding::Expand(expr) => {
+                let env = call.caller.clone();
+                let level = call.level;
+                self.frames.push(Frame::new(level.depth, true));
+                self.stack.push(RopeTask::BindArg(call));
+                self.stack.push(RopeTask::Expand { expr, env, level });
+            }
+            ArgBinding::Done => {
+                let level = self.callee(call.level);
+                self.enter_body(call.def, call.params, level)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Level of a body called from `caller`, with the pending work on the stack.
+    fn callee(&self, caller: Level) -> Level {
+        let next = self.stack.last().and_then(|task| match task {
+            RopeTask::Expand { level, .. } => Some(level.depth),
+            RopeTask::Call(call) | RopeTask::BindArg(call) => Some(call.level.depth),
+            // The caller's body ends here; only shallower work follows
+            RopeTask::Finish { .. } => None,
+        });
+        caller.callee(is_tail(caller.depth, next), self.step_count)
+    }
+
+    /// Expand a function body, reusing a memoized result when it fits.
+    fn enter_body(
+        &mut self,
+        def: &'a FuncDef,
+        params: Env,
+        level: Level,
+    ) -> Result<(), ExpandError> {
+        let key = MemoKey::new(def.name, &params);
+        let depth = level.depth;
+
+        if let Some(entry) = self.memo.get(&key) {
+            if entry.steps <= self.budget() && depth + entry.height <= self.limits.max_depth {
+                let (rope, steps, peak) = (entry.rope.clone(), entry.steps, depth + entry.height);
+                if !self.reserve(ROPE_REF_BYTES, def.span)? {
+                    return Ok(());
                 }
-                Ok(*n)
+                self.step_count += steps;
+                let frame = self.top();
+                frame.peak = frame.peak.max(peak);
+                frame.push_rope(rope);
+                return Ok(());
             }
-            NumAtom::Param(p) => {
-                // Look up the parameter value
-                if let Some(value) = params.get(p) {
-                    match value {
-                        ParamValue::Number(n) => Ok(*n),
-                        ParamValue::Commands(_) => {
-                            // E008: CmdSeq type parameter used in num_expr
-                            Err(ExpandError::type_error(
-                                format!(
-                                    "Parameter '{}' is CmdSeq type but used in numeric expression",
-                                    p
-                                ),
-                                span,
-                            ))
-                        }
-                    }
-                } else {
-                    Err(ExpandError::new(
-                        format!("Undefined parameter '{}'", p),
+        }
+
+        self.frames.push(Frame::new(depth, false));
+        self.stack.push(RopeTask::Finish {
+            key,
+            depth,
+            steps: self.step_count,
+        });
+        self.stack.push(RopeTask::Expand {
+            expr: &def.body,
+            env: Rc::new(params),
+            level,
+        });
+        Ok(())
+    }
+}
+
+/// Evaluate a numeric argument (`Arg::Number` or `Arg::NumExpr`).
+fn eval_num_arg(arg: &Arg, params: &Env) -> Result<ParamValue, ExpandError> {
+    match arg {
+        Arg::Number(n, span) => {
+            // E007: Check numeric range (-255..=255)
+            if *n < -255 || *n > 255 {
+                return Err(ExpandError::numeric_out_of_range(*n, *span));
+            }
+            Ok(ParamValue::Number(*n))
+        }
+        Arg::NumExpr { first, rest, span } => {
+            // Evaluate extended num_expr: first ((op atom)*)
+            // Examples: X-1, 10-3+1, X+Y-2
+
+            // Evaluate the first atom
+            let mut result = eval_num_atom(first, params, *span)?;
+
+            // Apply each operation in sequence (left-to-right)
+            for (op, atom) in rest {
+                let atom_value = eval_num_atom(atom, params, *span)?;
+                result = match op {
+                    NumOp::Add => result + atom_value,
+                    NumOp::Sub => result - atom_value,
+                };
+
+                // E007: Check intermediate result range
+                if !(-255..=255).contains(&result) {
+                    return Err(ExpandError::numeric_out_of_range(result, *span));
+                }
+            }
+
+            Ok(ParamValue::Number(result))
+        }
+        Arg::Command(expr) => unreachable!(
+            "command argument at {} must be expanded, not evaluated numerically",
+            expr.span()
+        ),
+    }
+}
+
+/// Evaluate a numeric atom to an i32 value.
+fn eval_num_atom(atom: &NumAtom, params: &Env, span: Span) -> Result<i32, ExpandError> {
+    match atom {
+        NumAtom::Number(n) => {
+            // E007: Check range
+            if *n < -255 || *n > 255 {
+                return Err(ExpandError::numeric_out_of_range(*n, span));
+            }
+            Ok(*n)
+        }
+        NumAtom::Param(p) => {
+            // Look up the parameter value
+            match params.get(p) {
+                Some(ParamValue::Number(n)) => Ok(*n),
+                Some(ParamValue::Commands(_)) => {
+                    // E008: CmdSeq type parameter used in num_expr
+                    Err(ExpandError::type_error(
+                        format!(
+                            "Parameter '{}' is CmdSeq type but used in numeric expression",
+                            p
+                        ),
                         span,
                     ))
                 }
+                None => Err(ExpandError::undefined_parameter(*p, span)),
             }
         }
     }
 }
 
+/// Expander for macro and function expansion.
+pub struct Expander {
+    /// Limit configuration
+    limits: LimitConfig,
+}
+
+impl Default for Expander {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Expander {
+    /// Create a new expander with default settings.
+    pub fn new() -> Self {
+        Self {
+            limits: LimitConfig::default(),
+        }
+    }
+
+    /// Create a new expander with custom limits.
+    pub fn with_limits(limits: LimitConfig) -> Self {
+        Self { limits }
+    }
+
+    /// Create a new expander with custom max depth (for backwards compatibility).
+    pub fn with_max_depth(max_depth: usize) -> Self {
+        Self {
+            limits: LimitConfig {
+                max_depth,
+                ..LimitConfig::default()
+            },
+        }
+    }
+
+    /// Expand an agent's expression to a list of commands.
+    pub fn expand_agent(&self, agent: &Agent) -> Result<Vec<Command>, ExpandError> {
+        self.expand_agent_iter(agent).collect()
+    }
+
+    /// Lazily expand an agent's expression, yielding commands on demand.
+    ///
+    /// Follows the same `MAX_STEP`/`MAX_DEPTH`/`ON_LIMIT` semantics as
+    /// [`expand_agent`](Self::expand_agent): with `ON_LIMIT=ERROR` the error is
+    /// yielded in place of the offending command, with `ON_LIMIT=TRUNCATE` the
+    /// iterator simply ends. Nothing is yielded after an error.
+    pub fn expand_agent_iter<'a>(&'a self, agent: &'a Agent) -> ExpandIter<'a> {
+        ExpandIter {
+            expansion: Expansion::new(agent, &self.limits),
+            done: false,
+        }
+    }
+
+    /// Expand an agent's expression into a shared, compressed form.
+    ///
+    /// Each function body is expanded once per distinct set of bindings and
+    /// reused as a shared node of the returned [`CommandRope`], so programs
+    /// whose output is exponential in their size (e.g. `f(X):XXXX f(f(f(s)))`)
+    /// can be measured with [`CommandRope::len`] and sliced without building
+    /// the flat command list. Limits behave as in
+    /// [`expand_agent`](Self::expand_agent), except that `MAX_MEMORY` is
+    /// charged for the rope's leaves and node references, which are all kept
+    /// for the result, rather than for live expansion state. To describe outputs beyond the defaults, raise
+    /// [`LimitConfig::max_step`] and [`LimitConfig::max_memory`].
+    pub fn expand_agent_compressed(&self, agent: &Agent) -> Result<CommandRope, ExpandError> {
+        Compression::new(agent, &self.limits).run()
+    }
+}
+
+/// Streaming expansion of a single agent, created by
+/// [`Expander::expand_agent_iter`].
+pub struct ExpandIter<'a> {
+    /// Expansion state
+    expansion: Expansion<'a>,
+    /// Whether the expansion has finished or failed
+    done: bool,
+}
+
+impl ExpandIter<'_> {
+    /// Whether the expansion was cut short by a limit under `ON_LIMIT=TRUNCATE`.
+    pub fn is_truncated(&self) -> bool {
+        self.expansion.limit.is_some()
+    }
+
+    /// Summary of the expansion so far: steps, peak depth and any limit hit.
+    pub fn stats(&self) -> ExpandStats {
+        self.expansion.stats()
+    }
+
+    /// Number of commands generated so far, including argument evaluation.
+    pub fn step_count(&self) -> usize {
+        self.expansion.step_count
+    }
+}
+
+impl Iterator for ExpandIter<'_> {
+    type Item = Result<Command, ExpandError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.expansion.next_command() {
+            Ok(Some(cmd)) => Some(Ok(cmd)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for ExpandIter<'_> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -646,6 +1467,17 @@ mod tests {
         assert_eq!(cmds.len(), 3);
     }
 
+    #[test]
+    fn test_wait_primitive() {
+        // `w` in a body and as an argument; waits count toward MAX_STEP
+        let cmds = expand_source("EXTENSION=WAIT\n0: f(A,X):Awf(A,X-1) f(sw,2)").unwrap();
+        let chars: String = cmds.iter().map(Command::as_char).collect();
+        assert_eq!(chars, "swwsww");
+
+        let result = expand_source("EXTENSION=WAIT\nMAX_STEP=3\n0: wwww");
+        assert!(result.unwrap_err().message.contains("E004"));
+    }
+
     #[test]
     fn test_max_step_exact_boundary() {
         // MAX_STEP=5 with a(5) should succeed with exactly 5 commands
@@ -671,6 +1503,95 @@ mod tests {
         assert_eq!(cmds.len(), 5);
     }
 
+    #[test]
+    fn test_deep_recursion_without_stack_overflow() {
+        // 20,000 nested calls exceed the old native-stack-bound limit
+        let result = expand_source("MAX_DEPTH=20000\n0: a:sas a");
+        assert!(result.is_err());
+
+        let cmds = expand_source("MAX_DEPTH=20000\nON_LIMIT=TRUNCATE\n0: a:sas a").unwrap();
+        assert_eq!(cmds.len(), 20000);
+    }
+
+    #[test]
+    fn test_productive_tail_calls_keep_depth() {
+        // Default limits: `a:sa a` runs until MAX_STEP, not MAX_DEPTH
+        let program = parse_source("0: a:sa a");
+        let expander = Expander::with_limits(program.limits.clone());
+        let mut iter = expander.expand_agent_iter(&program.agents[0]);
+        assert_eq!(iter.by_ref().count(), 1_000_000);
+        let stats = iter.stats();
+        assert_eq!(stats.limit.unwrap().0, LimitKind::MaxStep);
+        assert_eq!(stats.peak_depth, 1);
+
+        // Without a step in between, tail calls still nest
+        let err = expand_source("ON_LIMIT=ERROR\n0: a:a a").unwrap_err();
+        assert!(err.message.contains("E005"), "{}", err.message);
+        let err = expand_source("ON_LIMIT=ERROR\n0: a:b b:a a").unwrap_err();
+        assert!(err.message.contains("E005"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_non_tail_recursion_depth() {
+        // a(255) recurses before emitting, so 255 frames are pending at once
+        let cmds = expand_source("0: a(X):a(X-1)s a(255)").unwrap();
+        assert_eq!(cmds.len(), 255);
+    }
+
+    #[test]
+    fn test_max_memory_error() {
+        let result = expand_source("MAX_MEMORY=5\n0: ssssss");
+        let err = result.unwrap_err();
+        assert!(err.message.contains("E006"));
+    }
+
+    #[test]
+    fn test_max_memory_truncate() {
+        // The argument is cut off, so only the commands before the call remain
+        let cmds =
+            expand_source("MAX_MEMORY=20\nON_LIMIT=TRUNCATE\n0: f(X):XX ssf(ssssss)").unwrap();
+        assert_eq!(cmds.len(), 2);
+    }
+
+    #[test]
+    fn test_max_memory_ignores_yielded_output() {
+        // Output is released once yielded, so only the work stack is held
+        let cmds = expand_source("MAX_MEMORY=9\n0: a(X):sa(X-1) a(255)").unwrap();
+        assert_eq!(cmds.len(), 255);
+
+        let source = "MAX_DEPTH=10000000\nMAX_STEP=2000000\nON_LIMIT=TRUNCATE\n0: a:sa a";
+        let program = parse_source(source);
+        let expander = Expander::with_limits(program.limits.clone());
+        let mut iter = expander.expand_agent_iter(&program.agents[0]);
+        assert_eq!(iter.by_ref().count(), 2_000_000);
+        assert_eq!(iter.stats().limit.unwrap().0, LimitKind::MaxStep);
+    }
+
+    #[test]
+    fn test_max_memory_counts_arguments() {
+        assert!(expand_source("MAX_MEMORY=27\n0: a(X,Y):Ya(X-1,Y) a(3,ss)").is_ok());
+        assert!(expand_source("MAX_MEMORY=26\n0: a(X,Y):Ya(X-1,Y) a(3,ss)").is_err());
+        // One more bound command needs one more byte
+        assert!(expand_source("MAX_MEMORY=27\n0: a(X,Y):Ya(X-1,Y) a(3,sss)").is_err());
+    }
+
+    #[test]
+    fn test_max_memory_counts_work_stack() {
+        // Non-tail recursion keeps one pending `s` per level
+        assert!(expand_source("MAX_MEMORY=800\n0: a(X):a(X-1)s a(100)").is_ok());
+        assert!(expand_source("MAX_MEMORY=799\n0: a(X):a(X-1)s a(100)").is_err());
+    }
+
+    #[test]
+    fn test_max_memory_releases_finished_arguments() {
+        // Each argument is freed once its call ends, so three calls fit in the
+        // memory of one
+        let single = "MAX_MEMORY=35\n0: f(X):X f(ss)";
+        let source = "MAX_MEMORY=35\n0: f(X):X f(ss)f(ss)f(ss)";
+        assert_eq!(expand_source(single).unwrap().len(), 2);
+        assert_eq!(expand_source(source).unwrap().len(), 6);
+    }
+
     #[test]
     fn test_default_max_step() {
         // Default MAX_STEP (1,000,000) should allow small expansions
@@ -679,4 +1600,159 @@ mod tests {
         let cmds = result.unwrap();
         assert_eq!(cmds.len(), 100);
     }
+
+    fn parse_source(source: &str) -> crate::ast::Program {
+        let mut parser = Parser::new(source).expect("Parser creation failed");
+        parser.parse_program().expect("Parsing failed")
+    }
+
+    #[test]
+    fn test_expand_iter_matches_expand_agent() {
+        let program = parse_source("0: x:ss f(X):XrX a(X,Y):Ya(X-1,Y) xf(l)a(3,sr)");
+        let expander = Expander::with_limits(program.limits.clone());
+        let agent = &program.agents[0];
+
+        let streamed: Vec<Command> = expander
+            .expand_agent_iter(agent)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(streamed, expander.expand_agent(agent).unwrap());
+    }
+
+    #[test]
+    fn test_expand_iter_is_lazy() {
+        // Would generate a million commands if fully materialized
+        let program = parse_source("0: a(X):sa(X-1) a(255) b(X):a(255)b(X-1) b(255)");
+        let expander = Expander::with_limits(program.limits.clone());
+        let mut iter = expander.expand_agent_iter(&program.agents[0]);
+
+        let first: Vec<Command> = iter.by_ref().take(3).map(Result::unwrap).collect();
+        assert_eq!(first, vec![Command::Straight; 3]);
+        assert_eq!(iter.step_count(), 3);
+    }
+
+    #[test]
+    fn test_expand_iter_error_then_fused() {
+        let program = parse_source("MAX_STEP=3\n0: ssss");
+        let expander = Expander::with_limits(program.limits.clone());
+        let mut iter = expander.expand_agent_iter(&program.agents[0]);
+
+        for _ in 0..3 {
+            assert_eq!(iter.next().unwrap().unwrap(), Command::Straight);
+        }
+        let err = iter.next().unwrap().unwrap_err();
+        assert!(err.message.contains("E004"));
+        assert!(iter.next().is_none());
+        assert!(!iter.is_truncated());
+    }
+
+    #[test]
+    fn test_expand_iter_stats() {
+        let program = parse_source("MAX_MEMORY=20\nON_LIMIT=TRUNCATE\n0: f(X):XX ssf(ssssss)");
+        let expander = Expander::with_limits(program.limits.clone());
+        let mut iter = expander.expand_agent_iter(&program.agents[0]);
+
+        assert_eq!(iter.by_ref().count(), 2);
+        let stats = iter.stats();
+        assert!(stats.truncated());
+        let (kind, span) = stats.limit.unwrap();
+        assert_eq!(kind, LimitKind::MaxMemory);
+        assert_eq!((span.line, span.column), (3, 16));
+        assert_eq!(stats.steps, 2);
+        assert_eq!(stats.peak_depth, 0);
+    }
+
+    #[test]
+    fn test_expand_iter_truncate() {
+        let program = parse_source("MAX_STEP=3\nON_LIMIT=TRUNCATE\n0: ssss");
+        let expander = Expander::with_limits(program.limits.clone());
+        let mut iter = expander.expand_agent_iter(&program.agents[0]);
+
+        assert_eq!(iter.by_ref().count(), 3);
+        assert!(iter.is_truncated());
+        assert!(iter.next().is_none());
+    }
+
+    fn compressed_source(source: &str) -> Result<CommandRope, ExpandError> {
+        let program = parse_source(source);
+        let expander = Expander::with_limits(program.limits.clone());
+        expander.expand_agent_compressed(&program.agents[0])
+    }
+
+    #[test]
+    fn test_compressed_matches_flat() {
+        let sources = [
+            "0: srl",
+            "0: x:ss f(X):XrX a(X,Y):Ya(X-1,Y) xf(l)a(3,sr)",
+            "0: f(X):XXXX f(f(f(s)))",
+            "0: a(X):sa(X-1) b(X):a(3)rb(X-1) b(5)b(5)",
+            "0: f(X,Y):XsY f(,)",
+            "0: f(X):g(s) g(Y):XY f(r)",
+            "MAX_STEP=7\nON_LIMIT=TRUNCATE\n0: f(X):XXXX f(ss)",
+            "MAX_STEP=10\nON_LIMIT=TRUNCATE\n0: a(X):sa(X-1) b(X):a(4)rb(X-1) b(9)",
+            "MAX_STEP=5\nON_LIMIT=TRUNCATE\n0: f(X):rX f(f(f(f(s))))",
+            "MAX_DEPTH=5\nON_LIMIT=TRUNCATE\n0: a:sa b(X):ab(X-1) b(3)a",
+            "MAX_DEPTH=3\nON_LIMIT=TRUNCATE\n0: a(X):sa(X-1) b(X):a(2)b(X-1) b(4)",
+        ];
+
+        for source in sources {
+            let flat = expand_source(source).unwrap();
+            let rope = compressed_source(source).unwrap();
+            assert_eq!(rope.to_vec(), flat, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_compressed_errors_match_flat() {
+        let sources = [
+            "MAX_STEP=7\n0: f(X):XXXX f(ss)",
+            "MAX_STEP=10\n0: a(X):sa(X-1) b(X):a(4)rb(X-1) b(9)",
+            "MAX_DEPTH=4\n0: a(X):sa(X-1) b(X):a(2)b(X-1)s b(4)",
+            "0: f(X):sf(X-1) f(s)",
+        ];
+
+        for source in sources {
+            let flat = expand_source(source).unwrap_err();
+            let rope = compressed_source(source).unwrap_err();
+            assert_eq!(rope.message, flat.message, "{}", source);
+            assert_eq!((rope.line, rope.column), (flat.line, flat.column));
+        }
+    }
+
+    #[test]
+    fn test_compressed_exponential_output() {
+        // 2^40 - 1 commands: far too many to materialize
+        let program = parse_source("0: f(X):f(X-1)sf(X-1) f(40)");
+        let expander = Expander::with_limits(LimitConfig {
+            max_step: usize::MAX,
+            ..program.limits.clone()
+        });
+        let rope = expander
+            .expand_agent_compressed(&program.agents[0])
+            .unwrap();
+
+        assert_eq!(rope.len(), (1u64 << 40) - 1);
+        assert!(rope.node_count() < 200);
+        assert_eq!(rope.get(0), Some(Command::Straight));
+        assert_eq!(rope.slice(1 << 39..(1 << 39) + 3).to_vec().len(), 3);
+    }
+
+    #[test]
+    fn test_compressed_shares_repeated_argument() {
+        let rope = compressed_source("0: f(X):XXXX f(f(f(f(s))))").unwrap();
+        assert_eq!(rope.len(), 256);
+        assert!(rope.node_count() <= 5);
+    }
+
+    #[test]
+    fn test_compressed_max_memory() {
+        let source = "MAX_MEMORY=5\n0: ssssss";
+        assert!(compressed_source(source)
+            .unwrap_err()
+            .message
+            .contains("E006"));
+
+        let source = "MAX_MEMORY=5\nON_LIMIT=TRUNCATE\n0: ssssss";
+        assert_eq!(compressed_source(source).unwrap().len(), 5);
+    }
 }
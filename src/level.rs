@@ -0,0 +1,437 @@
+//! Herbert levels and the judge.
+//!
+//! A level is a board with walls, white and gray buttons and a start pose,
+//! written as plain text:
+//!
+//! ```text
+//! // Comments start with //
+//! NAME=Square
+//! LIMIT=10
+//! .......
+//! .o...o.
+//! ...#...
+//! .o.^.o.
+//! ...x...
+//! ```
+//!
+//! Optional `KEY=VALUE` headers come first: `NAME` (free text), `LIMIT`
+//! (byte limit) and `POINTS` (point value). The grid follows, one row per line, all rows the same width:
+//!
+//! | Char | Cell |
+//! |------|------|
+//! | `.` | Empty |
+//! | `#` | Wall |
+//! | `o` | White button |
+//! | `x` | Gray button |
+//! | `^` `>` `v` `<` | Start (empty cell), facing north/east/south/west |
+//!
+//! A robot presses a button by moving onto it. White buttons stay pressed;
+//! gray buttons toggle on every visit. A level is solved when every white
+//! button is pressed and no gray button is left pressed. Solutions are then
+//! scored against the byte limit with [`Score`].
+
+use crate::error::{CompileError, LevelError};
+use crate::output::{CompileResult, CompiledProgram};
+use crate::world::{Heading, Pose, Position, World};
+use crate::{compile_native, count_bytes_native};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// A Herbert level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Level {
+    /// Level name (`NAME=` header)
+    pub name: Option<String>,
+    /// Maximum solution size in bytes (`LIMIT=` header)
+    pub byte_limit: Option<u32>,
+    /// Point value (`POINTS=` header)
+    pub points: Option<u32>,
+    /// Board and walls
+    pub world: World,
+    /// Start pose of every agent
+    pub start: Pose,
+    /// White buttons, in reading order
+    pub white_buttons: Vec<Position>,
+    /// Gray buttons, in reading order
+    pub gray_buttons: Vec<Position>,
+}
+
+/// Result of judging a solution against a level.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Judgement {
+    /// All white buttons pressed and no gray button left pressed
+    pub solved: bool,
+    /// White buttons never pressed
+    pub unpressed_white: Vec<Position>,
+    /// Gray buttons left pressed (visited an odd number of times)
+    pub pressed_gray: Vec<Position>,
+    /// Timeline length of the solution
+    pub steps: usize,
+    /// Solution size (see `count_bytes_native`)
+    pub bytes: u32,
+    /// The level's byte limit, if any
+    pub byte_limit: Option<u32>,
+    /// Whether `bytes` is within `byte_limit` (true without a limit)
+    pub within_limit: bool,
+    /// Score, if the level has both a point value and a byte limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<Score>,
+}
+
+/// Score breakdown of a solution (HOJ scoring).
+///
+/// A solved level earns its full points at or under the byte limit. Above the
+/// limit the points shrink in proportion, `points × limit / bytes` rounded
+/// down. An unsolved level earns nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Score {
+    /// Solution size
+    pub bytes: u32,
+    /// Byte limit of the level
+    pub limit: u32,
+    /// Whether the level was solved
+    pub solved: bool,
+    /// Points earned
+    pub points: u32,
+    /// Point value of the level
+    pub max_points: u32,
+}
+
+impl Score {
+    /// Score a solution of `bytes` bytes for a level worth `max_points`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use h2lang::level::Score;
+    ///
+    /// assert_eq!(Score::new(100, 10, 10, true).points, 100);
+    /// assert_eq!(Score::new(100, 10, 40, true).points, 25);
+    /// assert_eq!(Score::new(100, 10, 5, false).points, 0);
+    /// ```
+    pub fn new(max_points: u32, limit: u32, bytes: u32, solved: bool) -> Self {
+        let points = if !solved {
+            0
+        } else if bytes <= limit {
+            max_points
+        } else {
+            (u64::from(max_points) * u64::from(limit) / u64::from(bytes)) as u32
+        };
+        Self {
+            bytes,
+            limit,
+            solved,
+            points,
+            max_points,
+        }
+    }
+}
+
+impl Level {
+    /// Parse a level from text.
+    pub fn parse(text: &str) -> Result<Self, LevelError> {
+        let mut name = None;
+        let mut byte_limit = None;
+        let mut points = None;
+        let mut rows: Vec<(usize, &str)> = Vec::new();
+
+        for (index, raw) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let line = raw.split("//").next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                if !rows.is_empty() {
+                    return Err(LevelError::new(
+                        "Headers must come before the grid",
+                        line_no,
+                        1,
+                    ));
+                }
+                let value = value.trim();
+                match key.trim() {
+                    "NAME" => name = Some(value.to_string()),
+                    "LIMIT" => byte_limit = Some(parse_number(key, value, line_no)?),
+                    "POINTS" => points = Some(parse_number(key, value, line_no)?),
+                    other => {
+                        return Err(LevelError::new(
+                            format!("Unknown header '{}'", other),
+                            line_no,
+                            1,
+                        ))
+                    }
+                }
+                continue;
+            }
+            rows.push((line_no, line));
+        }
+
+        let Some(&(_, first)) = rows.first() else {
+            return Err(LevelError::new("Level has no grid", 1, 1));
+        };
+        let width = first.chars().count();
+        let mut world = World::new(width as u32, rows.len() as u32);
+        let mut start = None;
+        let mut white_buttons = Vec::new();
+        let mut gray_buttons = Vec::new();
+
+        for (y, &(line_no, row)) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(LevelError::new(
+                    format!("Row has {} cells, expected {}", row.chars().count(), width),
+                    line_no,
+                    1,
+                ));
+            }
+            for (x, c) in row.chars().enumerate() {
+                let position = Position::new(x as i32, y as i32);
+                let heading = match c {
+                    '.' => None,
+                    '#' => {
+                        world.add_wall(position);
+                        None
+                    }
+                    'o' => {
+                        white_buttons.push(position);
+                        None
+                    }
+                    'x' => {
+                        gray_buttons.push(position);
+                        None
+                    }
+                    '^' => Some(Heading::North),
+                    '>' => Some(Heading::East),
+                    'v' => Some(Heading::South),
+                    '<' => Some(Heading::West),
+                    other => {
+                        return Err(LevelError::new(
+                            format!("Unknown cell '{}'", other),
+                            line_no,
+                            x + 1,
+                        ))
+                    }
+                };
+                if let Some(heading) = heading {
+                    if start.is_some() {
+                        return Err(LevelError::new(
+                            "Level has more than one start",
+                            line_no,
+                            x + 1,
+                        ));
+                    }
+                    start = Some(Pose { position, heading });
+                }
+            }
+        }
+
+        let start = start.ok_or_else(|| LevelError::new("Level has no start", rows[0].0, 1))?;
+        Ok(Self {
+            name,
+            byte_limit,
+            points,
+            world,
+            start,
+            white_buttons,
+            gray_buttons,
+        })
+    }
+
+    /// Compile `source` and judge it against this level.
+    ///
+    /// Returns the compile errors if the solution does not compile.
+    pub fn judge(&self, source: &str) -> Result<Judgement, Vec<CompileError>> {
+        let program = match compile_native(source) {
+            CompileResult::Success { program } => program,
+            CompileResult::Error { errors } => return Err(errors),
+        };
+        let bytes = count_bytes_native(source).map_err(|e| vec![CompileError::new(e, 0, 0)])?;
+        Ok(self.judge_program(&program, bytes))
+    }
+
+    /// Judge an already compiled solution of `bytes` bytes.
+    ///
+    /// Every agent starts at the level's start pose; buttons pressed by any
+    /// agent count.
+    pub fn judge_program(&self, program: &CompiledProgram, bytes: u32) -> Judgement {
+        let white: HashSet<Position> = self.white_buttons.iter().copied().collect();
+        let gray: HashSet<Position> = self.gray_buttons.iter().copied().collect();
+        let mut pressed_white = HashSet::new();
+        let mut gray_visits: HashMap<Position, usize> = HashMap::new();
+
+        let starts = program
+            .agents
+            .iter()
+            .map(|agent| (agent.id, self.start))
+            .collect();
+        for trace in self.world.run_program(program, &starts) {
+            for state in trace.states.iter().filter(|s| s.moved) {
+                if white.contains(&state.position) {
+                    pressed_white.insert(state.position);
+                } else if gray.contains(&state.position) {
+                    *gray_visits.entry(state.position).or_default() += 1;
+                }
+            }
+        }
+
+        let unpressed_white: Vec<Position> = self
+            .white_buttons
+            .iter()
+            .filter(|p| !pressed_white.contains(p))
+            .copied()
+            .collect();
+        let pressed_gray: Vec<Position> = self
+            .gray_buttons
+            .iter()
+            .filter(|p| gray_visits.get(p).is_some_and(|n| n % 2 == 1))
+            .copied()
+            .collect();
+
+        let solved = unpressed_white.is_empty() && pressed_gray.is_empty();
+        let score = self
+            .points
+            .zip(self.byte_limit)
+            .map(|(points, limit)| Score::new(points, limit, bytes, solved));
+        Judgement {
+            solved,
+            unpressed_white,
+            pressed_gray,
+            steps: program.max_steps,
+            bytes,
+            byte_limit: self.byte_limit,
+            within_limit: self.byte_limit.map_or(true, |limit| bytes <= limit),
+            score,
+        }
+    }
+}
+
+/// Parse the numeric value of a `KEY=VALUE` header.
+fn parse_number(key: &str, value: &str, line: usize) -> Result<u32, LevelError> {
+    value.parse().map_err(|_| {
+        LevelError::new(
+            format!("Invalid {} value '{}'", key.trim(), value),
+            line,
+            key.len() + 2,
+        )
+    })
+}
+
+impl FromStr for Level {
+    type Err = LevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "LIMIT=4\n>.o.o\n";
+
+    #[test]
+    fn test_parse_level() {
+        let level =
+            Level::parse("// A small level\nNAME=Test\nLIMIT=12\n\n..o..\n.#x#.\n..^..\n").unwrap();
+        assert_eq!(level.name.as_deref(), Some("Test"));
+        assert_eq!(level.byte_limit, Some(12));
+        assert_eq!((level.world.width(), level.world.height()), (5, 3));
+        assert!(level.world.is_wall(Position::new(1, 1)));
+        assert_eq!(level.white_buttons, vec![Position::new(2, 0)]);
+        assert_eq!(level.gray_buttons, vec![Position::new(2, 1)]);
+        assert_eq!(level.start, Pose::new(2, 2, Heading::North));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Level::parse("...\n.?^\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 2));
+        assert!(Level::parse("...\n..\n").is_err());
+        assert!(Level::parse("...\n")
+            .unwrap_err()
+            .message
+            .contains("no start"));
+        assert!(Level::parse("^.v\n")
+            .unwrap_err()
+            .message
+            .contains("more than one"));
+        assert!(Level::parse("LIMIT=abc\n^\n").is_err());
+        assert!(Level::parse("SIZE=3\n^\n").is_err());
+        assert!(Level::parse("^\nLIMIT=3\n").is_err());
+        assert!(Level::parse("// nothing\n").is_err());
+    }
+
+    #[test]
+    fn test_judge_solved() {
+        let level: Level = LINE.parse().unwrap();
+        let judgement = level.judge("sssss").unwrap();
+        assert!(judgement.solved);
+        assert_eq!(judgement.steps, 5);
+        assert_eq!(judgement.bytes, 5);
+        assert!(!judgement.within_limit);
+
+        let judgement = level.judge("a:sa a").unwrap();
+        assert!(judgement.solved);
+        assert!(judgement.within_limit);
+    }
+
+    #[test]
+    fn test_judge_reports_unpressed_buttons() {
+        let level: Level = LINE.parse().unwrap();
+        let judgement = level.judge("ss").unwrap();
+        assert!(!judgement.solved);
+        assert_eq!(judgement.unpressed_white, vec![Position::new(4, 0)]);
+    }
+
+    #[test]
+    fn test_multi_cell_move_presses_buttons_on_the_way() {
+        let level: Level = LINE.parse().unwrap();
+        let mut program = crate::compile_program("s");
+        program.agents[0].commands[0].steps = Some(4);
+        assert!(level.judge_program(&program, 1).solved);
+    }
+
+    #[test]
+    fn test_gray_buttons_toggle() {
+        let level = Level::parse(">x.\n").unwrap();
+        let once = level.judge("s").unwrap();
+        assert!(!once.solved);
+        assert_eq!(once.pressed_gray, vec![Position::new(1, 0)]);
+
+        // Visited twice: on, then off again
+        assert!(level.judge("ssrrss").unwrap().solved);
+        // Bumping into the edge beyond it does not press it again
+        assert!(!level.judge("ssss").unwrap().solved);
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(Score::new(100, 10, 11, true).points, 90);
+        assert_eq!(Score::new(100, 10, 1000, true).points, 1);
+        assert_eq!(Score::new(100, 0, 3, true).points, 0);
+        assert_eq!(
+            Score::new(u32::MAX, u32::MAX, u32::MAX, true).points,
+            u32::MAX
+        );
+    }
+
+    #[test]
+    fn test_judge_scores_with_points() {
+        let level = Level::parse("POINTS=50\nLIMIT=4\n>.o.o\n").unwrap();
+        assert_eq!(level.judge("a:sa a").unwrap().score.unwrap().points, 50);
+        assert_eq!(level.judge("sssss").unwrap().score.unwrap().points, 40);
+        assert_eq!(level.judge("sss").unwrap().score.unwrap().points, 0);
+        // Without POINTS there is nothing to score
+        let level: Level = LINE.parse().unwrap();
+        assert!(level.judge("a:sa a").unwrap().score.is_none());
+    }
+
+    #[test]
+    fn test_judge_compile_error() {
+        let level: Level = LINE.parse().unwrap();
+        let errors = level.judge("f(s)").unwrap_err();
+        assert!(!errors.is_empty());
+    }
+}
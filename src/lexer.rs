@@ -13,6 +13,12 @@ pub struct Lexer<'a> {
     at_line_start: bool,
     /// Track if we just saw '=' (for directive value recognition)
     after_equals: bool,
+    /// Set when the last error was an invalid directive name or value
+    in_bad_directive: bool,
+    /// Name of the most recent directive
+    directive: Option<String>,
+    /// Set by `EXTENSION=WAIT`: `w` is the wait primitive, not an identifier
+    wait_enabled: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -26,6 +32,9 @@ impl<'a> Lexer<'a> {
             column: 1,
             at_line_start: true,
             after_equals: false,
+            in_bad_directive: false,
+            directive: None,
+            wait_enabled: false,
         }
     }
 
@@ -124,6 +133,11 @@ impl<'a> Lexer<'a> {
                 TokenKind::Equals
             }
 
+            'w' if self.wait_enabled => {
+                self.at_line_start = false;
+                TokenKind::Wait
+            }
+
             // Identifiers (lowercase letters except s, r, l)
             c if c.is_ascii_lowercase() => {
                 self.at_line_start = false;
@@ -138,7 +152,7 @@ impl<'a> Lexer<'a> {
                 self.after_equals = false;
 
                 // At line start, check if this could be a directive
-                // Directives are multi-char uppercase words with underscores: MAX_STEP, MAX_DEPTH, MAX_MEMORY, ON_LIMIT
+                // Directives are multi-char uppercase words with underscores, e.g. MAX_STEP, ON_LIMIT
                 let next_char = self.peek_char();
                 let could_be_directive = was_at_line_start
                     && (next_char == Some('_')
@@ -150,31 +164,45 @@ impl<'a> Lexer<'a> {
 
                     // Check if it's a known directive
                     match word.as_str() {
-                        "MAX_STEP" | "MAX_DEPTH" | "MAX_MEMORY" | "ON_LIMIT" => {
+                        "MAX_STEP" | "MAX_DEPTH" | "MAX_MEMORY" | "ON_LIMIT" | "ON_COLLISION"
+                        | "PRIORITY" | "ON_END" | "EXTENSION" => {
+                            self.directive = Some(word.clone());
                             TokenKind::Directive(word)
                         }
                         _ => {
                             // Unknown directive - E009
-                            return Err(LexerError::new(
-                                format!("Unknown directive '{}' (E009)", word),
-                                start_line,
-                                start_column,
+                            self.in_bad_directive = true;
+                            return Err(LexerError::invalid_directive(
+                                format!("Unknown directive '{}'", word),
+                                Span::new(start_pos, self.current_pos, start_line, start_column),
                             ));
                         }
                     }
                 } else if was_after_equals {
-                    // After '=', check if this could be ERROR or TRUNCATE
+                    // After '=', check if this could be a keyword value (ERROR, WAIT, LOW_ID, ...)
                     let next_char = self.peek_char();
                     if next_char.map(|c| c.is_ascii_uppercase()).unwrap_or(false) {
                         let word = self.read_uppercase_word(c);
                         match word.as_str() {
-                            "ERROR" | "TRUNCATE" => TokenKind::DirectiveValue(word),
+                            "ERROR" | "TRUNCATE" | "WARN" | "WAIT" | "LOW_ID" | "HIGH_ID"
+                            | "LONGEST" | "OMIT" | "STOP" | "LOOP" => {
+                                if word == "WAIT" && self.directive.as_deref() == Some("EXTENSION")
+                                {
+                                    self.wait_enabled = true;
+                                }
+                                TokenKind::DirectiveValue(word)
+                            }
                             _ => {
                                 // Unknown directive value - E009
-                                return Err(LexerError::new(
-                                    format!("Unknown directive value '{}' (E009)", word),
-                                    start_line,
-                                    start_column,
+                                self.in_bad_directive = true;
+                                return Err(LexerError::invalid_directive(
+                                    format!("Unknown directive value '{}'", word),
+                                    Span::new(
+                                        start_pos,
+                                        self.current_pos,
+                                        start_line,
+                                        start_column,
+                                    ),
                                 ));
                             }
                         }
@@ -212,10 +240,9 @@ impl<'a> Lexer<'a> {
 
             // Unknown character
             _ => {
-                return Err(LexerError::new(
+                return Err(LexerError::at(
                     format!("Unexpected character '{}'", ch),
-                    start_line,
-                    start_column,
+                    Span::new(start_pos, self.current_pos, start_line, start_column),
                 ));
             }
         };
@@ -240,6 +267,50 @@ impl<'a> Lexer<'a> {
         Ok(tokens)
     }
 
+    /// Tokenize the entire input, skipping over invalid input.
+    ///
+    /// Returns every token that could be read together with all lexer errors.
+    /// An error inside a directive line discards the rest of that line, so the
+    /// parser does not see a dangling `=value`.
+    pub fn tokenize_recovering(&mut self) -> (Vec<Token>, Vec<LexerError>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        let mut line_start = 0;
+
+        loop {
+            match self.next_token() {
+                Ok(token) => {
+                    let kind = token.kind.clone();
+                    tokens.push(token);
+                    match kind {
+                        TokenKind::Eof => break,
+                        TokenKind::Newline => line_start = tokens.len(),
+                        _ => {}
+                    }
+                }
+                Err(e) => {
+                    errors.push(e);
+                    if std::mem::take(&mut self.in_bad_directive) {
+                        tokens.truncate(line_start);
+                        self.skip_line();
+                    }
+                }
+            }
+        }
+
+        (tokens, errors)
+    }
+
+    /// Skip the rest of the current line (up to, not including, the newline).
+    fn skip_line(&mut self) {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c == '\n' || c == '\r' {
+                break;
+            }
+            self.advance();
+        }
+    }
+
     /// Skip comments (// or #).
     fn skip_comment(&mut self) {
         if let Some(&(_, ch)) = self.chars.peek() {
@@ -644,6 +715,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_directive_priority() {
+        let mut lexer = Lexer::new("PRIORITY=HIGH_ID");
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Directive("PRIORITY".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Equals);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::DirectiveValue("HIGH_ID".to_string())
+        );
+    }
+
+    #[test]
+    fn test_wait_extension() {
+        let kinds = |input| {
+            Lexer::new(input)
+                .tokenize()
+                .unwrap()
+                .into_iter()
+                .map(|t| t.kind)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds("w")[0], TokenKind::Ident('w'));
+        // Other directives with a WAIT value leave `w` alone
+        assert!(kinds("ON_COLLISION=WAIT\nw").contains(&TokenKind::Ident('w')));
+        let tokens = kinds("EXTENSION=WAIT\nww");
+        assert_eq!(
+            tokens[tokens.len() - 3..],
+            [TokenKind::Wait, TokenKind::Wait, TokenKind::Eof]
+        );
+    }
+
     #[test]
     fn test_unknown_directive_error() {
         let mut lexer = Lexer::new("MAX_STEPS=100");
@@ -738,4 +843,43 @@ mod tests {
             TokenKind::DirectiveValue("ERROR".to_string())
         );
     }
+
+    #[test]
+    fn test_tokenize_recovering_collects_errors() {
+        let mut lexer = Lexer::new("0: s?r@l");
+        let (tokens, errors) = lexer.tokenize_recovering();
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].column), (1, 5));
+        assert_eq!((errors[1].line, errors[1].column), (1, 7));
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::AgentId(0),
+                TokenKind::Colon,
+                TokenKind::Space,
+                TokenKind::Straight,
+                TokenKind::Right,
+                TokenKind::Left,
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_recovering_skips_bad_directive_line() {
+        let mut lexer = Lexer::new("MAX_STEPS=10\nON_LIMIT=ABORT\ns");
+        let (tokens, errors) = lexer.tokenize_recovering();
+        assert_eq!(errors.len(), 2);
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Newline,
+                TokenKind::Newline,
+                TokenKind::Straight,
+                TokenKind::Eof,
+            ]
+        );
+    }
 }
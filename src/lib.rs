@@ -87,9 +87,19 @@
 //! - [`ast`]: Abstract Syntax Tree definitions
 //! - [`lexer`]: Tokenizer for source code
 //! - [`parser`]: Recursive descent parser
+//! - [`resolver`]: Static checks of calls and parameters
+//! - [`lint`]: Warning-level diagnostics
 //! - [`expander`]: Macro and function expansion
+//! - [`rope`]: Shared command sequences for compressed expansion
 //! - [`scheduler`]: Multi-agent parallel scheduling
+//! - [`collision`]: Collision detection between placed agents
 //! - [`output`]: JSON-serializable output structures
+//! - [`compact`]: Merging runs of commands
+//! - [`timing`]: Physical-time timelines
+//! - [`world`]: Grid world simulator for Herbert boards
+//! - [`level`]: Herbert levels, judging and scoring
+//! - [`render`]: SVG and text pictures of agent paths
+//! - [`toio`]: toio Core Cube payloads and mat targets
 //! - [`token`]: Token type definitions
 //! - [`error`]: Error types for compilation stages
 //!
@@ -107,21 +117,61 @@
 #![warn(rustdoc::missing_crate_level_docs)]
 
 pub mod ast;
+pub mod collision;
+pub mod compact;
 pub mod error;
 pub mod expander;
+pub mod level;
 pub mod lexer;
+pub mod lint;
 pub mod output;
 pub mod parser;
+pub mod render;
+pub mod resolver;
+pub mod rope;
 pub mod scheduler;
+pub mod timing;
+pub mod toio;
 pub mod token;
+pub mod world;
 
+use ast::{OnCollision, OnEnd};
+use error::{CompileError, ParseError};
 use expander::Expander;
-use output::{CompileResult, CompiledProgram};
+use lint::{LintConfig, Linter};
+use output::{AgentPose, CompileResult, CompiledAgent, CompiledProgram, ToioCommand};
 use parser::Parser;
+use resolver::Resolver;
 use scheduler::Scheduler;
-use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use token::Span;
 use wasm_bindgen::prelude::*;
 
+/// Options for [`compile_native_with_options`] and [`compile_with_options`].
+///
+/// Missing fields deserialize to their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompileOptions {
+    /// Which lint warnings to report
+    pub lints: LintConfig,
+    /// Add every agent's pose after each step to the timeline
+    pub poses: bool,
+    /// Start poses for pose output and collision checks; other agents start
+    /// at the origin facing north and are not checked for collisions
+    pub start_poses: Vec<AgentPose>,
+    /// Timeline entries for finished agents; overrides the `ON_END` directive
+    pub on_end: Option<OnEnd>,
+    /// Reject language extensions that HOJ does not have (`EXTENSION=WAIT`)
+    pub strict_hoj: bool,
+    /// Add each agent's `compacted` commands, with runs merged
+    pub compact: bool,
+    /// Add a `timed_timeline` in milliseconds with these command durations
+    pub timing: Option<timing::TimingOptions>,
+}
+
 /// Helper function to serialize values to JsValue using JSON-compatible format.
 /// This ensures that JavaScript receives plain objects instead of Map instances.
 fn to_js_value<T: Serialize>(value: &T) -> JsValue {
@@ -166,8 +216,12 @@ pub fn init() {
 /// # Returns
 ///
 /// A [`JsValue`] containing a serialized [`CompileResult`]:
-/// - On success: `{ "status": "success", "program": { ... } }`
-/// - On error: `{ "status": "error", "errors": [ ... ] }`
+/// - On success: `{ "status": "success", "version": 2, "program": { ... } }`
+/// - On error: `{ "status": "error", "version": 2, "errors": [ ... ] }`
+///
+/// Each error carries `code` (e.g. `"E004"`, or `null` for plain syntax errors),
+/// `severity`, `line`/`column`, the byte range `start`/`end` and `message`,
+/// plus `expected`/`found` for parse errors.
 ///
 /// # Example (JavaScript)
 ///
@@ -187,14 +241,89 @@ pub fn init() {
 /// - Maximum recursion depth exceeded
 #[wasm_bindgen]
 pub fn compile(source: &str) -> JsValue {
-    let result = compile_internal(source);
+    let result = compile_internal(source, &CompileOptions::default());
     to_js_value(&result)
 }
 
+/// Compiles H2 source code with a custom lint configuration.
+///
+/// Same as [`compile`], but `lints` selects which warnings appear in
+/// `program.warnings`. It is a [`LintConfig`] object; omitted lints stay
+/// enabled, and `undefined`/`null` enables all of them.
+///
+/// # Example (JavaScript)
+///
+/// ```javascript
+/// const result = compile_with_lints('0: a:sa x:s a', { unused_definition: false });
+/// console.log(result.program.warnings);  // only the W004 recursion warning
+/// ```
+#[wasm_bindgen]
+pub fn compile_with_lints(source: &str, lints: JsValue) -> JsValue {
+    let options = match from_js_config(lints, "lint configuration") {
+        Ok(lints) => CompileOptions {
+            lints,
+            ..CompileOptions::default()
+        },
+        Err(error) => return to_js_value(&error),
+    };
+    to_js_value(&compile_internal(source, &options))
+}
+
+/// Compiles H2 source code with [`CompileOptions`].
+///
+/// `options` may set `lints`, `poses`, `start_poses`, `on_end`, `strict_hoj`,
+/// `compact` and `timing`; `undefined`/`null` uses the defaults.
+///
+/// - With `poses: true`, every timeline entry carries a `poses` array with each
+///   agent's `{agent_id, x, y, heading}` after that step (`heading` in
+///   clockwise quarter turns from north, `y` growing southwards).
+/// - `on_end` (`"omit"`, `"stop"` or `"loop"`) overrides the `ON_END`
+///   directive.
+/// - With `compact: true`, every agent carries a `compacted` list of merged
+///   commands, each with the `start_step`/`end_step` range of timeline steps
+///   it replaces.
+/// - A `timing` object (`{ durations: { straight_ms, turn_ms, wait_ms },
+///   agents, barriers }`) adds a `timed_timeline` whose entries start at
+///   `time_ms` and give each command's `start_ms`/`end_ms`.
+///
+/// # Example (JavaScript)
+///
+/// ```javascript
+/// const result = compile_with_options('0: srs', {
+///   poses: true,
+///   start_poses: [{ agent_id: 0, x: 2, y: 2, heading: 0 }],
+/// });
+/// console.log(result.program.timeline[2].poses);  // [{ agent_id: 0, x: 3, y: 1, heading: 1 }]
+/// ```
+#[wasm_bindgen]
+pub fn compile_with_options(source: &str, options: JsValue) -> JsValue {
+    match from_js_config::<CompileOptions>(options, "compile options") {
+        Ok(options) => to_js_value(&compile_internal(source, &options)),
+        Err(error) => to_js_value(&error),
+    }
+}
+
+/// Deserialize an optional configuration object passed from JavaScript.
+///
+/// `undefined` and `null` give the default; invalid objects give an error
+/// result naming `what`.
+fn from_js_config<T: DeserializeOwned + Default>(
+    value: JsValue,
+    what: &str,
+) -> Result<T, CompileResult> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(T::default());
+    }
+    serde_wasm_bindgen::from_value(value).map_err(|e| CompileResult::Error {
+        errors: vec![CompileError::new(format!("Invalid {}: {}", what, e), 0, 0)],
+    })
+}
+
 /// Validates H2 source code without full compilation.
 ///
-/// Performs lexical and syntactic analysis without macro/function expansion.
-/// Useful for quick syntax checking in editors or IDEs.
+/// Performs lexical, syntactic and static semantic analysis (undefined
+/// functions, arity, argument types, unbound parameters) without expansion.
+/// Useful for quick checking in editors or IDEs.
 ///
 /// # Arguments
 ///
@@ -203,8 +332,8 @@ pub fn compile(source: &str) -> JsValue {
 /// # Returns
 ///
 /// A [`JsValue`] containing a unified response format:
-/// - On success: `{ "status": "ok", "valid": true }`
-/// - On error: `{ "status": "error", "errors": [ ... ] }`
+/// - On success: `{ "status": "ok", "valid": true, "version": 2, "warnings": [ ... ] }`
+/// - On error: `{ "status": "error", "version": 2, "errors": [ ... ] }`
 ///
 /// # Example (JavaScript)
 ///
@@ -218,23 +347,18 @@ pub fn compile(source: &str) -> JsValue {
 /// ```
 #[wasm_bindgen]
 pub fn validate(source: &str) -> JsValue {
-    let mut parser = match Parser::new(source) {
-        Ok(p) => p,
-        Err(e) => {
-            return to_js_value(&CompileResult::Error {
-                errors: vec![e.into()],
-            })
-        }
-    };
+    let (program, checked, errors) = analyze(source);
 
-    match parser.parse_program() {
-        Ok(_) => {
-            let result = serde_json::json!({ "status": "ok", "valid": true });
-            to_js_value(&result)
-        }
-        Err(e) => to_js_value(&CompileResult::Error {
-            errors: vec![e.into()],
-        }),
+    if errors.is_empty() {
+        let result = serde_json::json!({
+            "status": "ok",
+            "valid": true,
+            "version": output::OUTPUT_VERSION,
+            "warnings": lint_warnings(&program, &checked, &LintConfig::default()),
+        });
+        to_js_value(&result)
+    } else {
+        to_js_value(&CompileResult::Error { errors })
     }
 }
 
@@ -342,6 +466,142 @@ pub fn count_bytes(source: &str) -> JsValue {
     }
 }
 
+/// Scores H2 source code against a level's point value and byte limit.
+///
+/// Counts bytes like [`count_bytes`] and applies the HOJ scoring formula (see
+/// [`level::Score`]): full `points` at or under `limit` bytes, `points ×
+/// limit / bytes` above it, and 0 when `solved` is false. Source that does
+/// not compile is an error.
+///
+/// # Returns
+///
+/// A [`JsValue`] containing:
+/// - On success: `{ "status": "success", "bytes", "limit", "solved", "points", "max_points" }`
+/// - On error: `{ "status": "error", "message": "<error message>" }`
+///
+/// # Example (JavaScript)
+///
+/// ```javascript
+/// const result = score("ssss", 100, 2, true);
+/// console.log(result.points); // 50
+/// ```
+#[wasm_bindgen]
+pub fn score(source: &str, points: u32, limit: u32, solved: bool) -> JsValue {
+    match score_native(source, points, limit, solved) {
+        Ok(score) => {
+            let mut result = serde_json::to_value(score).unwrap_or_default();
+            result["status"] = "success".into();
+            to_js_value(&result)
+        }
+        Err(e) => {
+            let result = serde_json::json!({ "status": "error", "message": e });
+            to_js_value(&result)
+        }
+    }
+}
+
+/// Compiles H2 source code and renders every agent's path as an SVG image.
+///
+/// `options` is a [`render::SvgOptions`] object (`cell_size`, `grid`,
+/// `step_numbers`, `start_poses`); `undefined`/`null` uses the defaults.
+///
+/// # Returns
+///
+/// A [`JsValue`] containing:
+/// - On success: `{ "status": "success", "svg": "<svg ...>...</svg>" }`
+/// - On error: the compile error result (`{ "status": "error", "errors": [...] }`)
+///
+/// # Example (JavaScript)
+///
+/// ```javascript
+/// const result = render_svg('0: a:ssra a\n1: lssss', { step_numbers: true });
+/// if (result.status === 'success') {
+///   document.getElementById('preview').innerHTML = result.svg;
+/// }
+/// ```
+#[wasm_bindgen]
+pub fn render_svg(source: &str, options: JsValue) -> JsValue {
+    let options = match from_js_config(options, "SVG options") {
+        Ok(options) => options,
+        Err(error) => return to_js_value(&error),
+    };
+    match render_svg_native(source, &options) {
+        Ok(svg) => to_js_value(&serde_json::json!({ "status": "success", "svg": svg })),
+        Err(errors) => to_js_value(&CompileResult::Error { errors }),
+    }
+}
+
+/// Compiles H2 source code and draws every agent's path as text.
+///
+/// `options` is a [`render::TextOptions`] object (`style`: `"unicode"` or
+/// `"ascii"`, `start_poses`, `max_cells`); `undefined`/`null` uses the
+/// defaults.
+///
+/// # Returns
+///
+/// A [`JsValue`] containing:
+/// - On success: `{ "status": "success", "text": "┌─▶\n│..\n0..\n" }`
+/// - On error: the compile error result (`{ "status": "error", "errors": [...] }`),
+///   also used when the grid exceeds `max_cells`
+#[wasm_bindgen]
+pub fn render_text(source: &str, options: JsValue) -> JsValue {
+    let options = match from_js_config(options, "text options") {
+        Ok(options) => options,
+        Err(error) => return to_js_value(&error),
+    };
+    match render_text_native(source, &options) {
+        Ok(text) => to_js_value(&serde_json::json!({ "status": "success", "text": text })),
+        Err(errors) => to_js_value(&CompileResult::Error { errors }),
+    }
+}
+
+/// Compiles H2 source code and encodes it as toio Core Cube BLE payloads.
+///
+/// `options` is a [`toio::ToioOptions`] object (`mode`: `"timed"` or
+/// `"target"`, `speed`, `step_ms`, `turn_ms`, `step_distance`, `origin`,
+/// `mat`, `start_poses`); `undefined`/`null` uses the defaults. Each payload is an
+/// array of bytes to write to the motor control characteristic.
+///
+/// # Returns
+///
+/// A [`JsValue`] containing:
+/// - On success: `{ "status": "success", "agents": [{ "agent_id": 0, "payloads": [[2, 1, 1, 50, 2, 1, 50, 50]] }] }`
+/// - On error: the compile error result (`{ "status": "error", "errors": [...] }`)
+#[wasm_bindgen]
+pub fn toio_payloads(source: &str, options: JsValue) -> JsValue {
+    let options = match from_js_config(options, "toio options") {
+        Ok(options) => options,
+        Err(error) => return to_js_value(&error),
+    };
+    match toio_payloads_native(source, &options) {
+        Ok(agents) => to_js_value(&serde_json::json!({ "status": "success", "agents": agents })),
+        Err(errors) => to_js_value(&CompileResult::Error { errors }),
+    }
+}
+
+/// Compiles H2 source code and maps each agent's path onto toio play mat poses.
+///
+/// `options` is a [`toio::ToioOptions`] object as for [`toio_payloads`]; only
+/// `step_distance`, `origin`, `mat` and `start_poses` matter here. Each
+/// target is an absolute `{ x, y, angle }` mat pose, one per command.
+///
+/// # Returns
+///
+/// A [`JsValue`] containing:
+/// - On success: `{ "status": "success", "agents": [{ "agent_id": 0, "start": { "x": 250, "y": 250, "angle": 270 }, "targets": [...] }] }`
+/// - On error: the compile error result (`{ "status": "error", "errors": [...] }`)
+#[wasm_bindgen]
+pub fn toio_targets(source: &str, options: JsValue) -> JsValue {
+    let options = match from_js_config(options, "toio options") {
+        Ok(options) => options,
+        Err(error) => return to_js_value(&error),
+    };
+    match toio_targets_native(source, &options) {
+        Ok(agents) => to_js_value(&serde_json::json!({ "status": "success", "agents": agents })),
+        Err(errors) => to_js_value(&CompileResult::Error { errors }),
+    }
+}
+
 // =============================================================================
 // Native Rust API
 // =============================================================================
@@ -419,8 +679,58 @@ pub fn count_bytes(source: &str) -> JsValue {
 /// - **Lexer errors**: Invalid characters, malformed tokens
 /// - **Parser errors**: Syntax errors, unexpected tokens
 /// - **Expansion errors**: Undefined macros/functions, infinite recursion
+///
+/// Lint warnings (all lints enabled) are returned in `program.warnings`; see
+/// [`compile_native_with_lints`] to choose them.
 pub fn compile_native(source: &str) -> CompileResult {
-    compile_internal(source)
+    compile_internal(source, &CompileOptions::default())
+}
+
+/// Compiles H2 source code with a custom lint configuration (native Rust API).
+///
+/// # Examples
+///
+/// ```
+/// use h2lang::compile_native_with_lints;
+/// use h2lang::lint::LintConfig;
+/// use h2lang::output::CompileResult;
+///
+/// let lints = LintConfig {
+///     unused_definition: false,
+///     ..LintConfig::default()
+/// };
+/// if let CompileResult::Success { program } = compile_native_with_lints("x:ss y:rr x", &lints) {
+///     assert!(program.warnings.is_empty());
+/// }
+/// ```
+pub fn compile_native_with_lints(source: &str, lints: &LintConfig) -> CompileResult {
+    let options = CompileOptions {
+        lints: *lints,
+        ..CompileOptions::default()
+    };
+    compile_internal(source, &options)
+}
+
+/// Compiles H2 source code with [`CompileOptions`] (native Rust API).
+///
+/// # Examples
+///
+/// ```
+/// use h2lang::output::{AgentPose, CompileResult};
+/// use h2lang::{compile_native_with_options, CompileOptions};
+///
+/// let options = CompileOptions {
+///     poses: true,
+///     start_poses: vec![AgentPose { agent_id: 0, x: 2, y: 2, heading: 0 }],
+///     ..CompileOptions::default()
+/// };
+/// if let CompileResult::Success { program } = compile_native_with_options("0: srs", &options) {
+///     let poses = program.timeline[2].poses.as_ref().unwrap();
+///     assert_eq!((poses[0].x, poses[0].y, poses[0].heading), (3, 1, 1));
+/// }
+/// ```
+pub fn compile_native_with_options(source: &str, options: &CompileOptions) -> CompileResult {
+    compile_internal(source, options)
 }
 
 /// Counts bytes in H2 source code according to HOJ golf scoring rules (native Rust API).
@@ -453,6 +763,192 @@ pub fn count_bytes_native(source: &str) -> Result<u32, String> {
     count_bytes_internal(source)
 }
 
+/// Compiles H2 source code and renders every agent's path as an SVG image (native Rust API).
+///
+/// Returns the compile errors if the source does not compile, or an E018
+/// error if an agent leaves the grid. See [`render::render_svg`] to render an
+/// already compiled program.
+///
+/// # Examples
+///
+/// ```
+/// use h2lang::render::SvgOptions;
+/// use h2lang::render_svg_native;
+///
+/// let svg = render_svg_native("0: ssrss\n1: llss", &SvgOptions::default()).unwrap();
+/// assert!(svg.contains("<polyline"));
+/// assert!(render_svg_native("f(s)", &SvgOptions::default()).is_err());
+/// ```
+pub fn render_svg_native(
+    source: &str,
+    options: &render::SvgOptions,
+) -> Result<String, Vec<CompileError>> {
+    match compile_native(source) {
+        CompileResult::Success { program } => {
+            render::render_svg(&program, options).map_err(|e| vec![render_error(e)])
+        }
+        CompileResult::Error { errors } => Err(errors),
+    }
+}
+
+/// Compiles H2 source code and draws every agent's path as text (native Rust API).
+///
+/// Returns the compile errors if the source does not compile, an E018 error
+/// if an agent leaves the grid, or an uncoded error if the grid would exceed
+/// `options.max_cells`. See [`render::render_text`] for the drawing rules.
+///
+/// # Examples
+///
+/// ```
+/// use h2lang::render::{TextOptions, TextStyle};
+/// use h2lang::render_text_native;
+///
+/// let options = TextOptions {
+///     style: TextStyle::Ascii,
+///     ..TextOptions::default()
+/// };
+/// assert_eq!(render_text_native("a:ssr aaaa", &options).unwrap(), "+-+\n|.|\n0-+\n");
+/// ```
+pub fn render_text_native(
+    source: &str,
+    options: &render::TextOptions,
+) -> Result<String, Vec<CompileError>> {
+    match compile_native(source) {
+        CompileResult::Success { program } => {
+            render::render_text(&program, options).map_err(|e| vec![render_error(e)])
+        }
+        CompileResult::Error { errors } => Err(errors),
+    }
+}
+
+/// Diagnostic for a rendering error: E018 if an agent leaves the grid, an
+/// uncoded error otherwise. Neither has a source location.
+fn render_error(e: error::RenderError) -> CompileError {
+    match e {
+        error::RenderError::PoseOverflow(_) => CompileError {
+            code: Some(error::ErrorCode::E018),
+            ..CompileError::new(e.to_string(), 0, 0)
+        },
+        error::RenderError::GridTooLarge { .. } => CompileError::new(e.to_string(), 0, 0),
+    }
+}
+
+/// Compiles H2 source code and encodes it as toio Core Cube BLE payloads (native Rust API).
+///
+/// Agents start at `options.start_poses`, which also enable the collision
+/// checks and `ON_COLLISION=WAIT` scheduling of [`CompileOptions`]. Returns
+/// the compile errors if the source does not compile, an E016 error at the
+/// agent's line if a target position lies outside `options.mat`, or an
+/// uncoded error for invalid options. See [`toio::encode_program`] for the
+/// encoding.
+///
+/// # Examples
+///
+/// ```
+/// use h2lang::toio::ToioOptions;
+/// use h2lang::toio_payloads_native;
+///
+/// let agents = toio_payloads_native("0: sr", &ToioOptions::default()).unwrap();
+/// assert_eq!(agents[0].payloads[0], [0x02, 0x01, 0x01, 50, 0x02, 0x01, 50, 50]);
+/// ```
+pub fn toio_payloads_native(
+    source: &str,
+    options: &toio::ToioOptions,
+) -> Result<Vec<toio::AgentPayloads>, Vec<CompileError>> {
+    let (program, ast) = compile_for_toio(source, options)?;
+    toio::encode_program(&program, options).map_err(|e| vec![toio_error(&ast, e)])
+}
+
+/// Compiles H2 source code and maps each agent's path onto toio play mat poses (native Rust API).
+///
+/// Each agent gets its mat start pose and one absolute "move to" pose per
+/// command, from `options.origin`, `options.step_distance` and
+/// `options.start_poses`. Errors are as for [`toio_payloads_native`]; a pose
+/// outside `options.mat`, start included, is an E016 error at the agent's
+/// line.
+///
+/// # Examples
+///
+/// ```
+/// use h2lang::toio::{MatTarget, ToioOptions};
+/// use h2lang::toio_targets_native;
+///
+/// let agents = toio_targets_native("0: sr", &ToioOptions::default()).unwrap();
+/// assert_eq!(agents[0].targets[0], MatTarget { x: 250, y: 210, angle: 270 });
+/// ```
+pub fn toio_targets_native(
+    source: &str,
+    options: &toio::ToioOptions,
+) -> Result<Vec<toio::AgentTargets>, Vec<CompileError>> {
+    let (program, ast) = compile_for_toio(source, options)?;
+    toio::mat_targets(&program, options).map_err(|e| vec![toio_error(&ast, e)])
+}
+
+/// Compile with the toio start poses, keeping the parsed program for
+/// [`toio_error`].
+fn compile_for_toio(
+    source: &str,
+    options: &toio::ToioOptions,
+) -> Result<(CompiledProgram, ast::Program), Vec<CompileError>> {
+    let compile_options = CompileOptions {
+        start_poses: options.start_poses.clone(),
+        ..CompileOptions::default()
+    };
+    match compile_with_ast(source, &compile_options) {
+        (ast, CompileResult::Success { program }) => Ok((program, ast)),
+        (_, CompileResult::Error { errors }) => Err(errors),
+    }
+}
+
+/// Diagnostic for a toio encoding error: E016 at the agent's line in `ast`,
+/// or an uncoded error for invalid options.
+fn toio_error(ast: &ast::Program, e: error::ToioError) -> CompileError {
+    match e {
+        error::ToioError::OutOfRange { agent_id, .. } => {
+            let span = agent_span(ast, agent_id);
+            CompileError {
+                code: Some(error::ErrorCode::E016),
+                start: span.start,
+                end: span.end,
+                ..CompileError::new(e.to_string(), span.line, span.column)
+            }
+        }
+        error::ToioError::InvalidOption(_) => CompileError::new(e.to_string(), 0, 0),
+    }
+}
+
+/// Scores H2 source code against a level's point value and byte limit (native Rust API).
+///
+/// Returns an error if the source code does not compile, with the messages
+/// of every compile error joined by `"; "`, so that an invalid program is
+/// never scored.
+///
+/// # Examples
+///
+/// ```
+/// use h2lang::score_native;
+///
+/// let score = score_native("a:sa a", 100, 2, true).unwrap();
+/// assert_eq!(score.bytes, 4);
+/// assert_eq!(score.points, 50);
+///
+/// assert_eq!(score_native("a:sa a", 100, 2, false).unwrap().points, 0);
+/// assert!(score_native("f(X):sa(X-1) f(10)", 100, 2, true).is_err());
+/// ```
+pub fn score_native(
+    source: &str,
+    points: u32,
+    limit: u32,
+    solved: bool,
+) -> Result<level::Score, String> {
+    if let CompileResult::Error { errors } = compile_native(source) {
+        let messages: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+        return Err(messages.join("; "));
+    }
+    let bytes = count_bytes_internal(source)?;
+    Ok(level::Score::new(points, limit, bytes, solved))
+}
+
 /// Internal byte counting implementation with syntax validation.
 ///
 /// First validates the source code by parsing it. If parsing succeeds,
@@ -508,6 +1004,7 @@ fn count_bytes_internal(source: &str) -> Result<u32, String> {
             TokenKind::Straight => count += 1,
             TokenKind::Right => count += 1,
             TokenKind::Left => count += 1,
+            TokenKind::Wait => count += 1,
 
             // Count numbers (1 byte per literal, regardless of digit count)
             TokenKind::Number(_) => count += 1,
@@ -542,56 +1039,294 @@ fn count_bytes_internal(source: &str) -> Result<u32, String> {
 ///
 /// 1. **Lexing**: Source → Tokens
 /// 2. **Parsing**: Tokens → AST
-/// 3. **Expansion**: AST → Expanded Commands (macro/function resolution)
-/// 4. **Scheduling**: Commands → Parallel Timeline
-/// 5. **Output**: Timeline → JSON-serializable structures
-fn compile_internal(source: &str) -> CompileResult {
-    // Phase 1: Parse source code into AST
-    let mut parser = match Parser::new(source) {
-        Ok(p) => p,
-        Err(e) => {
-            return CompileResult::Error {
-                errors: vec![e.into()],
-            }
-        }
-    };
+/// 3. **Resolving**: static checks of calls and parameters (see [`resolver`])
+/// 4. **Expansion**: AST → Commands, streamed into JSON-serializable form
+/// 5. **Scheduling**: Commands → Parallel Timeline, with waits for
+///    `ON_COLLISION=WAIT` and collision checks for placed agents on the
+///    padded timeline
+fn compile_internal(source: &str, options: &CompileOptions) -> CompileResult {
+    compile_with_ast(source, options).1
+}
 
-    let program = match parser.parse_program() {
-        Ok(p) => p,
-        Err(e) => {
-            return CompileResult::Error {
-                errors: vec![e.into()],
-            }
-        }
-    };
+/// [`compile_internal`], also returning the parsed program so that callers
+/// can locate later diagnostics without parsing again.
+fn compile_with_ast(source: &str, options: &CompileOptions) -> (ast::Program, CompileResult) {
+    // Phase 1: Parse and resolve, collecting every syntax and semantic error
+    let (program, checked, errors) = analyze(source);
+    let result = compile_analyzed(&program, &checked, errors, options);
+    (program, result)
+}
+
+/// Phases 2-5 of [`compile_internal`] for the agents at indices `checked`,
+/// given the errors found so far.
+fn compile_analyzed(
+    program: &ast::Program,
+    checked: &[usize],
+    mut errors: Vec<CompileError>,
+    options: &CompileOptions,
+) -> CompileResult {
+    if options.strict_hoj {
+        errors.extend(extension_errors(program));
+    }
 
     // Phase 2: Expand macros and functions for each agent
-    // Use limits from parsed directives
+    // Use limits from parsed directives. Commands are streamed straight into
+    // their output form so no intermediate `Vec<Command>` is kept. Each agent
+    // is expanded on its own so every failing agent is reported.
     let expander = Expander::with_limits(program.limits.clone());
-    let mut expanded_agents = Vec::new();
+    let mut compiled_agents = Vec::with_capacity(program.agents.len());
 
-    for agent in &program.agents {
-        match expander.expand_agent(agent) {
-            Ok(commands) => {
-                expanded_agents.push((agent.id, commands));
-            }
-            Err(e) => {
-                return CompileResult::Error {
-                    errors: vec![e.into()],
-                }
+    for agent in checked.iter().map(|&i| &program.agents[i]) {
+        let mut expansion = expander.expand_agent_iter(agent);
+        let commands: Result<Vec<ToioCommand>, _> = expansion
+            .by_ref()
+            .map(|cmd| cmd.map(ToioCommand::from))
+            .collect();
+
+        match commands {
+            Ok(commands) => compiled_agents.push(CompiledAgent {
+                id: agent.id,
+                commands,
+                expansion: expansion.stats().into(),
+                compacted: None,
+            }),
+            Err(e) => errors.push(e.into()),
+        }
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| (e.line, e.column));
+        return CompileResult::Error { errors };
+    }
+
+    // Phase 3: Delay placed agents until they no longer collide
+    let on_collision = program.schedule.on_collision;
+    let on_end = options.on_end.unwrap_or(program.schedule.on_end);
+    if on_collision == OnCollision::Wait && on_end == OnEnd::Loop {
+        // Waits are planned for agents that stay put once they finish
+        let span = directive_span(program, "ON_COLLISION");
+        let error = ParseError::invalid_directive(
+            "ON_COLLISION=WAIT cannot be combined with ON_END=LOOP",
+            span,
+        );
+        return CompileResult::Error {
+            errors: vec![error.into()],
+        };
+    }
+    if on_collision == OnCollision::Wait {
+        let priority = program.schedule.priority;
+        match Scheduler::schedule_with_waits(&compiled_agents, &options.start_poses, priority) {
+            Ok(agents) => compiled_agents = agents,
+            Err(error) => {
+                let span = agent_span(program, error.agent_id());
+                let errors = vec![error.to_compile_error(span)];
+                return CompileResult::Error { errors };
             }
         }
     }
 
-    // Phase 3: Schedule parallel execution across agents
-    let timeline = Scheduler::schedule(&expanded_agents);
+    // Phase 4: Schedule parallel execution and build the output timeline
+    let mut compiled = CompiledProgram::from_agents(compiled_agents);
+    compiled.warnings = lint_warnings(program, checked, &options.lints);
+    compiled.pad_timeline(on_end);
+
+    // Phase 5: Check placed agents for collisions, following looping agents
+    let unplaced = unplaced_warnings(program, &options.start_poses);
+    compiled.warnings.extend(unplaced);
+    let collisions = match collisions(&compiled, &options.start_poses) {
+        Ok(collisions) => collisions,
+        Err(overflow) => return pose_overflow_error(program, overflow),
+    };
+    for collision in collisions {
+        let span = agent_span(program, collision.agents[0]);
+        let diagnostic = collision.to_compile_error(on_collision, span);
+        match on_collision {
+            OnCollision::Error => errors.push(diagnostic),
+            OnCollision::Warn | OnCollision::Wait => compiled.warnings.push(diagnostic),
+        }
+    }
+    if !errors.is_empty() {
+        return CompileResult::Error { errors };
+    }
 
-    // Phase 4: Convert to JSON-serializable output format
-    let compiled = CompiledProgram::from_expanded(&expanded_agents, timeline);
+    if options.poses {
+        if let Err(overflow) = compiled.add_poses(&options.start_poses) {
+            return pose_overflow_error(program, overflow);
+        }
+    }
+    if options.compact {
+        compiled.compact();
+    }
+    if let Some(timing) = &options.timing {
+        compiled.add_timed_timeline(timing);
+    }
 
     CompileResult::Success { program: compiled }
 }
 
+/// Parse `source` and statically check every agent.
+///
+/// Returns the program, the indices of agents that are free of errors (ready
+/// to expand), and all lexer, parser and resolver errors ordered by position.
+/// Agents whose lines contain syntax errors are not resolved; if the
+/// directives contain errors, no agent is.
+fn analyze(source: &str) -> (ast::Program, Vec<usize>, Vec<CompileError>) {
+    let (mut program, mut errors) = parse_recovering(source);
+
+    let error_lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+    let directives_failed = program
+        .agents
+        .first()
+        .is_some_and(|first| error_lines.iter().any(|&line| line < first.span.line));
+    let agent_lines: Vec<usize> = program.agents.iter().map(|a| a.span.line).collect();
+
+    let mut checked = Vec::with_capacity(program.agents.len());
+    let mut seen = HashSet::new();
+    for (i, agent) in program.agents.iter_mut().enumerate() {
+        let next_line = agent_lines.get(i + 1).copied().unwrap_or(usize::MAX);
+        if directives_failed
+            || error_lines
+                .iter()
+                .any(|&line| (agent_lines[i]..next_line).contains(&line))
+        {
+            continue;
+        }
+
+        let resolve_errors = Resolver::resolve_agent(agent);
+        if resolve_errors.is_empty() {
+            checked.push(i);
+        } else {
+            // Errors in shared definitions are reported once, not per agent
+            let new = resolve_errors
+                .into_iter()
+                .map(CompileError::from)
+                .filter(|e| seen.insert((e.start, e.message.clone())));
+            errors.extend(new);
+        }
+    }
+
+    errors.sort_by_key(|e| (e.line, e.column));
+    (program, checked, errors)
+}
+
+/// Collisions between the agents that have a start pose in `starts`.
+fn collisions(
+    program: &CompiledProgram,
+    starts: &[AgentPose],
+) -> Result<Vec<collision::Collision>, error::PoseOverflow> {
+    let placed: Vec<_> = program
+        .timeline_paths(starts)?
+        .into_iter()
+        .filter(|path| starts.iter().any(|s| s.agent_id == path[0].agent_id))
+        .collect();
+    Ok(collision::detect_collisions(&placed))
+}
+
+/// W006 for each agent that `ON_COLLISION` cannot check because it has no
+/// start pose, when the program sets `ON_COLLISION` and has several agents.
+fn unplaced_warnings(program: &ast::Program, starts: &[AgentPose]) -> Vec<CompileError> {
+    let requested = program.directives.iter().any(|d| d.name == "ON_COLLISION");
+    if !requested || program.agents.len() < 2 {
+        return Vec::new();
+    }
+    program
+        .agents
+        .iter()
+        .filter(|agent| !starts.iter().any(|s| s.agent_id == agent.id))
+        .map(|agent| {
+            let message = format!(
+                "Agent {} has no start pose and is not checked for collisions",
+                agent.id
+            );
+            CompileError::coded(
+                error::ErrorCode::W006,
+                error::Severity::Warning,
+                message,
+                agent.span,
+            )
+        })
+        .collect()
+}
+
+/// E018 at the line of the agent that left the grid.
+fn pose_overflow_error(program: &ast::Program, overflow: error::PoseOverflow) -> CompileResult {
+    let span = agent_span(program, overflow.agent_id);
+    CompileResult::Error {
+        errors: vec![overflow.to_compile_error(span)],
+    }
+}
+
+/// E015 for every `EXTENSION` directive, for strict HOJ mode.
+fn extension_errors(program: &ast::Program) -> Vec<CompileError> {
+    program
+        .directives
+        .iter()
+        .filter(|d| d.name == "EXTENSION")
+        .map(|d| {
+            let message = "EXTENSION is not available in strict HOJ mode";
+            CompileError::coded(
+                error::ErrorCode::E015,
+                error::Severity::Error,
+                message,
+                d.span,
+            )
+        })
+        .collect()
+}
+
+/// Span of the first `name` directive, for diagnostics about its value.
+fn directive_span(program: &ast::Program, name: &str) -> Span {
+    let directive = program.directives.iter().find(|d| d.name == name);
+    directive.map(|d| d.span).unwrap_or_default()
+}
+
+/// Span of agent `id`'s line, for diagnostics about the whole agent.
+fn agent_span(program: &ast::Program, id: u32) -> Span {
+    let agent = program.agents.iter().find(|a| a.id == id);
+    agent.map(|a| a.span).unwrap_or_default()
+}
+
+/// Lint the agents at indices `checked`, in source order.
+fn lint_warnings(
+    program: &ast::Program,
+    checked: &[usize],
+    lints: &LintConfig,
+) -> Vec<CompileError> {
+    let agents: Vec<&ast::Agent> = checked.iter().map(|&i| &program.agents[i]).collect();
+    Linter::lint_agents(&program.shared, &agents, lints)
+        .into_iter()
+        .map(CompileError::from)
+        .collect()
+}
+
+/// Lex and parse `source`, recovering from errors.
+///
+/// Returns the (possibly partial) program and every lexer and parser error,
+/// ordered by position.
+fn parse_recovering(source: &str) -> (ast::Program, Vec<CompileError>) {
+    let (mut parser, lexer_errors) = Parser::new_recovering(source);
+    let (program, parse_errors) = parser.parse_program_recovering();
+
+    let mut errors: Vec<CompileError> = lexer_errors
+        .into_iter()
+        .map(CompileError::from)
+        .chain(parse_errors.into_iter().map(CompileError::from))
+        .collect();
+    errors.sort_by_key(|e| (e.line, e.column));
+
+    (program, errors)
+}
+
+/// Compile `source` with the default options, panicking on errors (for unit
+/// tests).
+#[cfg(test)]
+pub(crate) fn compile_program(source: &str) -> CompiledProgram {
+    match compile_native(source) {
+        CompileResult::Success { program } => program,
+        CompileResult::Error { errors } => panic!("{:?}", errors),
+    }
+}
+
 // =============================================================================
 // Unit Tests
 // =============================================================================
@@ -602,7 +1337,7 @@ mod tests {
 
     #[test]
     fn test_compile_simple() {
-        let result = compile_internal("0: srl");
+        let result = compile_native("0: srl");
         match result {
             CompileResult::Success { program } => {
                 assert_eq!(program.agents.len(), 1);
@@ -619,7 +1354,7 @@ mod tests {
 
     #[test]
     fn test_compile_multiple_agents() {
-        let result = compile_internal("0: srl\n1: lrs");
+        let result = compile_native("0: srl\n1: lrs");
         match result {
             CompileResult::Success { program } => {
                 assert_eq!(program.agents.len(), 2);
@@ -635,7 +1370,7 @@ mod tests {
 
     #[test]
     fn test_compile_with_macro() {
-        let result = compile_internal("0: x:ss xrx");
+        let result = compile_native("0: x:ss xrx");
         match result {
             CompileResult::Success { program } => {
                 // x:ss xrx -> ssrss (5 commands)
@@ -649,7 +1384,7 @@ mod tests {
 
     #[test]
     fn test_compile_with_function() {
-        let result = compile_internal("0: f(X):XXX f(s)");
+        let result = compile_native("0: f(X):XXX f(s)");
         match result {
             CompileResult::Success { program } => {
                 // f(X):XXX f(s) -> sss (3 commands)
@@ -663,7 +1398,7 @@ mod tests {
 
     #[test]
     fn test_compile_nested_function() {
-        let result = compile_internal("0: f(X):XX f(f(s))");
+        let result = compile_native("0: f(X):XX f(f(s))");
         match result {
             CompileResult::Success { program } => {
                 // f(X):XX f(f(s)) -> f(ss) -> ssss (4 commands)
@@ -678,7 +1413,7 @@ mod tests {
     #[test]
     fn test_compile_error_undefined_function() {
         // In v0.5.0, macros are 0-arg functions, so "x" alone is a 0-arg function call
-        let result = compile_internal("0: x");
+        let result = compile_native("0: x");
         match result {
             CompileResult::Success { .. } => {
                 panic!("Expected error for undefined function");
@@ -692,7 +1427,7 @@ mod tests {
 
     #[test]
     fn test_compile_error_syntax() {
-        let result = compile_internal("0: (");
+        let result = compile_native("0: (");
         match result {
             CompileResult::Success { .. } => {
                 panic!("Expected syntax error");
@@ -705,7 +1440,7 @@ mod tests {
 
     #[test]
     fn test_timeline_parallel_execution() {
-        let result = compile_internal("0: srl\n1: lrs");
+        let result = compile_native("0: srl\n1: lrs");
         match result {
             CompileResult::Success { program } => {
                 // Step 0: agent 0 -> s, agent 1 -> l
@@ -729,7 +1464,7 @@ mod tests {
     #[test]
     fn test_complex_hoj_example() {
         // From HOJ tutorial: square pattern
-        let result = compile_internal("0: f(X):XXXX f(sssr)");
+        let result = compile_native("0: f(X):XXXX f(sssr)");
         match result {
             CompileResult::Success { program } => {
                 // f(X):XXXX f(sssr) -> sssrsssrsssrsssr (16 commands)
@@ -740,4 +1475,94 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_compile_reports_truncation() {
+        // `a:sas a` never returns, so it recurses until MAX_DEPTH
+        let result = compile_native("MAX_DEPTH=5\nON_LIMIT=TRUNCATE\n0: a:sas a\n1: srl");
+        match result {
+            CompileResult::Success { program } => {
+                let info = program.agents[0].expansion;
+                assert!(info.truncated);
+                let hit = info.limit_hit.unwrap();
+                assert_eq!(hit.limit, output::LimitType::MaxDepth);
+                assert_eq!((hit.line, hit.column), (3, 6));
+                assert_eq!(info.steps_generated, 5);
+                assert_eq!(info.peak_depth, 5);
+
+                let info = program.agents[1].expansion;
+                assert!(!info.truncated);
+                assert!(info.limit_hit.is_none());
+                assert_eq!(info.steps_generated, 3);
+                assert_eq!(info.peak_depth, 0);
+            }
+            CompileResult::Error { errors } => {
+                panic!("Compilation failed: {:?}", errors);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_reports_max_step_in_json() {
+        let result = compile_native("MAX_STEP=3\nON_LIMIT=TRUNCATE\n0: a(X):sa(X-1) a(10)");
+        let json = serde_json::to_value(&result).unwrap();
+        let expansion = &json["program"]["agents"][0]["expansion"];
+        assert_eq!(expansion["truncated"], true);
+        assert_eq!(expansion["limit_hit"]["limit"], "max_step");
+        assert_eq!(expansion["steps_generated"], 3);
+        // Tail calls after a step reuse their caller's depth
+        assert_eq!(expansion["peak_depth"], 1);
+    }
+
+    #[test]
+    fn test_compile_reports_all_errors() {
+        let result = compile_native("0: s?\n1: a\n2: f(X):X f(s\n3: srl");
+        match result {
+            CompileResult::Error { errors } => {
+                let positions: Vec<_> = errors.iter().map(|e| (e.line, e.column)).collect();
+                assert_eq!(positions, vec![(1, 5), (2, 4), (3, 14)]);
+                assert!(errors[1].message.contains("Undefined function 'a'"));
+            }
+            _ => panic!("Expected errors"),
+        }
+    }
+
+    #[test]
+    fn test_compile_reports_expansion_error_per_agent() {
+        let result = compile_native("MAX_STEP=2\n0: sss\n1: ss\n2: rrr");
+        match result {
+            CompileResult::Error { errors } => {
+                assert_eq!(errors.len(), 2);
+                assert_eq!(errors[0].line, 2);
+                assert_eq!(errors[1].line, 4);
+                assert!(errors.iter().all(|e| e.message.contains("E004")));
+            }
+            _ => panic!("Expected errors"),
+        }
+    }
+
+    #[test]
+    fn test_compile_skips_expansion_after_directive_error() {
+        // Limits are unknown when a directive is invalid, so nothing is expanded
+        let result = compile_native("MAX_STEP=0\n0: a\n1: s)");
+        match result {
+            CompileResult::Error { errors } => {
+                assert_eq!(errors.len(), 2);
+                assert_eq!(errors[0].line, 1);
+                assert_eq!(errors[1].line, 3);
+            }
+            _ => panic!("Expected errors"),
+        }
+    }
+
+    #[test]
+    fn test_analyze_reports_semantic_errors_without_expanding() {
+        let (_, checked, errors) = analyze("0: a(X):sb(X) a(0)\n1: f(X):XX f(3)\n2: srl");
+        assert_eq!(checked, vec![2]);
+        let codes: Vec<_> = errors.iter().map(|e| e.code).collect();
+        assert_eq!(
+            codes,
+            vec![Some(error::ErrorCode::E002), Some(error::ErrorCode::E008)]
+        );
+    }
 }
@@ -0,0 +1,527 @@
+//! Lint warnings for H2 Language.
+//!
+//! Lints run on a program that parsed and resolved without errors. They never
+//! fail compilation; their diagnostics are returned with `severity: "warning"`
+//! next to the compiled program. Each lint can be turned off in [`LintConfig`].
+
+use crate::ast::{Agent, Arg, Definition, Expr, FuncDef, NumAtom, NumOp, ParamType, Program};
+use crate::error::{CompileError, ErrorCode, Severity};
+use crate::resolver::{visit_calls, visit_params};
+use crate::token::Span;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// A single lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// W001: Function never called from the agent's main expression
+    UnusedDefinition,
+    /// W002: Parameter never referenced by the body or the functions it calls
+    UnusedParameter,
+    /// W003: Call whose Int argument is a constant ≤0, so it always returns empty
+    EmptyCall,
+    /// W004: Function calls itself without decreasing any Int argument
+    UnboundedRecursion,
+}
+
+impl Lint {
+    /// Warning code reported for this lint.
+    pub fn code(self) -> ErrorCode {
+        match self {
+            Lint::UnusedDefinition => ErrorCode::W001,
+            Lint::UnusedParameter => ErrorCode::W002,
+            Lint::EmptyCall => ErrorCode::W003,
+            Lint::UnboundedRecursion => ErrorCode::W004,
+        }
+    }
+}
+
+/// Which lints are enabled.
+///
+/// All lints are enabled by default. Missing fields deserialize as enabled,
+/// so `{"unused_parameter": false}` turns off just one lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LintConfig {
+    /// W001: unused definitions
+    pub unused_definition: bool,
+    /// W002: unused parameters
+    pub unused_parameter: bool,
+    /// W003: calls that always return empty
+    pub empty_call: bool,
+    /// W004: recursion without a numeric decrease
+    pub unbounded_recursion: bool,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            unused_definition: true,
+            unused_parameter: true,
+            empty_call: true,
+            unbounded_recursion: true,
+        }
+    }
+}
+
+impl LintConfig {
+    /// Configuration with every lint disabled.
+    pub fn none() -> Self {
+        Self {
+            unused_definition: false,
+            unused_parameter: false,
+            empty_call: false,
+            unbounded_recursion: false,
+        }
+    }
+
+    /// Whether `lint` is enabled.
+    pub fn is_enabled(&self, lint: Lint) -> bool {
+        match lint {
+            Lint::UnusedDefinition => self.unused_definition,
+            Lint::UnusedParameter => self.unused_parameter,
+            Lint::EmptyCall => self.empty_call,
+            Lint::UnboundedRecursion => self.unbounded_recursion,
+        }
+    }
+}
+
+/// A lint diagnostic.
+#[derive(Debug, Clone)]
+pub struct LintWarning {
+    /// Lint that fired
+    pub lint: Lint,
+    /// Human-readable description, without the code
+    pub message: String,
+    /// Source range the warning points at
+    pub span: Span,
+}
+
+impl LintWarning {
+    fn new(lint: Lint, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            lint,
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl From<LintWarning> for CompileError {
+    fn from(w: LintWarning) -> Self {
+        Self::coded(w.lint.code(), Severity::Warning, w.message, w.span)
+    }
+}
+
+/// Lint pass over resolved programs.
+pub struct Linter;
+
+impl Linter {
+    /// Lint every agent of a program.
+    pub fn lint_program(program: &Program, config: &LintConfig) -> Vec<LintWarning> {
+        let agents: Vec<&Agent> = program.agents.iter().collect();
+        Self::lint_agents(&program.shared, &agents, config)
+    }
+
+    /// Lint `agents` and the `shared` definitions they may call, returning
+    /// warnings in source order.
+    ///
+    /// Warnings inside shared definitions are reported once, however many
+    /// agents share them.
+    pub fn lint_agents(
+        shared: &[Definition],
+        agents: &[&Agent],
+        config: &LintConfig,
+    ) -> Vec<LintWarning> {
+        let mut warnings: Vec<LintWarning> = agents
+            .iter()
+            .flat_map(|agent| Self::lint_agent(agent, config))
+            .collect();
+        warnings.extend(Self::lint_shared(shared, agents, config));
+        warnings.sort_by_key(|w| (w.span.line, w.span.column));
+        let mut seen = HashSet::new();
+        warnings.retain(|w| seen.insert((w.span.start, w.message.clone())));
+        warnings
+    }
+
+    /// W001 for shared definitions that none of `agents` calls.
+    ///
+    /// [`lint_agent`](Self::lint_agent) leaves shared definitions out of W001,
+    /// since each agent may use only some of them.
+    pub fn lint_shared(
+        shared: &[Definition],
+        agents: &[&Agent],
+        config: &LintConfig,
+    ) -> Vec<LintWarning> {
+        if !config.unused_definition {
+            return Vec::new();
+        }
+        let used: HashSet<char> = agents
+            .iter()
+            .flat_map(|agent| {
+                let defs = definitions(agent);
+                reachable(&[&agent.expression], &defs, |_| true)
+                    .into_iter()
+                    .filter(move |name| defs[name].shared)
+            })
+            .collect();
+        shared
+            .iter()
+            .filter(|def| !used.contains(&def.name()))
+            .map(|def| {
+                LintWarning::new(
+                    Lint::UnusedDefinition,
+                    format!("Shared function '{}' is never called", def.name()),
+                    def.span(),
+                )
+            })
+            .collect()
+    }
+
+    /// Lint one agent, returning warnings in source order.
+    pub fn lint_agent(agent: &Agent, config: &LintConfig) -> Vec<LintWarning> {
+        let defs = definitions(agent);
+
+        let mut warnings = Vec::new();
+        if config.unused_definition {
+            unused_definitions(agent, &defs, &mut warnings);
+        }
+        if config.unused_parameter {
+            unused_parameters(agent, &defs, &mut warnings);
+        }
+        if config.empty_call {
+            empty_calls(agent, &defs, &mut warnings);
+        }
+        if config.unbounded_recursion {
+            unbounded_recursion(agent, &mut warnings);
+        }
+
+        warnings.sort_by_key(|w| (w.span.line, w.span.column));
+        warnings
+    }
+}
+
+/// An agent's definitions by name.
+fn definitions(agent: &Agent) -> HashMap<char, &FuncDef> {
+    agent
+        .definitions
+        .iter()
+        .map(|Definition::Function(def)| (def.name, def))
+        .collect()
+}
+
+/// Functions reachable from `roots` through calls, skipping functions for
+/// which `enter` returns false.
+fn reachable<'a>(
+    roots: &[&'a Expr],
+    defs: &HashMap<char, &'a FuncDef>,
+    enter: impl Fn(&FuncDef) -> bool,
+) -> HashSet<char> {
+    let mut visited = HashSet::new();
+    let mut pending: Vec<&Expr> = roots.to_vec();
+    while let Some(expr) = pending.pop() {
+        visit_calls(expr, &mut |name, _, _| {
+            if let Some(def) = defs.get(&name) {
+                if enter(def) && visited.insert(name) {
+                    pending.push(&def.body);
+                }
+            }
+        });
+    }
+    visited
+}
+
+/// W001: definitions that the main expression never reaches. Shared
+/// definitions are checked across agents by [`Linter::lint_shared`].
+fn unused_definitions(
+    agent: &Agent,
+    defs: &HashMap<char, &FuncDef>,
+    warnings: &mut Vec<LintWarning>,
+) {
+    let used = reachable(&[&agent.expression], defs, |_| true);
+    for Definition::Function(def) in &agent.definitions {
+        if !def.shared && !used.contains(&def.name) {
+            warnings.push(LintWarning::new(
+                Lint::UnusedDefinition,
+                format!("Function '{}' is never called", def.name),
+                def.span,
+            ));
+        }
+    }
+}
+
+/// W002: parameters referenced neither by the body nor, through dynamic
+/// scope, by any function it calls.
+fn unused_parameters(
+    agent: &Agent,
+    defs: &HashMap<char, &FuncDef>,
+    warnings: &mut Vec<LintWarning>,
+) {
+    for Definition::Function(def) in &agent.definitions {
+        for &param in &def.params {
+            let mut used = mentions(&def.body, param);
+            if !used {
+                let callees = reachable(&[&def.body], defs, |f| !f.params.contains(&param));
+                used = callees.iter().any(|name| mentions(&defs[name].body, param));
+            }
+            if !used {
+                warnings.push(LintWarning::new(
+                    Lint::UnusedParameter,
+                    format!(
+                        "Parameter '{}' of function '{}' is never used",
+                        param, def.name
+                    ),
+                    def.span,
+                ));
+            }
+        }
+    }
+}
+
+/// Whether `expr` references `param` directly.
+fn mentions(expr: &Expr, param: char) -> bool {
+    let mut found = false;
+    visit_params(expr, &mut |p, _, _| found |= p == param);
+    found
+}
+
+/// W003: calls with a constant ≤0 Int argument (or `f()` with an Int
+/// parameter), which always return empty.
+fn empty_calls(agent: &Agent, defs: &HashMap<char, &FuncDef>, warnings: &mut Vec<LintWarning>) {
+    let bodies = agent
+        .definitions
+        .iter()
+        .map(|Definition::Function(d)| &d.body);
+    for expr in std::iter::once(&agent.expression).chain(bodies) {
+        visit_calls(expr, &mut |name, args, span| {
+            let Some(def) = defs.get(&name) else {
+                return;
+            };
+            let int_params = def
+                .params
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| def.param_types.get(p) == Some(&ParamType::Int));
+
+            for (index, param) in int_params {
+                let value = match args.get(index) {
+                    Some(arg) => constant_value(arg),
+                    // `f()` binds Int parameters to 0
+                    None if args.is_empty() => Some(0),
+                    None => None,
+                };
+                if let Some(value) = value.filter(|&v| v <= 0) {
+                    warnings.push(LintWarning::new(
+                        Lint::EmptyCall,
+                        format!(
+                            "Call to '{}' always returns nothing: parameter '{}' is {}",
+                            name, param, value
+                        ),
+                        span,
+                    ));
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// Value of an argument that contains no parameters.
+fn constant_value(arg: &Arg) -> Option<i32> {
+    match arg {
+        Arg::Number(n, _) => Some(*n),
+        Arg::NumExpr { first, rest, .. } => {
+            let atom = |a: &NumAtom| match a {
+                NumAtom::Number(n) => Some(*n),
+                NumAtom::Param(_) => None,
+            };
+            rest.iter().try_fold(atom(first)?, |acc, (op, a)| {
+                let v = atom(a)?;
+                Some(match op {
+                    NumOp::Add => acc + v,
+                    NumOp::Sub => acc - v,
+                })
+            })
+        }
+        Arg::Command(_) => None,
+    }
+}
+
+/// W004: self-calls in which no Int parameter decreases, so recursion only
+/// stops at `MAX_STEP` or `MAX_DEPTH`.
+fn unbounded_recursion(agent: &Agent, warnings: &mut Vec<LintWarning>) {
+    for Definition::Function(def) in &agent.definitions {
+        let has_int = def.param_types.values().any(|t| *t == ParamType::Int);
+        visit_calls(&def.body, &mut |name, args, span| {
+            if name != def.name {
+                return;
+            }
+            // `f()` with an Int parameter terminates immediately
+            if args.is_empty() && has_int {
+                return;
+            }
+            let decreases = def.params.iter().zip(args).any(|(param, arg)| {
+                def.param_types.get(param) == Some(&ParamType::Int) && decrement(arg, *param) > 0
+            });
+            if !decreases {
+                warnings.push(LintWarning::new(
+                    Lint::UnboundedRecursion,
+                    format!(
+                        "Function '{}' calls itself without decreasing an Int argument",
+                        def.name
+                    ),
+                    span,
+                ));
+            }
+        });
+    }
+}
+
+/// How much `arg` decreases `param` by, for arguments of the form
+/// `PARAM ± constants`; 0 for anything else.
+fn decrement(arg: &Arg, param: char) -> i32 {
+    let Arg::NumExpr { first, rest, .. } = arg else {
+        return 0;
+    };
+    if *first != NumAtom::Param(param) {
+        return 0;
+    }
+    rest.iter()
+        .try_fold(0, |acc, (op, atom)| match (op, atom) {
+            (NumOp::Sub, NumAtom::Number(n)) => Some(acc + n),
+            (NumOp::Add, NumAtom::Number(n)) => Some(acc - n),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+
+    fn lint_with(source: &str, config: &LintConfig) -> Vec<LintWarning> {
+        let mut program = Parser::new(source).unwrap().parse_program().unwrap();
+        assert!(Resolver::resolve_program(&mut program).is_empty());
+        Linter::lint_program(&program, config)
+    }
+
+    fn lints(source: &str) -> Vec<Lint> {
+        lint_with(source, &LintConfig::default())
+            .iter()
+            .map(|w| w.lint)
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_program() {
+        assert!(lints("0: f(X):XX a(X):sa(X-1) f(a(3))").is_empty());
+    }
+
+    #[test]
+    fn test_unused_definition() {
+        let warnings = lint_with("x:ss y:rr x", &LintConfig::default());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::UnusedDefinition);
+        assert_eq!(warnings[0].span.column, 6);
+        // Reached only through another function
+        assert!(lints("x:y y:rr x").is_empty());
+    }
+
+    #[test]
+    fn test_unused_shared_definition() {
+        // `f` is used by agent 1 only; `g` by nobody
+        let warnings = lint_with("f:ss g:rr\n0: s\n1: f", &LintConfig::default());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "Shared function 'g' is never called");
+        assert_eq!((warnings[0].span.line, warnings[0].span.column), (1, 6));
+        // Reached through an agent's own function
+        assert!(lints("f:ss\n0: g:f g").is_empty());
+        // A shadowed shared definition is not used by that agent
+        assert_eq!(lints("f:ss\n0: f:rr f"), vec![Lint::UnusedDefinition]);
+    }
+
+    #[test]
+    fn test_shared_warnings_reported_once() {
+        let warnings = lint_with("a:sa\n0: a\n1: a\n2: a", &LintConfig::default());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::UnboundedRecursion);
+    }
+
+    #[test]
+    fn test_lint_some_agents() {
+        let mut program = Parser::new("f:ss\n0: f\n1: s")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        assert!(Resolver::resolve_program(&mut program).is_empty());
+        let config = LintConfig::default();
+        // `f` counts as unused when only agent 1 is linted
+        let agents = [&program.agents[1]];
+        let warnings = Linter::lint_agents(&program.shared, &agents, &config);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::UnusedDefinition);
+        let agents: Vec<&Agent> = program.agents.iter().collect();
+        assert!(Linter::lint_agents(&program.shared, &agents, &config).is_empty());
+    }
+
+    #[test]
+    fn test_unused_parameter() {
+        assert_eq!(lints("f(X,Y):X f(s,r)"), vec![Lint::UnusedParameter]);
+        // Used by a callee through dynamic scope
+        assert!(lints("f(X):g(s) g(Y):XY f(r)").is_empty());
+    }
+
+    #[test]
+    fn test_empty_call() {
+        assert_eq!(lints("a(X):sa(X-1) a(0)"), vec![Lint::EmptyCall]);
+        assert_eq!(lints("a(X):sa(X-1) a(2-5)"), vec![Lint::EmptyCall]);
+        assert_eq!(lints("a(X):sa(X-1) sa()"), vec![Lint::EmptyCall]);
+        assert!(lints("a(X):sa(X-1) a(1)").is_empty());
+    }
+
+    #[test]
+    fn test_unbounded_recursion() {
+        let warnings = lint_with("a:sa a", &LintConfig::default());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::UnboundedRecursion);
+        assert_eq!(warnings[0].lint.code(), ErrorCode::W004);
+
+        assert_eq!(lints("a(X):sa(X) a(3)"), vec![Lint::UnboundedRecursion]);
+        assert_eq!(lints("a(X):sa(X+1) a(3)"), vec![Lint::UnboundedRecursion]);
+        assert!(lints("a(X):sa(X-1) a(3)").is_empty());
+    }
+
+    #[test]
+    fn test_config_disables_lints() {
+        let source = "x:ss a:sa a";
+        assert_eq!(lints(source).len(), 2);
+        assert!(lint_with(source, &LintConfig::none()).is_empty());
+
+        let config = LintConfig {
+            unused_definition: false,
+            ..LintConfig::default()
+        };
+        assert!(!config.is_enabled(Lint::UnusedDefinition));
+        let warnings = lint_with(source, &config);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::UnboundedRecursion);
+    }
+
+    #[test]
+    fn test_config_deserializes_missing_fields_as_enabled() {
+        let config: LintConfig = serde_json::from_str(r#"{"empty_call": false}"#).unwrap();
+        assert!(!config.empty_call);
+        assert!(config.unused_definition && config.unused_parameter);
+    }
+
+    #[test]
+    fn test_warning_to_compile_error() {
+        let warning = lint_with("x:ss y:rr x", &LintConfig::default()).remove(0);
+        let error = CompileError::from(warning);
+        assert_eq!(error.code, Some(ErrorCode::W001));
+        assert_eq!(error.severity, Severity::Warning);
+        assert!(error.message.starts_with("[W001] "));
+    }
+}
@@ -1,9 +1,19 @@
 //! Output data structures for JSON serialization.
 
-use crate::error::CompileError;
-use crate::expander::Command;
+use crate::ast::OnEnd;
+use crate::compact::{self, CompactCommand};
+use crate::error::{CompileError, PoseOverflow};
+use crate::expander::{Command, ExpandStats, LimitKind};
 use crate::scheduler::{AgentCommand, TimelineStep};
-use serde::{Deserialize, Serialize};
+use crate::timing::{self, TimedEntry, TimingOptions};
+use crate::world::{Heading, Position};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// Version of the JSON produced for a [`CompileResult`].
+///
+/// Serialized as the top-level `version` field. Version 2 added structured
+/// error fields (`code`, `severity`, `start`/`end`, `expected`/`found`).
+pub const OUTPUT_VERSION: u32 = 2;
 
 /// Command type for JSON output.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -25,12 +35,13 @@ impl From<Command> for CommandType {
             Command::Straight => CommandType::Straight,
             Command::Right => CommandType::RotateRight,
             Command::Left => CommandType::RotateLeft,
+            Command::Wait => CommandType::Wait,
         }
     }
 }
 
 /// toio command with optional parameters.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ToioCommand {
     /// Command type
     #[serde(rename = "type")]
@@ -81,6 +92,16 @@ impl ToioCommand {
             angle: None,
         }
     }
+
+    /// Rotation in degrees, positive clockwise: `angle`, or ±90° for a
+    /// rotation without one. Moves and waits do not turn (0).
+    pub fn turn_degrees(&self) -> i32 {
+        match self.command_type {
+            CommandType::RotateRight => self.angle.unwrap_or(90),
+            CommandType::RotateLeft => self.angle.unwrap_or(-90),
+            CommandType::Straight | CommandType::Wait => 0,
+        }
+    }
 }
 
 impl From<Command> for ToioCommand {
@@ -89,6 +110,69 @@ impl From<Command> for ToioCommand {
             Command::Straight => ToioCommand::straight(),
             Command::Right => ToioCommand::rotate_right(),
             Command::Left => ToioCommand::rotate_left(),
+            Command::Wait => ToioCommand::wait(),
+        }
+    }
+}
+
+/// Limit type for JSON output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitType {
+    /// `MAX_STEP` was reached
+    MaxStep,
+    /// `MAX_DEPTH` was reached
+    MaxDepth,
+    /// `MAX_MEMORY` was reached
+    MaxMemory,
+}
+
+impl From<LimitKind> for LimitType {
+    fn from(kind: LimitKind) -> Self {
+        match kind {
+            LimitKind::MaxStep => LimitType::MaxStep,
+            LimitKind::MaxDepth => LimitType::MaxDepth,
+            LimitKind::MaxMemory => LimitType::MaxMemory,
+        }
+    }
+}
+
+/// A limit hit that truncated an agent's output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitHit {
+    /// Which limit fired
+    pub limit: LimitType,
+    /// Line where it fired (1-based)
+    pub line: usize,
+    /// Column where it fired (1-based)
+    pub column: usize,
+}
+
+/// Expansion metadata for a compiled agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ExpansionInfo {
+    /// Whether the output was cut off by a limit (`ON_LIMIT=TRUNCATE`)
+    pub truncated: bool,
+    /// The limit that cut the output off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_hit: Option<LimitHit>,
+    /// Commands generated, including argument evaluation
+    pub steps_generated: usize,
+    /// Deepest call level reached
+    pub peak_depth: usize,
+}
+
+impl From<ExpandStats> for ExpansionInfo {
+    fn from(stats: ExpandStats) -> Self {
+        Self {
+            truncated: stats.truncated(),
+            limit_hit: stats.limit.map(|(kind, span)| LimitHit {
+                limit: kind.into(),
+                line: span.line,
+                column: span.column,
+            }),
+            steps_generated: stats.steps,
+            peak_depth: stats.peak_depth,
         }
     }
 }
@@ -100,6 +184,27 @@ pub struct CompiledAgent {
     pub id: u32,
     /// List of commands
     pub commands: Vec<ToioCommand>,
+    /// Expansion metadata (truncation, steps generated, peak depth)
+    #[serde(default)]
+    pub expansion: ExpansionInfo,
+    /// `commands` with runs merged (only with compaction enabled)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compacted: Option<Vec<CompactCommand>>,
+}
+
+impl CompiledAgent {
+    /// Poses of this agent: `start`, then the pose after each command.
+    ///
+    /// Returns [`PoseOverflow`] for the first command that leaves the grid.
+    pub fn path(&self, start: AgentPose) -> Result<Vec<AgentPose>, PoseOverflow> {
+        let mut path = Vec::with_capacity(self.commands.len() + 1);
+        path.push(start);
+        for (step, command) in self.commands.iter().enumerate() {
+            let next = path[path.len() - 1].try_apply(command, step)?;
+            path.push(next);
+        }
+        Ok(path)
+    }
 }
 
 /// Timeline entry for a single step.
@@ -109,6 +214,93 @@ pub struct TimelineEntry {
     pub step: usize,
     /// Commands for all agents at this step
     pub agent_commands: Vec<AgentTimelineCommand>,
+    /// Every agent's pose after this step (only with pose output enabled)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poses: Option<Vec<AgentPose>>,
+}
+
+/// Absolute pose of an agent.
+///
+/// Positions are grid cells with `y` growing southwards, as in
+/// [`world`](crate::world); `heading` counts clockwise quarter turns from
+/// north (0 = north, 1 = east, 2 = south, 3 = west).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentPose {
+    /// Agent ID
+    pub agent_id: u32,
+    /// Column
+    pub x: i32,
+    /// Row
+    pub y: i32,
+    /// Heading in 90° units clockwise from north
+    pub heading: u8,
+}
+
+impl AgentPose {
+    /// Origin facing north, the default start pose.
+    pub fn origin(agent_id: u32) -> Self {
+        Self {
+            agent_id,
+            x: 0,
+            y: 0,
+            heading: 0,
+        }
+    }
+
+    /// Pose after executing `command` on an unbounded grid, or `None` if
+    /// the cell it reaches does not fit in an `i32`.
+    pub fn apply(self, command: &ToioCommand) -> Option<Self> {
+        match command.command_type {
+            CommandType::Straight => {
+                let (x, y) = self.reach(command);
+                Some(Self {
+                    x: i32::try_from(x).ok()?,
+                    y: i32::try_from(y).ok()?,
+                    ..self
+                })
+            }
+            CommandType::RotateRight | CommandType::RotateLeft => {
+                // Angles are multiples of 90°
+                let turns = command.turn_degrees().div_euclid(90);
+                Some(Self {
+                    heading: (i32::from(self.heading) + turns).rem_euclid(4) as u8,
+                    ..self
+                })
+            }
+            CommandType::Wait => Some(self),
+        }
+    }
+
+    /// Cell reached by `command`, which cannot overflow an `i64`.
+    fn reach(self, command: &ToioCommand) -> (i64, i64) {
+        if command.command_type != CommandType::Straight {
+            return (i64::from(self.x), i64::from(self.y));
+        }
+        let (dx, dy) = Heading::from_quarter_turns(self.heading).delta();
+        let steps = i64::from(command.steps.unwrap_or(1));
+        (
+            i64::from(self.x) + i64::from(dx) * steps,
+            i64::from(self.y) + i64::from(dy) * steps,
+        )
+    }
+
+    /// Pose after `command`, the `step`th one, or the overflow it causes.
+    fn try_apply(self, command: &ToioCommand, step: usize) -> Result<Self, PoseOverflow> {
+        self.apply(command).ok_or_else(|| {
+            let (x, y) = self.reach(command);
+            PoseOverflow {
+                agent_id: self.agent_id,
+                step,
+                x,
+                y,
+            }
+        })
+    }
+
+    /// Cell the agent occupies.
+    pub fn position(&self) -> Position {
+        Position::new(self.x, self.y)
+    }
 }
 
 /// Agent command in timeline.
@@ -118,6 +310,10 @@ pub struct AgentTimelineCommand {
     pub agent_id: u32,
     /// Command to execute
     pub command: ToioCommand,
+    /// The agent's program (or pass of it) is complete after this step; only
+    /// set by [`CompiledProgram::pad_timeline`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub end: bool,
 }
 
 impl From<&AgentCommand> for AgentTimelineCommand {
@@ -125,6 +321,7 @@ impl From<&AgentCommand> for AgentTimelineCommand {
         Self {
             agent_id: ac.agent_id,
             command: ToioCommand::from(ac.command),
+            end: false,
         }
     }
 }
@@ -134,6 +331,7 @@ impl From<&TimelineStep> for TimelineEntry {
         Self {
             step: ts.step,
             agent_commands: ts.agent_commands.iter().map(|ac| ac.into()).collect(),
+            poses: None,
         }
     }
 }
@@ -147,6 +345,12 @@ pub struct CompiledProgram {
     pub max_steps: usize,
     /// Execution timeline
     pub timeline: Vec<TimelineEntry>,
+    /// Execution timeline in milliseconds (only with a timing model)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timed_timeline: Option<Vec<TimedEntry>>,
+    /// Lint warnings (`severity: "warning"`), in source order
+    #[serde(default)]
+    pub warnings: Vec<CompileError>,
 }
 
 impl CompiledProgram {
@@ -157,6 +361,8 @@ impl CompiledProgram {
             .map(|(id, cmds)| CompiledAgent {
                 id: *id,
                 commands: cmds.iter().map(|c| ToioCommand::from(*c)).collect(),
+                expansion: ExpansionInfo::default(),
+                compacted: None,
             })
             .collect();
 
@@ -167,12 +373,180 @@ impl CompiledProgram {
             agents,
             max_steps,
             timeline,
+            timed_timeline: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Create from already-converted agents, building the timeline directly.
+    ///
+    /// Equivalent to [`from_expanded`](Self::from_expanded) with the
+    /// [`Scheduler`](crate::scheduler::Scheduler) timeline, without keeping
+    /// intermediate `Command` lists around.
+    pub fn from_agents(agents: Vec<CompiledAgent>) -> Self {
+        let max_steps = agents.iter().map(|a| a.commands.len()).max().unwrap_or(0);
+
+        let timeline = (0..max_steps)
+            .map(|step| TimelineEntry {
+                step,
+                agent_commands: agents
+                    .iter()
+                    .filter_map(|agent| {
+                        agent.commands.get(step).map(|cmd| AgentTimelineCommand {
+                            agent_id: agent.id,
+                            command: cmd.clone(),
+                            end: false,
+                        })
+                    })
+                    .collect(),
+                poses: None,
+            })
+            .collect();
+
+        Self {
+            agents,
+            max_steps,
+            timeline,
+            timed_timeline: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// List every agent at every step of the timeline, according to `on_end`.
+    ///
+    /// [`OnEnd::Omit`] leaves the timeline as it is. [`OnEnd::Stop`] adds a
+    /// wait for each agent whose commands have run out; [`OnEnd::Loop`]
+    /// starts them again from the first command (agents without commands
+    /// wait). Each agent's last command, or its first wait if it has none, is
+    /// marked `end`; when looping, the last command of every pass is.
+    pub fn pad_timeline(&mut self, on_end: OnEnd) {
+        if on_end == OnEnd::Omit {
+            return;
+        }
+
+        let agents = &self.agents;
+        for entry in &mut self.timeline {
+            let step = entry.step;
+            entry.agent_commands = agents
+                .iter()
+                .map(|agent| {
+                    let len = agent.commands.len();
+                    let index = match on_end {
+                        OnEnd::Loop if len > 0 => Some(step % len),
+                        _ => (step < len).then_some(step),
+                    };
+                    match index {
+                        Some(i) => AgentTimelineCommand {
+                            agent_id: agent.id,
+                            command: agent.commands[i].clone(),
+                            end: i + 1 == len,
+                        },
+                        None => AgentTimelineCommand {
+                            agent_id: agent.id,
+                            command: ToioCommand::wait(),
+                            end: len == 0 && step == 0,
+                        },
+                    }
+                })
+                .collect();
+        }
+    }
+
+    /// Record every agent's pose after each step in the timeline.
+    ///
+    /// Agents start at their pose in `starts`, or at
+    /// [`AgentPose::origin`] if they have none, and follow their timeline
+    /// commands, so looping agents keep moving. Agents without a command at
+    /// a step keep their pose. Returns [`PoseOverflow`], leaving the timeline
+    /// unchanged, if an agent leaves the grid.
+    pub fn add_poses(&mut self, starts: &[AgentPose]) -> Result<(), PoseOverflow> {
+        let paths = self.timeline_paths(starts)?;
+        for (step, entry) in self.timeline.iter_mut().enumerate() {
+            entry.poses = Some(paths.iter().map(|path| path[step + 1]).collect());
+        }
+        Ok(())
+    }
+
+    /// Poses of every agent, in agent order: the start pose, then the pose
+    /// after each timeline step.
+    ///
+    /// Unlike [`agent_paths`](Self::agent_paths) this follows the timeline,
+    /// so after [`pad_timeline`](Self::pad_timeline) with [`OnEnd::Loop`]
+    /// looping agents keep moving. Every path has `timeline.len() + 1` poses.
+    /// Returns [`PoseOverflow`] for the first step that leaves the grid.
+    pub fn timeline_paths(
+        &self,
+        starts: &[AgentPose],
+    ) -> Result<Vec<Vec<AgentPose>>, PoseOverflow> {
+        let mut paths: Vec<Vec<AgentPose>> = self
+            .agents
+            .iter()
+            .map(|agent| {
+                let mut path = Vec::with_capacity(self.timeline.len() + 1);
+                path.push(start_pose(agent.id, starts));
+                path
+            })
+            .collect();
+
+        for entry in &self.timeline {
+            for path in &mut paths {
+                let pose = path[path.len() - 1];
+                let command = entry
+                    .agent_commands
+                    .iter()
+                    .find(|c| c.agent_id == pose.agent_id);
+                let next = match command {
+                    Some(c) => pose.try_apply(&c.command, entry.step)?,
+                    None => pose,
+                };
+                path.push(next);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Fill in every agent's `compacted` commands (see [`compact::compact`]).
+    ///
+    /// `commands` and the timeline are left as they are; each compacted
+    /// command's `start_step`/`end_step` point back into them.
+    pub fn compact(&mut self) {
+        for agent in &mut self.agents {
+            agent.compacted = Some(compact::compact(&agent.commands));
         }
     }
+
+    /// Lay out every agent's commands in milliseconds as `timed_timeline`
+    /// (see [`timing::timed_timeline`]).
+    pub fn add_timed_timeline(&mut self, options: &TimingOptions) {
+        self.timed_timeline = Some(timing::timed_timeline(&self.agents, options));
+    }
+
+    /// Poses of every agent, in agent order: the start pose, then the pose
+    /// after each of its commands.
+    ///
+    /// Starts are taken from `starts` as in [`add_poses`](Self::add_poses).
+    /// Returns [`PoseOverflow`] for the first agent that leaves the grid.
+    pub fn agent_paths(&self, starts: &[AgentPose]) -> Result<Vec<Vec<AgentPose>>, PoseOverflow> {
+        self.agents
+            .iter()
+            .map(|agent| agent.path(start_pose(agent.id, starts)))
+            .collect()
+    }
+}
+
+/// Start pose of `agent_id` in `starts`, or the origin.
+fn start_pose(agent_id: u32, starts: &[AgentPose]) -> AgentPose {
+    starts
+        .iter()
+        .find(|start| start.agent_id == agent_id)
+        .copied()
+        .unwrap_or_else(|| AgentPose::origin(agent_id))
 }
 
 /// Compile result (success or error).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Serializes with a top-level `version` field ([`OUTPUT_VERSION`]).
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "status")]
 pub enum CompileResult {
     /// Successful compilation
@@ -189,10 +563,47 @@ pub enum CompileResult {
     },
 }
 
+impl Serialize for CompileResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        /// Borrowed view of [`CompileResult`] with the output version added.
+        #[derive(Serialize)]
+        #[serde(tag = "status")]
+        enum Versioned<'a> {
+            #[serde(rename = "success")]
+            Success {
+                version: u32,
+                program: &'a CompiledProgram,
+            },
+            #[serde(rename = "error")]
+            Error {
+                version: u32,
+                errors: &'a [CompileError],
+            },
+        }
+
+        let version = OUTPUT_VERSION;
+        match self {
+            CompileResult::Success { program } => Versioned::Success { version, program },
+            CompileResult::Error { errors } => Versioned::Error { version, errors },
+        }
+        .serialize(serializer)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Agent `id` running `commands`, without expansion metadata.
+    fn agent(id: u32, commands: &[Command]) -> CompiledAgent {
+        CompiledAgent {
+            id,
+            commands: commands.iter().map(|&c| ToioCommand::from(c)).collect(),
+            expansion: ExpansionInfo::default(),
+            compacted: None,
+        }
+    }
+
     #[test]
     fn test_command_type_serialization() {
         let cmd = ToioCommand::straight();
@@ -200,12 +611,247 @@ mod tests {
         assert!(json.contains("straight"));
     }
 
+    #[test]
+    fn test_from_agents_matches_scheduler() {
+        use crate::scheduler::Scheduler;
+
+        let expanded = vec![
+            (
+                0,
+                vec![Command::Straight, Command::Right, Command::Straight],
+            ),
+            (1, vec![Command::Left]),
+        ];
+        let scheduled = CompiledProgram::from_expanded(&expanded, Scheduler::schedule(&expanded));
+        let direct = CompiledProgram::from_agents(
+            expanded.iter().map(|(id, cmds)| agent(*id, cmds)).collect(),
+        );
+
+        assert_eq!(
+            serde_json::to_value(&direct).unwrap(),
+            serde_json::to_value(&scheduled).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_turn_degrees() {
+        assert_eq!(ToioCommand::rotate_right().turn_degrees(), 90);
+        assert_eq!(ToioCommand::rotate_left().turn_degrees(), -90);
+        let unset = ToioCommand {
+            angle: None,
+            ..ToioCommand::rotate_left()
+        };
+        assert_eq!(unset.turn_degrees(), -90);
+        let half = ToioCommand {
+            angle: Some(180),
+            ..ToioCommand::rotate_right()
+        };
+        assert_eq!(half.turn_degrees(), 180);
+        assert_eq!(ToioCommand::straight().turn_degrees(), 0);
+    }
+
+    #[test]
+    fn test_agent_pose_apply() {
+        let pose = AgentPose::origin(0);
+        let pose = pose.apply(&ToioCommand::straight()).unwrap();
+        assert_eq!((pose.x, pose.y, pose.heading), (0, -1, 0));
+        let pose = pose.apply(&ToioCommand::rotate_left()).unwrap();
+        assert_eq!(pose.heading, 3);
+        let pose = pose
+            .apply(&ToioCommand {
+                steps: Some(3),
+                ..ToioCommand::straight()
+            })
+            .unwrap();
+        assert_eq!((pose.x, pose.y), (-3, -1));
+        let pose = pose
+            .apply(&ToioCommand {
+                angle: Some(180),
+                ..ToioCommand::rotate_right()
+            })
+            .unwrap();
+        assert_eq!(pose.heading, 1);
+        assert_eq!(pose.apply(&ToioCommand::wait()), Some(pose));
+    }
+
+    #[test]
+    fn test_pose_overflow() {
+        let far = ToioCommand {
+            steps: Some(u32::MAX),
+            ..ToioCommand::straight()
+        };
+        let east = AgentPose {
+            heading: 1,
+            ..AgentPose::origin(0)
+        };
+        assert_eq!(east.apply(&far), None);
+
+        let mut turner = agent(0, &[Command::Right]);
+        turner.commands.push(far);
+        let mut program = CompiledProgram::from_agents(vec![turner]);
+        let overflow = PoseOverflow {
+            agent_id: 0,
+            step: 1,
+            x: i64::from(u32::MAX),
+            y: 0,
+        };
+        assert_eq!(program.agent_paths(&[]), Err(overflow));
+        assert_eq!(program.timeline_paths(&[]), Err(overflow));
+        assert_eq!(program.add_poses(&[]), Err(overflow));
+        assert!(program.timeline.iter().all(|entry| entry.poses.is_none()));
+    }
+
+    #[test]
+    fn test_add_poses() {
+        let mut program = CompiledProgram::from_agents(vec![
+            agent(0, &[Command::Straight, Command::Right, Command::Straight]),
+            agent(1, &[Command::Left]),
+        ]);
+        let start = AgentPose {
+            agent_id: 1,
+            x: 5,
+            y: 5,
+            heading: 2,
+        };
+        program.add_poses(&[start]).unwrap();
+
+        let poses: Vec<_> = program
+            .timeline
+            .iter()
+            .map(|entry| entry.poses.clone().unwrap())
+            .collect();
+        assert_eq!(poses.len(), 3);
+        assert_eq!((poses[0][0].x, poses[0][0].y), (0, -1));
+        assert_eq!(
+            (poses[2][0].x, poses[2][0].y, poses[2][0].heading),
+            (1, -1, 1)
+        );
+        // Agent 1 finished after step 0 and keeps its pose
+        assert_eq!(poses[0][1].heading, 1);
+        assert_eq!(
+            poses[2][1],
+            AgentPose {
+                heading: 1,
+                ..start
+            }
+        );
+    }
+
+    /// Each step's `(agent_id, command char, end)`, with `w` for waits.
+    fn padded(on_end: OnEnd) -> Vec<Vec<(u32, char, bool)>> {
+        let mut program = CompiledProgram::from_agents(vec![
+            agent(0, &[Command::Straight, Command::Right, Command::Straight]),
+            agent(1, &[Command::Left, Command::Straight]),
+            agent(2, &[]),
+        ]);
+        program.pad_timeline(on_end);
+        program
+            .timeline
+            .iter()
+            .map(|entry| {
+                entry
+                    .agent_commands
+                    .iter()
+                    .map(|ac| {
+                        let c = match ac.command.command_type {
+                            CommandType::Straight => 's',
+                            CommandType::RotateRight => 'r',
+                            CommandType::RotateLeft => 'l',
+                            CommandType::Wait => 'w',
+                        };
+                        (ac.agent_id, c, ac.end)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pad_timeline_omit() {
+        let steps = padded(OnEnd::Omit);
+        assert_eq!(steps[2], vec![(0, 's', false)]);
+    }
+
+    #[test]
+    fn test_pad_timeline_stop() {
+        let steps = padded(OnEnd::Stop);
+        assert_eq!(
+            steps,
+            vec![
+                vec![(0, 's', false), (1, 'l', false), (2, 'w', true)],
+                vec![(0, 'r', false), (1, 's', true), (2, 'w', false)],
+                vec![(0, 's', true), (1, 'w', false), (2, 'w', false)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pad_timeline_loop() {
+        let steps = padded(OnEnd::Loop);
+        assert_eq!(
+            steps[2],
+            vec![(0, 's', true), (1, 'l', false), (2, 'w', false)]
+        );
+
+        // The end marker is only serialized when set
+        let json = serde_json::to_value(AgentTimelineCommand {
+            agent_id: 0,
+            command: ToioCommand::straight(),
+            end: false,
+        })
+        .unwrap();
+        assert!(json.get("end").is_none());
+    }
+
+    #[test]
+    fn test_poses_follow_looping_agents() {
+        let mut program = CompiledProgram::from_agents(vec![
+            agent(0, &[Command::Straight, Command::Straight]),
+            agent(1, &[Command::Straight]),
+        ]);
+        program.pad_timeline(OnEnd::Loop);
+        program.add_poses(&[]).unwrap();
+        let last = program.timeline[1].poses.clone().unwrap();
+        assert_eq!((last[0].y, last[1].y), (-2, -2));
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut program = CompiledProgram::from_agents(vec![
+            agent(0, &[Command::Straight, Command::Straight, Command::Right]),
+            agent(1, &[]),
+        ]);
+        assert!(serde_json::to_value(&program).unwrap()["agents"][0]
+            .get("compacted")
+            .is_none());
+        program.compact();
+        let json = serde_json::to_value(&program).unwrap();
+        assert_eq!(
+            json["agents"][0]["compacted"],
+            serde_json::json!([
+                { "type": "straight", "steps": 2, "start_step": 0, "end_step": 2 },
+                { "type": "rotate_right", "angle": 90, "start_step": 2, "end_step": 3 },
+            ])
+        );
+        assert_eq!(json["agents"][1]["compacted"], serde_json::json!([]));
+        assert_eq!(program.timeline.len(), 3);
+    }
+
+    #[test]
+    fn test_poses_omitted_by_default() {
+        let program = CompiledProgram::from_agents(vec![agent(0, &[Command::Straight])]);
+        let json = serde_json::to_value(&program).unwrap();
+        assert!(json["timeline"][0].get("poses").is_none());
+    }
+
     #[test]
     fn test_compile_result_success() {
         let program = CompiledProgram {
             agents: vec![],
             max_steps: 0,
             timeline: vec![],
+            timed_timeline: None,
+            warnings: vec![],
         };
         let result = CompileResult::Success { program };
         let json = serde_json::to_string(&result).unwrap();
@@ -215,14 +861,25 @@ mod tests {
     #[test]
     fn test_compile_result_error() {
         let result = CompileResult::Error {
-            errors: vec![CompileError {
-                line: 1,
-                column: 5,
-                message: "Test error".to_string(),
-            }],
+            errors: vec![CompileError::new("Test error", 1, 5)],
         };
         let json = serde_json::to_string(&result).unwrap();
         assert!(json.contains("error"));
         assert!(json.contains("Test error"));
     }
+
+    #[test]
+    fn test_compile_result_version_round_trip() {
+        let result = CompileResult::Error {
+            errors: vec![CompileError::new("Test error", 1, 5)],
+        };
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["version"], OUTPUT_VERSION);
+        assert_eq!(json["status"], "error");
+        assert!(json["errors"][0]["code"].is_null());
+        assert_eq!(json["errors"][0]["severity"], "error");
+
+        let parsed: CompileResult = serde_json::from_value(json).unwrap();
+        assert!(matches!(parsed, CompileResult::Error { errors } if errors[0].column == 5));
+    }
 }
@@ -4,9 +4,9 @@
 
 use crate::ast::{
     Agent, Arg, Definition, Directive, DirectiveValue, Expr, FuncDef, LimitConfig, NumAtom, NumOp,
-    OnLimitBehavior, ParamType, Primitive, Program,
+    OnCollision, OnEnd, OnLimitBehavior, ParamType, Primitive, Priority, Program, ScheduleConfig,
 };
-use crate::error::ParseError;
+use crate::error::{LexerError, ParseError};
 use crate::lexer::Lexer;
 use crate::token::{Span, Token, TokenKind};
 use std::collections::HashMap;
@@ -24,23 +24,42 @@ pub struct Parser {
     buffer: VecDeque<Token>,
     /// Last consumed token's span (for error reporting)
     last_span: Span,
+    /// Number of tokens consumed so far (to guarantee progress during recovery)
+    position: usize,
+    /// Errors recorded while recovering
+    errors: Vec<ParseError>,
 }
 
 impl Parser {
     /// Create a new parser for the given input.
     pub fn new(input: &str) -> Result<Self, ParseError> {
         let mut lexer = Lexer::new(input);
-        let tokens = lexer
-            .tokenize()
-            .map_err(|e| ParseError::new(e.message, Span::new(0, 0, e.line, e.column)))?;
+        let tokens = lexer.tokenize().map_err(ParseError::from)?;
 
+        Ok(Self::from_tokens(tokens))
+    }
+
+    /// Create a parser that skips over lexer errors instead of failing.
+    ///
+    /// Returns the parser together with every lexer error found. Use with
+    /// [`parse_program_recovering`](Self::parse_program_recovering) to collect
+    /// all diagnostics in one pass.
+    pub fn new_recovering(input: &str) -> (Self, Vec<LexerError>) {
+        let mut lexer = Lexer::new(input);
+        let (tokens, errors) = lexer.tokenize_recovering();
+        (Self::from_tokens(tokens), errors)
+    }
+
+    fn from_tokens(tokens: Vec<Token>) -> Self {
         let default_span = Span::new(0, 0, 1, 1);
 
-        Ok(Self {
+        Self {
             tokens: tokens.into_iter().peekable(),
             buffer: VecDeque::new(),
             last_span: default_span,
-        })
+            position: 0,
+            errors: Vec::new(),
+        }
     }
 
     /// Fill buffer up to n elements if possible.
@@ -80,6 +99,8 @@ impl Parser {
 
     /// Advance to next token, returning the consumed token.
     fn advance(&mut self) -> Option<Token> {
+        self.position += 1;
+
         // First try to take from buffer (O(1) with VecDeque)
         if !self.buffer.is_empty() {
             let token = self.buffer.pop_front().unwrap();
@@ -129,70 +150,159 @@ impl Parser {
 
     /// Parse the entire program.
     ///
-    /// Structure: `directives* (agent_block | single_agent_block)`
+    /// Structure: `directives* (shared_block? agent_block | single_agent_block)`
     ///
     /// Supports two modes:
     /// 1. **With agent prefix**: `0: srl` - traditional multi-agent syntax
     /// 2. **Without agent prefix**: `srl` - single agent mode (defaults to agent 0)
+    ///
+    /// Unprefixed lines followed by agent lines form a shared block of
+    /// definitions that every agent can call (E017 for expressions in it).
+    ///
+    /// Returns the first error found; see
+    /// [`parse_program_recovering`](Self::parse_program_recovering) to collect all of them.
     pub fn parse_program(&mut self) -> Result<Program, ParseError> {
+        let (program, errors) = self.parse_program_recovering();
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(program),
+        }
+    }
+
+    /// Parse the entire program, recovering from errors.
+    ///
+    /// After an error the parser resynchronizes at the next newline, agent
+    /// line or definition, so a single pass reports every syntax error. The
+    /// returned program contains whatever could be parsed; it is only
+    /// meaningful when no errors were reported for the agent in question.
+    pub fn parse_program_recovering(&mut self) -> (Program, Vec<ParseError>) {
         // Skip leading newlines
         while self.check(&TokenKind::Newline) {
             self.advance();
         }
 
         // Parse directives first
-        let directives = self.parse_directives()?;
-        let limits = Self::build_limit_config(&directives)?;
+        let directives = self.parse_directives();
+        let limits = Self::build_limit_config(&directives, &mut self.errors);
+        let schedule = Self::build_schedule_config(&directives, &mut self.errors);
 
         // Skip newlines after directives
         while self.check(&TokenKind::Newline) {
             self.advance();
         }
 
-        // Check if we're at EOF (empty program or directives only)
-        if self.check(&TokenKind::Eof) {
-            return Ok(Program {
-                directives,
-                limits,
-                agents: Vec::new(),
-            });
-        }
-
         // Parse agents
         let mut agents = Vec::new();
+        let mut shared = Vec::new();
 
         // Determine mode: check if first token is AgentId
         let has_agent_prefix = matches!(self.current_kind(), TokenKind::AgentId(_));
 
-        if has_agent_prefix {
+        if self.check(&TokenKind::Eof) {
+            // Empty program or directives only
+        } else if has_agent_prefix {
             // Traditional multi-agent mode
-            while !self.check(&TokenKind::Eof) {
-                let agent = self.parse_agent_line_with_prefix()?;
-                agents.push(agent);
-
-                // Skip newlines between agents
-                while self.check(&TokenKind::Newline) {
-                    self.advance();
-                }
-            }
+            self.parse_agent_lines(&mut agents);
         } else {
             // Single agent mode (no prefix)
             // All lines are treated as the same agent (agent 0)
-            let agent = self.parse_agent_line_without_prefix_multiline()?;
-            agents.push(agent);
+            let agent = self.parse_agent_line_without_prefix_multiline();
+            if matches!(self.current_kind(), TokenKind::AgentId(_)) {
+                // The lines were a shared block: definitions only, visible to
+                // every agent that follows
+                if !agent.expression.is_empty() {
+                    self.errors
+                        .push(ParseError::shared_expression(agent.expression.span()));
+                }
+                shared = agent.definitions;
+                self.parse_agent_lines(&mut agents);
+                Self::share_definitions(&mut shared, &mut agents);
+            } else {
+                agents.push(agent);
+            }
         }
 
-        Ok(Program {
+        let program = Program {
             directives,
             limits,
+            schedule,
+            shared,
             agents,
-        })
+        };
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|e| (e.line, e.column));
+        (program, errors)
+    }
+
+    /// Parse agent lines until EOF.
+    fn parse_agent_lines(&mut self, agents: &mut Vec<Agent>) {
+        while !self.check(&TokenKind::Eof) {
+            let agent = self.parse_agent_line_with_prefix();
+
+            // Each agent ID may appear only once (E012)
+            match agents.iter().find(|a: &&Agent| a.id == agent.id) {
+                Some(first) => self.errors.push(ParseError::duplicate_agent(
+                    agent.id, agent.span, first.span,
+                )),
+                None => agents.push(agent),
+            }
+
+            // Skip newlines between agents
+            while self.check(&TokenKind::Newline) {
+                self.advance();
+            }
+        }
+    }
+
+    /// Mark `shared` definitions as shared and give every agent a copy of
+    /// those it does not define itself.
+    ///
+    /// An agent's own definition shadows the shared one of the same name for
+    /// the whole agent, including calls from other shared bodies.
+    fn share_definitions(shared: &mut [Definition], agents: &mut [Agent]) {
+        for Definition::Function(def) in shared.iter_mut() {
+            def.shared = true;
+        }
+        for agent in agents {
+            let own = std::mem::take(&mut agent.definitions);
+            let inherited = shared
+                .iter()
+                .filter(|def| !own.iter().any(|d| d.name() == def.name()));
+            agent.definitions = inherited.cloned().collect();
+            agent.definitions.extend(own);
+        }
+    }
+
+    /// Skip tokens after an error until parsing can resume: at a newline, the
+    /// next agent line, or the start of a definition.
+    ///
+    /// Always consumes at least one token if none were consumed since `start`.
+    fn synchronize(&mut self, start: usize) {
+        if self.position == start {
+            self.advance();
+        }
+
+        loop {
+            match self.current_kind() {
+                TokenKind::Newline | TokenKind::Eof | TokenKind::AgentId(_) => break,
+                TokenKind::Ident(_)
+                    if matches!(self.peek_nth(1).map(|t| &t.kind), Some(TokenKind::Colon))
+                        || self.is_function_definition() =>
+                {
+                    break
+                }
+                _ => {
+                    self.advance();
+                }
+            }
+        }
     }
 
     /// Parse directives at the beginning of the program.
     ///
-    /// Directives are lines like `MAX_STEP=1000` or `ON_LIMIT=ERROR`
-    fn parse_directives(&mut self) -> Result<Vec<Directive>, ParseError> {
+    /// Directives are lines like `MAX_STEP=1000` or `ON_LIMIT=ERROR`.
+    /// A malformed directive is reported and the rest of its line skipped.
+    fn parse_directives(&mut self) -> Vec<Directive> {
         let mut directives = Vec::new();
 
         loop {
@@ -202,41 +312,57 @@ impl Parser {
             }
 
             // Check if this is a directive
-            if let TokenKind::Directive(name) = self.current_kind() {
-                let start_span = self.current_span();
-                self.advance();
+            if !matches!(self.current_kind(), TokenKind::Directive(_)) {
+                // Not a directive, stop parsing directives
+                break;
+            }
 
-                // Expect '='
-                self.expect(&TokenKind::Equals)?;
+            match self.parse_directive() {
+                Ok(directive) => directives.push(directive),
+                Err(e) => {
+                    self.errors.push(e);
+                    while !self.is_at_end_of_line() {
+                        self.advance();
+                    }
+                }
+            }
+        }
 
-                // Parse directive value (number or directive value like ERROR/TRUNCATE)
-                let value = self.parse_directive_value()?;
+        directives
+    }
 
-                let end_span = self.current_span();
-                let span = Span::new(
-                    start_span.start,
-                    end_span.end,
-                    start_span.line,
-                    start_span.column,
-                );
+    /// Parse a single directive line: `NAME '=' value`
+    fn parse_directive(&mut self) -> Result<Directive, ParseError> {
+        let start_span = self.current_span();
+        let TokenKind::Directive(name) = self.current_kind() else {
+            unreachable!("parse_directive called on a non-directive token");
+        };
+        self.advance();
 
-                directives.push(Directive { name, value, span });
+        // Expect '='
+        self.expect(&TokenKind::Equals)?;
 
-                // Expect newline or EOF after directive
-                if !self.check(&TokenKind::Newline) && !self.check(&TokenKind::Eof) {
-                    return Err(ParseError::unexpected_token(
-                        "newline or end of input",
-                        self.current_kind().description(),
-                        self.current_span(),
-                    ));
-                }
-            } else {
-                // Not a directive, stop parsing directives
-                break;
-            }
+        // Parse directive value (number or directive value like ERROR/TRUNCATE)
+        let value = self.parse_directive_value()?;
+
+        let end_span = self.current_span();
+        let span = Span::new(
+            start_span.start,
+            end_span.end,
+            start_span.line,
+            start_span.column,
+        );
+
+        // Expect newline or EOF after directive
+        if !self.check(&TokenKind::Newline) && !self.check(&TokenKind::Eof) {
+            return Err(ParseError::unexpected_token(
+                "newline or end of input",
+                self.current_kind().description(),
+                self.current_span(),
+            ));
         }
 
-        Ok(directives)
+        Ok(Directive { name, value, span })
     }
 
     /// Parse directive value (number or ERROR/TRUNCATE)
@@ -262,42 +388,50 @@ impl Parser {
     ///
     /// When no directives are specified, uses HOJ-compatible defaults (Truncate).
     /// When directives are specified but ON_LIMIT is not, uses spec default (Error).
-    fn build_limit_config(directives: &[Directive]) -> Result<LimitConfig, ParseError> {
+    /// Invalid directives are reported to `errors` and otherwise ignored.
+    fn build_limit_config(directives: &[Directive], errors: &mut Vec<ParseError>) -> LimitConfig {
         let mut config = LimitConfig::default();
 
         // Track if ON_LIMIT was explicitly set
         let mut on_limit_explicit = false;
+        let mut limit_directives = false;
 
         for directive in directives {
+            limit_directives |= !matches!(
+                directive.name.as_str(),
+                "ON_COLLISION" | "PRIORITY" | "ON_END" | "EXTENSION"
+            );
             match directive.name.as_str() {
                 "MAX_STEP" => {
                     if let DirectiveValue::Number(n) = &directive.value {
                         if *n < 1 || *n > 10_000_000 {
-                            return Err(ParseError::new(
-                                format!("MAX_STEP value {} out of range (1..10000000) (E009)", n),
+                            errors.push(ParseError::invalid_directive(
+                                format!("MAX_STEP value {} out of range (1..10000000)", n),
                                 directive.span,
                             ));
+                            continue;
                         }
                         config.max_step = *n as usize;
                     } else {
-                        return Err(ParseError::new(
-                            "MAX_STEP requires a numeric value (E009)",
+                        errors.push(ParseError::invalid_directive(
+                            "MAX_STEP requires a numeric value",
                             directive.span,
                         ));
                     }
                 }
                 "MAX_DEPTH" => {
                     if let DirectiveValue::Number(n) = &directive.value {
-                        if *n < 1 || *n > 10_000 {
-                            return Err(ParseError::new(
-                                format!("MAX_DEPTH value {} out of range (1..10000) (E009)", n),
+                        if *n < 1 || *n > 10_000_000 {
+                            errors.push(ParseError::invalid_directive(
+                                format!("MAX_DEPTH value {} out of range (1..10000000)", n),
                                 directive.span,
                             ));
+                            continue;
                         }
                         config.max_depth = *n as usize;
                     } else {
-                        return Err(ParseError::new(
-                            "MAX_DEPTH requires a numeric value (E009)",
+                        errors.push(ParseError::invalid_directive(
+                            "MAX_DEPTH requires a numeric value",
                             directive.span,
                         ));
                     }
@@ -305,15 +439,16 @@ impl Parser {
                 "MAX_MEMORY" => {
                     if let DirectiveValue::Number(n) = &directive.value {
                         if *n < 1 || *n > 10_000_000 {
-                            return Err(ParseError::new(
-                                format!("MAX_MEMORY value {} out of range (1..10000000) (E009)", n),
+                            errors.push(ParseError::invalid_directive(
+                                format!("MAX_MEMORY value {} out of range (1..10000000)", n),
                                 directive.span,
                             ));
+                            continue;
                         }
                         config.max_memory = *n as usize;
                     } else {
-                        return Err(ParseError::new(
-                            "MAX_MEMORY requires a numeric value (E009)",
+                        errors.push(ParseError::invalid_directive(
+                            "MAX_MEMORY requires a numeric value",
                             directive.span,
                         ));
                     }
@@ -325,9 +460,9 @@ impl Parser {
                             "ERROR" => config.on_limit = OnLimitBehavior::Error,
                             "TRUNCATE" => config.on_limit = OnLimitBehavior::Truncate,
                             _ => {
-                                return Err(ParseError::new(
+                                errors.push(ParseError::invalid_directive(
                                     format!(
-                                        "ON_LIMIT value '{}' invalid, expected ERROR or TRUNCATE (E009)",
+                                        "ON_LIMIT value '{}' invalid, expected ERROR or TRUNCATE",
                                         s
                                     ),
                                     directive.span,
@@ -335,58 +470,116 @@ impl Parser {
                             }
                         }
                     } else {
-                        return Err(ParseError::new(
-                            "ON_LIMIT requires ERROR or TRUNCATE (E009)",
+                        errors.push(ParseError::invalid_directive(
+                            "ON_LIMIT requires ERROR or TRUNCATE",
+                            directive.span,
+                        ));
+                    }
+                }
+                // Handled by build_schedule_config
+                "ON_COLLISION" | "PRIORITY" | "ON_END" => {}
+                // Enables the `w` primitive in the lexer
+                "EXTENSION" => {
+                    if !matches!(&directive.value, DirectiveValue::String(s) if s == "WAIT") {
+                        errors.push(ParseError::invalid_directive(
+                            "EXTENSION requires WAIT",
                             directive.span,
                         ));
                     }
                 }
                 _ => {
-                    return Err(ParseError::new(
-                        format!("Unknown directive '{}' (E009)", directive.name),
+                    errors.push(ParseError::invalid_directive(
+                        format!("Unknown directive '{}'", directive.name),
                         directive.span,
                     ));
                 }
             }
         }
 
-        // If directives were specified but ON_LIMIT was not explicitly set,
-        // use spec default (Error) instead of HOJ default (Truncate)
-        if !directives.is_empty() && !on_limit_explicit {
+        // If limit directives were specified but ON_LIMIT was not explicitly
+        // set, use spec default (Error) instead of HOJ default (Truncate)
+        if limit_directives && !on_limit_explicit {
             config.on_limit = OnLimitBehavior::Error;
         }
 
-        Ok(config)
+        config
+    }
+
+    /// Build scheduling configuration from directives.
+    ///
+    /// Invalid values are reported as E009 and leave the default in place.
+    fn build_schedule_config(
+        directives: &[Directive],
+        errors: &mut Vec<ParseError>,
+    ) -> ScheduleConfig {
+        let mut config = ScheduleConfig::default();
+
+        for directive in directives {
+            let value = match &directive.value {
+                DirectiveValue::String(s) => s.as_str(),
+                DirectiveValue::Number(_) => "",
+            };
+            match directive.name.as_str() {
+                "ON_COLLISION" => match value {
+                    "WARN" => config.on_collision = OnCollision::Warn,
+                    "ERROR" => config.on_collision = OnCollision::Error,
+                    "WAIT" => config.on_collision = OnCollision::Wait,
+                    _ => errors.push(ParseError::invalid_directive(
+                        "ON_COLLISION requires WARN, ERROR or WAIT",
+                        directive.span,
+                    )),
+                },
+                "PRIORITY" => match value {
+                    "LOW_ID" => config.priority = Priority::LowId,
+                    "HIGH_ID" => config.priority = Priority::HighId,
+                    "LONGEST" => config.priority = Priority::Longest,
+                    _ => errors.push(ParseError::invalid_directive(
+                        "PRIORITY requires LOW_ID, HIGH_ID or LONGEST",
+                        directive.span,
+                    )),
+                },
+                "ON_END" => match value {
+                    "OMIT" => config.on_end = OnEnd::Omit,
+                    "STOP" => config.on_end = OnEnd::Stop,
+                    "LOOP" => config.on_end = OnEnd::Loop,
+                    _ => errors.push(ParseError::invalid_directive(
+                        "ON_END requires OMIT, STOP or LOOP",
+                        directive.span,
+                    )),
+                },
+                _ => {}
+            }
+        }
+
+        config
     }
 
     /// Parse agent with prefix: `agent_id ':' statement_list`
     ///
     /// Parses until EOF or the next agent ID is encountered.
     /// Supports multi-line code for a single agent.
-    fn parse_agent_line_with_prefix(&mut self) -> Result<Agent, ParseError> {
+    fn parse_agent_line_with_prefix(&mut self) -> Agent {
         let start_span = self.current_span();
 
-        // Parse agent ID
-        let id = match self.current_kind() {
-            TokenKind::AgentId(n) => n,
-            _ => {
-                return Err(ParseError::unexpected_token(
-                    "agent ID",
-                    self.current_kind().description(),
-                    self.current_span(),
-                ));
-            }
+        // Parse agent ID (callers only get here at an AgentId token)
+        let TokenKind::AgentId(id) = self.current_kind() else {
+            unreachable!("agent line must start with an agent ID");
         };
         self.advance();
 
-        // Expect ':'
-        self.expect(&TokenKind::Colon)?;
+        // Expect ':' (the lexer allows spaces before it)
+        self.skip_space();
+        let start = self.position;
+        if let Err(e) = self.expect(&TokenKind::Colon) {
+            self.errors.push(e);
+            self.synchronize(start);
+        }
 
         // Skip space after colon
         self.skip_space();
 
         // Parse statement list across multiple lines until next AgentId or EOF
-        let (definitions, expression) = self.parse_statement_list_multiline()?;
+        let (definitions, expression) = self.parse_statement_list_multiline();
 
         let end_span = self.current_span();
         let span = Span::new(
@@ -396,26 +589,26 @@ impl Parser {
             start_span.column,
         );
 
-        Ok(Agent {
+        Agent {
             id,
             definitions,
             expression,
             span,
-        })
+        }
     }
 
     /// Parse multiple lines without prefix as a single agent (agent 0).
     ///
     /// This is used for single-agent programs where all lines belong to agent 0.
     /// Continues parsing until EOF, treating newlines as whitespace separators.
-    fn parse_agent_line_without_prefix_multiline(&mut self) -> Result<Agent, ParseError> {
+    fn parse_agent_line_without_prefix_multiline(&mut self) -> Agent {
         let start_span = self.current_span();
 
         // Default to agent 0
         let id = 0;
 
         // Parse statement list across multiple lines
-        let (definitions, expression) = self.parse_statement_list_multiline()?;
+        let (definitions, expression) = self.parse_statement_list_multiline();
 
         let end_span = self.current_span();
         let span = Span::new(
@@ -425,18 +618,19 @@ impl Parser {
             start_span.column,
         );
 
-        Ok(Agent {
+        Agent {
             id,
             definitions,
             expression,
             span,
-        })
+        }
     }
 
     /// Parse statement list across multiple lines until EOF or next AgentId.
     ///
     /// This is used for single-agent programs or multi-line agent definitions.
-    fn parse_statement_list_multiline(&mut self) -> Result<(Vec<Definition>, Expr), ParseError> {
+    /// Errors are recorded and parsing resumes at the next synchronization point.
+    fn parse_statement_list_multiline(&mut self) -> (Vec<Definition>, Expr) {
         let mut definitions = Vec::new();
         let mut expression_terms = Vec::new();
 
@@ -457,12 +651,31 @@ impl Parser {
             }
 
             // Try to parse a definition (lookahead required)
-            if let Some(def) = self.try_parse_definition()? {
-                definitions.push(def);
-            } else {
+            let start = self.position;
+            let parsed = match self.try_parse_definition() {
+                Ok(Some(def)) => {
+                    // Same-name collision is forbidden (E011)
+                    let Definition::Function(new) = &def;
+                    let first = definitions
+                        .iter()
+                        .map(|Definition::Function(f)| f)
+                        .find(|f| f.name == new.name);
+                    match first {
+                        Some(first) => self.errors.push(ParseError::duplicate_function(
+                            new.name, new.span, first.span,
+                        )),
+                        None => definitions.push(def),
+                    }
+                    Ok(())
+                }
                 // Parse expression term
-                let term = self.parse_term()?;
-                expression_terms.push(term);
+                Ok(None) => self.parse_term().map(|term| expression_terms.push(term)),
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = parsed {
+                self.errors.push(e);
+                self.synchronize(start);
             }
         }
 
@@ -474,7 +687,7 @@ impl Parser {
             Expr::Sequence(expression_terms)
         };
 
-        Ok((definitions, expression))
+        (definitions, expression)
     }
 
     /// Try to parse a definition (macro or function).
@@ -581,6 +794,7 @@ impl Parser {
             param_types: HashMap::new(),
             body,
             span,
+            shared: false,
         })
     }
 
@@ -647,6 +861,7 @@ impl Parser {
             param_types,
             body,
             span,
+            shared: false,
         })
     }
 
@@ -747,13 +962,7 @@ impl Parser {
                 Some(existing) if *existing == new_type => Ok(()),
                 Some(_) => {
                     // Type conflict: E010
-                    Err(ParseError::new(
-                        format!(
-                            "[E010] Type conflict for parameter '{}': used as both CmdSeq and Int",
-                            param
-                        ),
-                        span,
-                    ))
+                    Err(ParseError::type_conflict(param, span))
                 }
             }
         } else {
@@ -815,6 +1024,10 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Primitive(Primitive::Left, span))
             }
+            TokenKind::Wait => {
+                self.advance();
+                Ok(Expr::Primitive(Primitive::Wait, span))
+            }
             TokenKind::Ident(name) => {
                 // Check if this is a function call with parentheses
                 if matches!(self.peek_nth(1).map(|t| &t.kind), Some(TokenKind::LParen)) {
@@ -1071,6 +1284,7 @@ impl Parser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ErrorCode;
 
     #[test]
     fn test_simple_program() {
@@ -1106,12 +1320,9 @@ mod tests {
         let program = parser.parse_program().unwrap();
 
         assert_eq!(program.agents[0].definitions.len(), 1);
-        if let Definition::Function(f) = &program.agents[0].definitions[0] {
-            assert_eq!(f.name, 'f');
-            assert_eq!(f.params, vec!['X']);
-        } else {
-            panic!("Expected function definition");
-        }
+        let Definition::Function(f) = &program.agents[0].definitions[0];
+        assert_eq!(f.name, 'f');
+        assert_eq!(f.params, vec!['X']);
     }
 
     #[test]
@@ -1449,4 +1660,186 @@ mod tests {
 
         assert_eq!(program.limits.on_limit, OnLimitBehavior::Truncate);
     }
+
+    #[test]
+    fn test_on_collision_directive() {
+        let program = Parser::new("ON_COLLISION=ERROR\n0: srl")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        assert_eq!(program.schedule.on_collision, OnCollision::Error);
+        // Not a limit directive: ON_LIMIT keeps the HOJ default
+        assert_eq!(program.limits.on_limit, OnLimitBehavior::Truncate);
+
+        let program = Parser::new("0: srl").unwrap().parse_program().unwrap();
+        assert_eq!(program.schedule.on_collision, OnCollision::Warn);
+
+        let (_, errors) = recover("ON_COLLISION=TRUNCATE\n0: srl");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::E009));
+    }
+
+    #[test]
+    fn test_priority_directive() {
+        let program = Parser::new("ON_COLLISION=WAIT\nPRIORITY=LONGEST\n0: srl")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        assert_eq!(program.schedule.on_collision, OnCollision::Wait);
+        assert_eq!(program.schedule.priority, Priority::Longest);
+        assert_eq!(program.limits.on_limit, OnLimitBehavior::Truncate);
+
+        let (program, errors) = recover("PRIORITY=WAIT\n0: srl");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::E009));
+        assert_eq!(program.schedule.priority, Priority::LowId);
+    }
+
+    #[test]
+    fn test_extension_directive() {
+        let program = Parser::new("EXTENSION=WAIT\n0: f:ww wf")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        assert!(matches!(
+            program.agents[0].expression,
+            Expr::Sequence(ref terms) if matches!(terms[0], Expr::Primitive(Primitive::Wait, _))
+        ));
+        assert_eq!(program.limits.on_limit, OnLimitBehavior::Truncate);
+
+        // Without the extension `w` is an ordinary function name
+        assert!(Parser::new("0: w:s w").unwrap().parse_program().is_ok());
+
+        let (_, errors) = recover("EXTENSION=LOOP\n0: s");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::E009));
+    }
+
+    #[test]
+    fn test_on_end_directive() {
+        let program = Parser::new("ON_END=LOOP\n0: srl")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        assert_eq!(program.schedule.on_end, OnEnd::Loop);
+        assert_eq!(program.limits.on_limit, OnLimitBehavior::Truncate);
+
+        let (program, errors) = recover("ON_END=WAIT\n0: srl");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::E009));
+        assert_eq!(program.schedule.on_end, OnEnd::Omit);
+    }
+
+    fn recover(input: &str) -> (Program, Vec<ParseError>) {
+        let (mut parser, lexer_errors) = Parser::new_recovering(input);
+        assert!(lexer_errors.is_empty());
+        parser.parse_program_recovering()
+    }
+
+    #[test]
+    fn test_recovery_reports_error_per_agent() {
+        let (program, errors) = recover("0: s)r\n1: x(\n2: srl");
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].column), (1, 5));
+        assert_eq!(errors[1].line, 2);
+        assert_eq!(program.agents.len(), 3);
+        assert_eq!(program.agents[2].id, 2);
+    }
+
+    #[test]
+    fn test_recovery_resumes_at_definition() {
+        let (program, errors) = recover("0: f(X):X) g:s g");
+        assert_eq!(errors.len(), 1);
+        let agent = &program.agents[0];
+        assert!(agent
+            .definitions
+            .iter()
+            .any(|Definition::Function(f)| f.name == 'g'));
+    }
+
+    #[test]
+    fn test_recovery_reports_directive_and_code_errors() {
+        let (program, errors) = recover("MAX_STEP=0\nON_LIMIT=ERROR x\ns)");
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(program.agents.len(), 1);
+    }
+
+    #[test]
+    fn test_recovery_missing_agent_colon() {
+        let (program, errors) = recover("0: s\n1:: s\n2: r");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(program.agents.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_program_returns_first_error() {
+        let mut parser = Parser::new("0: s)\n1: r)").unwrap();
+        let err = parser.parse_program().unwrap_err();
+        assert_eq!(err.line, 1);
+    }
+
+    #[test]
+    fn test_duplicate_function_reported_at_second() {
+        let (program, errors) = recover("0: a:s\nf(X):X a(Y):Y a");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::E011));
+        assert_eq!((errors[0].line, errors[0].column), (2, 8));
+        let first = errors[0].related.as_deref().unwrap();
+        assert_eq!((first.line, first.column), (1, 4));
+        // The first definition is kept
+        assert_eq!(program.agents[0].definitions.len(), 2);
+    }
+
+    #[test]
+    fn test_same_function_name_in_different_agents() {
+        let (_, errors) = recover("0: a:s a\n1: a:r a");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_shared_definitions() {
+        let (program, errors) = recover("f(X):XX g:ss\n0: f(g)\n1: g:rr f(g)");
+        assert!(errors.is_empty());
+        assert_eq!(program.shared.len(), 2);
+        assert_eq!(program.agents.len(), 2);
+
+        // Shared copies come first, in source order
+        let names = |i: usize| -> Vec<(char, bool)> {
+            program.agents[i]
+                .definitions
+                .iter()
+                .map(|Definition::Function(f)| (f.name, f.shared))
+                .collect()
+        };
+        assert_eq!(names(0), vec![('f', true), ('g', true)]);
+        // Agent 1's own `g` shadows the shared one
+        assert_eq!(names(1), vec![('f', true), ('g', false)]);
+    }
+
+    #[test]
+    fn test_shared_block_expression() {
+        let (_, errors) = recover("f:ss ff\n0: f");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::E017));
+        assert_eq!((errors[0].line, errors[0].column), (1, 6));
+
+        // Duplicates within the shared block are E011
+        let (_, errors) = recover("f:ss f:rr\n0: f");
+        assert_eq!(errors[0].code, Some(ErrorCode::E011));
+    }
+
+    #[test]
+    fn test_duplicate_agent_id() {
+        let (program, errors) = recover("0: s\n1: r\n0: l");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::E012));
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[0].related.as_deref().unwrap().line, 1);
+        assert_eq!(program.agents.len(), 2);
+    }
 }
@@ -0,0 +1,556 @@
+//! Pictures of compiled programs.
+//!
+//! Agents move on an unbounded grid as in [`AgentPose::apply`]; each agent's
+//! path is the list of cells it occupies, from its start pose to the end of
+//! its commands. [`render_svg`] draws the paths as an image, [`render_text`]
+//! as a character grid for terminals and snapshot tests.
+
+use crate::error::RenderError;
+use crate::output::{AgentPose, CompiledProgram};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Stroke colors, cycled by agent order.
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#17becf",
+];
+
+/// Options for [`render_svg`].
+///
+/// Missing fields deserialize to their defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SvgOptions {
+    /// Size of a grid cell in pixels
+    pub cell_size: u32,
+    /// Draw grid lines
+    pub grid: bool,
+    /// Label each point of a path with its step number
+    pub step_numbers: bool,
+    /// Start poses; other agents start at the origin facing north
+    pub start_poses: Vec<AgentPose>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: 20,
+            grid: true,
+            step_numbers: false,
+            start_poses: Vec::new(),
+        }
+    }
+}
+
+/// Smallest `(min_x, min_y, max_x, max_y)` containing every pose (the origin
+/// if there are none).
+fn bounds(paths: &[Vec<AgentPose>]) -> (i32, i32, i32, i32) {
+    let mut poses = paths.iter().flatten();
+    let Some(first) = poses.next() else {
+        return (0, 0, 0, 0);
+    };
+    poses.fold(
+        (first.x, first.y, first.x, first.y),
+        |(min_x, min_y, max_x, max_y), pose| {
+            (
+                min_x.min(pose.x),
+                min_y.min(pose.y),
+                max_x.max(pose.x),
+                max_y.max(pose.y),
+            )
+        },
+    )
+}
+
+/// Render a compiled program as a standalone SVG document.
+///
+/// Draws one polyline per agent through the centers of the cells it visits,
+/// a hollow circle at its start and a filled square at its end. The picture
+/// covers every visited cell plus a one-cell margin. Returns
+/// [`RenderError::PoseOverflow`] if an agent leaves the grid.
+///
+/// # Examples
+///
+/// ```
+/// use h2lang::compile_native;
+/// use h2lang::output::CompileResult;
+/// use h2lang::render::{render_svg, SvgOptions};
+///
+/// if let CompileResult::Success { program } = compile_native("0: ssrss\n1: llss") {
+///     let svg = render_svg(&program, &SvgOptions::default()).unwrap();
+///     assert!(svg.starts_with("<svg"));
+///     assert_eq!(svg.matches("<polyline").count(), 2);
+/// }
+/// ```
+pub fn render_svg(program: &CompiledProgram, options: &SvgOptions) -> Result<String, RenderError> {
+    let paths = program.agent_paths(&options.start_poses)?;
+    let (min_x, min_y, max_x, max_y) = bounds(&paths);
+    let cell = options.cell_size.max(1) as i64;
+    let columns = i64::from(max_x) - i64::from(min_x) + 3;
+    let rows = i64::from(max_y) - i64::from(min_y) + 3;
+    let (width, height) = (columns * cell, rows * cell);
+    // Pixel coordinates of a cell center
+    let center = |pose: &AgentPose| {
+        (
+            (i64::from(pose.x) - i64::from(min_x) + 1) * cell + cell / 2,
+            (i64::from(pose.y) - i64::from(min_y) + 1) * cell + cell / 2,
+        )
+    };
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        width, height
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    );
+
+    if options.grid {
+        let _ = writeln!(svg, r##"<g stroke="#dddddd" stroke-width="1">"##);
+        for column in 0..=columns {
+            let x = column * cell;
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{0}" y1="0" x2="{0}" y2="{1}"/>"#,
+                x, height
+            );
+        }
+        for row in 0..=rows {
+            let y = row * cell;
+            let _ = writeln!(
+                svg,
+                r#"<line x1="0" y1="{0}" x2="{1}" y2="{0}"/>"#,
+                y, width
+            );
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+
+    let stroke = (cell / 6).max(1);
+    let marker = (cell / 4).max(2);
+    for (index, path) in paths.iter().enumerate() {
+        let color = COLORS[index % COLORS.len()];
+        let agent_id = path[0].agent_id;
+        let points: Vec<String> = path
+            .iter()
+            .map(|pose| {
+                let (x, y) = center(pose);
+                format!("{},{}", x, y)
+            })
+            .collect();
+
+        let _ = writeln!(svg, r#"<g data-agent="{}">"#, agent_id);
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="{}" stroke-linejoin="round" stroke-linecap="round"/>"#,
+            points.join(" "),
+            color,
+            stroke
+        );
+        let (start_x, start_y) = center(&path[0]);
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="white" stroke="{}" stroke-width="{}"/>"#,
+            start_x, start_y, marker, color, stroke
+        );
+        let (end_x, end_y) = center(&path[path.len() - 1]);
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{2}" height="{2}" fill="{3}"/>"#,
+            end_x - marker,
+            end_y - marker,
+            marker * 2,
+            color
+        );
+        if options.step_numbers {
+            let font = (cell / 3).max(6);
+            for (step, pose) in path.iter().enumerate().skip(1) {
+                let (x, y) = center(pose);
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{}" y="{}" font-size="{}" fill="{}">{}</text>"#,
+                    x + marker,
+                    y - marker,
+                    font,
+                    color,
+                    step - 1
+                );
+            }
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// Character set for [`render_text`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextStyle {
+    /// Box-drawing characters and arrows
+    #[default]
+    Unicode,
+    /// `|`, `-` and `+` only
+    Ascii,
+}
+
+/// Options for [`render_text`].
+///
+/// Missing fields deserialize to their defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextOptions {
+    /// Character set
+    pub style: TextStyle,
+    /// Start poses; other agents start at the origin facing north
+    pub start_poses: Vec<AgentPose>,
+    /// Largest grid (width × height) to draw
+    pub max_cells: u64,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self {
+            style: TextStyle::default(),
+            start_poses: Vec::new(),
+            max_cells: 1_000_000,
+        }
+    }
+}
+
+/// Connection bits of a cell, one per neighbour it is joined to.
+const NORTH: u8 = 1;
+const EAST: u8 = 2;
+const SOUTH: u8 = 4;
+const WEST: u8 = 8;
+
+/// What a cell shows.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    /// Part of one agent's path, joined to the given neighbours
+    Path(usize, u8),
+    /// Visited by more than one agent
+    Shared,
+}
+
+/// Glyph marking an agent's start: its ID as a digit or letter.
+fn agent_glyph(agent_id: u32) -> char {
+    match agent_id {
+        0..=9 => char::from(b'0' + agent_id as u8),
+        10..=35 => char::from(b'A' + (agent_id - 10) as u8),
+        _ => '@',
+    }
+}
+
+/// Box-drawing character joining the neighbours in `mask`.
+fn line_glyph(mask: u8, style: TextStyle) -> char {
+    match style {
+        TextStyle::Unicode => match mask {
+            NORTH => '╵',
+            EAST => '╶',
+            SOUTH => '╷',
+            WEST => '╴',
+            0b0101 => '│',
+            0b1010 => '─',
+            0b0011 => '└',
+            0b0110 => '┌',
+            0b1100 => '┐',
+            0b1001 => '┘',
+            0b0111 => '├',
+            0b1110 => '┬',
+            0b1101 => '┤',
+            0b1011 => '┴',
+            0b1111 => '┼',
+            _ => '·',
+        },
+        TextStyle::Ascii => match mask {
+            NORTH | SOUTH | 0b0101 => '|',
+            EAST | WEST | 0b1010 => '-',
+            0 => 'o',
+            _ => '+',
+        },
+    }
+}
+
+/// Arrow marking an agent's end, pointing in its final heading.
+fn heading_glyph(heading: u8, style: TextStyle) -> char {
+    let arrows = match style {
+        TextStyle::Unicode => ['▲', '▶', '▼', '◀'],
+        TextStyle::Ascii => ['^', '>', 'v', '<'],
+    };
+    arrows[usize::from(heading % 4)]
+}
+
+/// Render the cells visited by each agent as a character grid.
+///
+/// Each agent's path is drawn with lines following its direction of travel.
+/// Its start cell shows the agent's glyph (`0`-`9`, then `A`-`Z`) and its end
+/// cell an arrow in its final heading; cells visited by more than one agent
+/// show `*`. Unvisited cells are `.`. Rows run north to south, each ending in
+/// a newline, and the grid covers exactly the visited cells.
+///
+/// The grid is the bounding box of the paths, so a short program can span a
+/// huge area. Returns [`RenderError::GridTooLarge`] instead of drawing more
+/// than `options.max_cells` cells, and [`RenderError::PoseOverflow`] if an
+/// agent leaves the grid.
+///
+/// # Examples
+///
+/// ```
+/// use h2lang::compile_native;
+/// use h2lang::output::CompileResult;
+/// use h2lang::render::{render_text, TextOptions};
+///
+/// if let CompileResult::Success { program } = compile_native("ssrss") {
+///     let text = render_text(&program, &TextOptions::default()).unwrap();
+///     assert_eq!(text, "┌─▶\n│..\n0..\n");
+/// }
+/// ```
+pub fn render_text(
+    program: &CompiledProgram,
+    options: &TextOptions,
+) -> Result<String, RenderError> {
+    let paths = program.agent_paths(&options.start_poses)?;
+    let (min_x, min_y, max_x, max_y) = bounds(&paths);
+    let width = (i64::from(max_x) - i64::from(min_x) + 1) as u64;
+    let height = (i64::from(max_y) - i64::from(min_y) + 1) as u64;
+    if width.saturating_mul(height) > options.max_cells {
+        return Err(RenderError::GridTooLarge {
+            width,
+            height,
+            max_cells: options.max_cells,
+        });
+    }
+
+    let mut cells: HashMap<(i32, i32), Cell> = HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        let mut visit = |x: i32, y: i32, mask: u8| {
+            let cell = cells.entry((x, y)).or_insert(Cell::Path(index, 0));
+            *cell = match *cell {
+                Cell::Path(owner, bits) if owner == index => Cell::Path(index, bits | mask),
+                _ => Cell::Shared,
+            };
+        };
+        visit(path[0].x, path[0].y, 0);
+        for pair in path.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            let (dx, dy) = (to.x.cmp(&from.x) as i32, to.y.cmp(&from.y) as i32);
+            let (out, into) = match (dx, dy) {
+                (0, 0) => continue,
+                (0, -1) => (NORTH, SOUTH),
+                (0, _) => (SOUTH, NORTH),
+                (1, _) => (EAST, WEST),
+                _ => (WEST, EAST),
+            };
+            visit(from.x, from.y, out);
+            // Multi-cell moves pass through the cells in between
+            let (mut x, mut y) = (from.x + dx, from.y + dy);
+            while (x, y) != (to.x, to.y) {
+                visit(x, y, out | into);
+                x += dx;
+                y += dy;
+            }
+            visit(to.x, to.y, into);
+        }
+    }
+
+    let mut grid = vec![vec!['.'; width as usize]; height as usize];
+    for (&(x, y), cell) in &cells {
+        grid[(y - min_y) as usize][(x - min_x) as usize] = match *cell {
+            Cell::Path(_, mask) => line_glyph(mask, options.style),
+            Cell::Shared => '*',
+        };
+    }
+    // End markers first, so a start on the same cell stays visible
+    for path in &paths {
+        let end = path[path.len() - 1];
+        grid[(end.y - min_y) as usize][(end.x - min_x) as usize] =
+            heading_glyph(end.heading, options.style);
+    }
+    for path in &paths {
+        let start = path[0];
+        grid[(start.y - min_y) as usize][(start.x - min_x) as usize] = agent_glyph(start.agent_id);
+    }
+
+    let mut text = String::with_capacity(((width + 1) * height) as usize);
+    for row in grid {
+        text.extend(row);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_program;
+    use crate::error::PoseOverflow;
+
+    #[test]
+    fn test_agent_paths() {
+        let paths = compile_program("0: srs\n1: ").agent_paths(&[]).unwrap();
+        let cells: Vec<(i32, i32)> = paths[0].iter().map(|p| (p.x, p.y)).collect();
+        assert_eq!(cells, vec![(0, 0), (0, -1), (0, -1), (1, -1)]);
+        assert_eq!(paths[1].len(), 1);
+    }
+
+    #[test]
+    fn test_svg_size_covers_path() {
+        let options = SvgOptions {
+            cell_size: 10,
+            ..SvgOptions::default()
+        };
+        // Path spans 1×3 cells, plus a one-cell margin on each side
+        let svg = render_svg(&compile_program("ss"), &options).unwrap();
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="30" height="50""#)
+        );
+        assert!(svg.contains(r#"points="15,35 15,25 15,15""#));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_svg_agents_and_markers() {
+        let svg = render_svg(
+            &compile_program("0: sr\n1: ls\n2: s"),
+            &SvgOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(svg.matches("<polyline").count(), 3);
+        assert_eq!(svg.matches("<circle").count(), 3);
+        assert!(svg.contains(r#"data-agent="2""#));
+        assert!(svg.contains(COLORS[1]));
+        assert!(!svg.contains("<text"));
+    }
+
+    #[test]
+    fn test_svg_options() {
+        let options = SvgOptions {
+            grid: false,
+            step_numbers: true,
+            start_poses: vec![AgentPose {
+                agent_id: 0,
+                x: 5,
+                y: 5,
+                heading: 1,
+            }],
+            ..SvgOptions::default()
+        };
+        let svg = render_svg(&compile_program("srs"), &options).unwrap();
+        assert!(!svg.contains("<line"));
+        assert_eq!(svg.matches("<text").count(), 3);
+        assert!(svg.contains(">2</text>"));
+    }
+
+    fn text(source: &str, style: TextStyle) -> String {
+        let options = TextOptions {
+            style,
+            ..TextOptions::default()
+        };
+        render_text(&compile_program(source), &options).unwrap()
+    }
+
+    #[test]
+    fn test_text_square() {
+        assert_eq!(text("a:ssr aaaa", TextStyle::Unicode), "┌─┐\n│.│\n0─┘\n");
+        assert_eq!(text("a:ssr aaaa", TextStyle::Ascii), "+-+\n|.|\n0-+\n");
+    }
+
+    #[test]
+    fn test_text_end_marker_and_crossing() {
+        // Ends facing west after crossing its own path
+        assert_eq!(text("ssrsrsrss", TextStyle::Unicode), ".┌┐\n◀┼┘\n.0.\n");
+        // The start glyph wins over a path returning to it
+        assert_eq!(
+            text("sslsslsslssss", TextStyle::Unicode),
+            "┌─┐..\n│.│..\n└─0─▶\n"
+        );
+        assert_eq!(text("r", TextStyle::Ascii), "0\n");
+    }
+
+    #[test]
+    fn test_text_multiple_agents() {
+        let options = TextOptions {
+            style: TextStyle::Ascii,
+            start_poses: vec![AgentPose {
+                agent_id: 1,
+                x: 2,
+                y: -1,
+                heading: 3,
+            }],
+            ..TextOptions::default()
+        };
+        // Agent 1 walks west across agent 0's path
+        let rendered = render_text(&compile_program("0: ss\n1: sss"), &options).unwrap();
+        assert_eq!(rendered, ".^..\n<*-1\n.0..\n");
+        assert_eq!(agent_glyph(11), 'B');
+        assert_eq!(agent_glyph(99), '@');
+    }
+
+    #[test]
+    fn test_text_multi_cell_move() {
+        let mut program = compile_program("rs");
+        program.agents[0].commands[1].steps = Some(3);
+        assert_eq!(
+            render_text(&program, &TextOptions::default()).unwrap(),
+            "0──▶\n"
+        );
+    }
+
+    #[test]
+    fn test_text_grid_limit() {
+        // A staircase of 40,000 steps spans 10,000 × 10,000 cells
+        let program = compile_program("MAX_STEP=40000\nON_LIMIT=TRUNCATE\na:srsla a");
+        let error = render_text(&program, &TextOptions::default()).unwrap_err();
+        assert_eq!(
+            error,
+            RenderError::GridTooLarge {
+                width: 10_001,
+                height: 10_001,
+                max_cells: 1_000_000,
+            }
+        );
+        let options = TextOptions {
+            max_cells: 8,
+            ..TextOptions::default()
+        };
+        assert_eq!(
+            render_text(&compile_program("ss"), &options).unwrap(),
+            "▲\n│\n0\n"
+        );
+        assert!(render_text(&compile_program("ssrss"), &options).is_err());
+    }
+
+    #[test]
+    fn test_render_pose_overflow() {
+        let start_poses = vec![AgentPose {
+            agent_id: 0,
+            x: i32::MAX,
+            y: 0,
+            heading: 1,
+        }];
+        let overflow = RenderError::PoseOverflow(PoseOverflow {
+            agent_id: 0,
+            step: 0,
+            x: i64::from(i32::MAX) + 1,
+            y: 0,
+        });
+        let program = compile_program("s");
+        let svg_options = SvgOptions {
+            start_poses: start_poses.clone(),
+            ..SvgOptions::default()
+        };
+        assert_eq!(render_svg(&program, &svg_options), Err(overflow.clone()));
+        let text_options = TextOptions {
+            start_poses,
+            ..TextOptions::default()
+        };
+        assert_eq!(render_text(&program, &text_options), Err(overflow));
+    }
+}
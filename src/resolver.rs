@@ -0,0 +1,460 @@
+//! Static semantic analysis for H2 Language.
+//!
+//! The resolver runs between parsing and expansion. It checks every function
+//! call without running anything, so errors are found even in code that
+//! expansion would never reach (e.g. behind `≤0` termination or truncation):
+//!
+//! - **E001/E002**: call to an undefined function
+//! - **E003**: argument count mismatch
+//! - **E008**: argument type does not match the parameter type
+//! - Parameter references that no enclosing call binds
+//!
+//! It also resolves pass-through arguments (a lone `X` in `f(X)`), which the
+//! parser cannot type: when `X` is an Int parameter of the caller, the
+//! argument is rewritten to a numeric expression.
+
+use crate::ast::{Agent, Arg, Definition, Expr, NumAtom, ParamType, Program};
+use crate::error::ExpandError;
+use crate::token::Span;
+use std::collections::{HashMap, HashSet};
+
+/// Parameter types of a function as far as they can be inferred.
+///
+/// `None` means the parameter is unconstrained (unused, or only passed
+/// through to unconstrained parameters) and accepts either type.
+struct Signature {
+    params: Vec<char>,
+    types: Vec<Option<ParamType>>,
+}
+
+impl Signature {
+    fn type_of(&self, param: char) -> Option<ParamType> {
+        let index = self.params.iter().position(|&p| p == param)?;
+        self.types[index]
+    }
+}
+
+/// Static checker for function calls and parameter references.
+pub struct Resolver;
+
+impl Resolver {
+    /// Resolve every agent of a program, returning all errors found.
+    pub fn resolve_program(program: &mut Program) -> Vec<ExpandError> {
+        program
+            .agents
+            .iter_mut()
+            .flat_map(Self::resolve_agent)
+            .collect()
+    }
+
+    /// Resolve one agent, returning all errors found in source order.
+    ///
+    /// Parameter types inferred from pass-through arguments are written back
+    /// to [`FuncDef::param_types`](crate::ast::FuncDef::param_types).
+    pub fn resolve_agent(agent: &mut Agent) -> Vec<ExpandError> {
+        let signatures = infer_signatures(agent);
+
+        for Definition::Function(def) in &mut agent.definitions {
+            let signature = &signatures[&def.name];
+            for (param, ty) in signature.params.iter().zip(&signature.types) {
+                if let Some(ty) = ty {
+                    def.param_types.insert(*param, *ty);
+                }
+            }
+        }
+
+        let mut errors = Vec::new();
+        for Definition::Function(def) in &mut agent.definitions {
+            let scope = &signatures[&def.name];
+            check_expr(&mut def.body, Some(scope), &signatures, &mut errors);
+        }
+        check_expr(&mut agent.expression, None, &signatures, &mut errors);
+
+        check_unbound_params(agent, &mut errors);
+
+        errors.sort_by_key(|e| (e.line, e.column));
+        errors
+    }
+}
+
+/// Infer parameter types from direct uses, then from pass-through arguments.
+fn infer_signatures(agent: &Agent) -> HashMap<char, Signature> {
+    let mut signatures: HashMap<char, Signature> = agent
+        .definitions
+        .iter()
+        .map(|Definition::Function(def)| {
+            let mut types = vec![None; def.params.len()];
+            visit_params(&def.body, &mut |param, usage, _| {
+                let index = def.params.iter().position(|&p| p == param);
+                if let (Some(index), Some(ty)) = (index, usage.implied_type()) {
+                    types[index].get_or_insert(ty);
+                }
+            });
+            let signature = Signature {
+                params: def.params.clone(),
+                types,
+            };
+            (def.name, signature)
+        })
+        .collect();
+
+    // A parameter only passed through takes the type of the parameter it is
+    // passed to, e.g. `X` in `f(X):g(X) g(Y):sg(Y-1)` is Int
+    loop {
+        let mut inferred = Vec::new();
+        for Definition::Function(def) in &agent.definitions {
+            let scope = &signatures[&def.name];
+            visit_calls(&def.body, &mut |name, args, _| {
+                let Some(callee) = signatures.get(&name) else {
+                    return;
+                };
+                if args.len() != callee.params.len() {
+                    return;
+                }
+                for (arg, ty) in args.iter().zip(&callee.types) {
+                    if let (Arg::Command(Expr::Param(p, _)), Some(ty)) = (arg, ty) {
+                        if scope.params.contains(p) && scope.type_of(*p).is_none() {
+                            inferred.push((def.name, *p, *ty));
+                        }
+                    }
+                }
+            });
+        }
+
+        if inferred.is_empty() {
+            return signatures;
+        }
+        for (name, param, ty) in inferred {
+            let signature = signatures.get_mut(&name).expect("signature exists");
+            let index = signature
+                .params
+                .iter()
+                .position(|&p| p == param)
+                .expect("parameter exists");
+            signature.types[index].get_or_insert(ty);
+        }
+    }
+}
+
+/// Check the calls in `expr`, evaluated in the scope of `scope` (`None` for
+/// the agent's main expression), and type pass-through arguments.
+fn check_expr(
+    expr: &mut Expr,
+    scope: Option<&Signature>,
+    signatures: &HashMap<char, Signature>,
+    errors: &mut Vec<ExpandError>,
+) {
+    match expr {
+        Expr::Primitive(_, _) | Expr::Param(_, _) => {}
+        Expr::Sequence(exprs) => {
+            for e in exprs {
+                check_expr(e, scope, signatures, errors);
+            }
+        }
+        Expr::FuncCall { name, args, span } => {
+            for arg in args.iter_mut() {
+                if let Arg::Command(e) = arg {
+                    check_expr(e, scope, signatures, errors);
+                }
+            }
+
+            let Some(callee) = signatures.get(name) else {
+                errors.push(ExpandError::undefined_function(*name, args.len(), *span));
+                return;
+            };
+
+            // `f()` on a function with parameters binds defaults (SPEC §4.4.1)
+            if args.is_empty() {
+                return;
+            }
+            if args.len() != callee.params.len() {
+                errors.push(ExpandError::argument_count_mismatch(
+                    *name,
+                    callee.params.len(),
+                    args.len(),
+                    *span,
+                ));
+                return;
+            }
+
+            for (index, arg) in args.iter_mut().enumerate() {
+                let actual = match arg {
+                    Arg::Number(_, _) | Arg::NumExpr { .. } => Some(ParamType::Int),
+                    Arg::Command(Expr::Param(p, _)) => scope.and_then(|s| s.type_of(*p)),
+                    Arg::Command(_) => Some(ParamType::CmdSeq),
+                };
+
+                if let (Some(actual), Some(expected)) = (actual, callee.types[index]) {
+                    if actual != expected {
+                        errors.push(ExpandError::type_error(
+                            format!(
+                                "Argument {} of function '{}' is {} but parameter '{}' is {}",
+                                index + 1,
+                                name,
+                                type_name(actual),
+                                callee.params[index],
+                                type_name(expected)
+                            ),
+                            arg.span(),
+                        ));
+                        continue;
+                    }
+                }
+
+                // Resolve an Int pass-through into a typed numeric argument
+                if let (Arg::Command(Expr::Param(p, span)), Some(ParamType::Int)) = (&*arg, actual)
+                {
+                    *arg = Arg::NumExpr {
+                        first: NumAtom::Param(*p),
+                        rest: Vec::new(),
+                        span: *span,
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Report parameter references that no call binds.
+///
+/// A function body also sees the parameters of its callers (dynamic scope),
+/// so a reference is unbound only if some chain of calls from the main
+/// expression reaches it without passing through a function that declares it.
+fn check_unbound_params(agent: &Agent, errors: &mut Vec<ExpandError>) {
+    let bodies: HashMap<char, (&[char], &Expr)> = agent
+        .definitions
+        .iter()
+        .map(|Definition::Function(def)| (def.name, (def.params.as_slice(), &def.body)))
+        .collect();
+
+    let mut names: Vec<char> = Vec::new();
+    let mut collect_names = |expr: &Expr| {
+        visit_params(expr, &mut |p, _, _| {
+            if !names.contains(&p) {
+                names.push(p);
+            }
+        })
+    };
+    collect_names(&agent.expression);
+    for (_, body) in bodies.values() {
+        collect_names(body);
+    }
+
+    let mut reported = HashSet::new();
+    for name in names {
+        // Walk the call graph from the main expression while `name` is unbound
+        let mut pending: Vec<&Expr> = vec![&agent.expression];
+        let mut visited: HashSet<char> = HashSet::new();
+        while let Some(expr) = pending.pop() {
+            visit_params(expr, &mut |p, _, span| {
+                if p == name && reported.insert((span.start, span.end, p)) {
+                    errors.push(ExpandError::undefined_parameter(p, span));
+                }
+            });
+            visit_calls(expr, &mut |callee, _, _| {
+                if let Some((params, body)) = bodies.get(&callee) {
+                    if !params.contains(&name) && visited.insert(callee) {
+                        pending.push(body);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// How a parameter reference is used.
+#[derive(Clone, Copy)]
+pub(crate) enum Use {
+    /// As a term (`X` in a body): CmdSeq
+    Term,
+    /// In a numeric expression (`X-1`): Int
+    Numeric,
+    /// As a whole argument (`X` in `f(X)`): takes the callee parameter's type
+    PassThrough,
+}
+
+impl Use {
+    /// The type this use implies, if any.
+    fn implied_type(self) -> Option<ParamType> {
+        match self {
+            Use::Term => Some(ParamType::CmdSeq),
+            Use::Numeric => Some(ParamType::Int),
+            Use::PassThrough => None,
+        }
+    }
+}
+
+/// Call `f(param, use, span)` for every parameter reference in `expr`.
+pub(crate) fn visit_params(expr: &Expr, f: &mut impl FnMut(char, Use, Span)) {
+    match expr {
+        Expr::Primitive(_, _) => {}
+        Expr::Param(p, span) => f(*p, Use::Term, *span),
+        Expr::Sequence(exprs) => {
+            for e in exprs {
+                visit_params(e, f);
+            }
+        }
+        Expr::FuncCall { args, .. } => {
+            for arg in args {
+                match arg {
+                    Arg::Command(Expr::Param(p, span)) => f(*p, Use::PassThrough, *span),
+                    Arg::Command(e) => visit_params(e, f),
+                    Arg::Number(_, _) => {}
+                    Arg::NumExpr { first, rest, span } => {
+                        let atoms = std::iter::once(first).chain(rest.iter().map(|(_, a)| a));
+                        for atom in atoms {
+                            if let NumAtom::Param(p) = atom {
+                                f(*p, Use::Numeric, *span);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Call `f(name, args, span)` for every function call in `expr`, including
+/// calls nested in arguments.
+pub(crate) fn visit_calls<'a>(expr: &'a Expr, f: &mut impl FnMut(char, &'a [Arg], Span)) {
+    match expr {
+        Expr::Primitive(_, _) | Expr::Param(_, _) => {}
+        Expr::Sequence(exprs) => {
+            for e in exprs {
+                visit_calls(e, f);
+            }
+        }
+        Expr::FuncCall { name, args, span } => {
+            f(*name, args, *span);
+            for arg in args {
+                if let Arg::Command(e) = arg {
+                    visit_calls(e, f);
+                }
+            }
+        }
+    }
+}
+
+fn type_name(ty: ParamType) -> &'static str {
+    match ty {
+        ParamType::CmdSeq => "CmdSeq",
+        ParamType::Int => "Int",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+    use crate::parser::Parser;
+
+    fn resolve(source: &str) -> (Program, Vec<ExpandError>) {
+        let mut program = Parser::new(source).unwrap().parse_program().unwrap();
+        let errors = Resolver::resolve_program(&mut program);
+        (program, errors)
+    }
+
+    fn codes(source: &str) -> Vec<Option<ErrorCode>> {
+        resolve(source).1.iter().map(|e| e.code).collect()
+    }
+
+    #[test]
+    fn test_valid_program() {
+        assert!(codes("0: f(X):XX a(X):sa(X-1) f(a(3))").is_empty());
+    }
+
+    #[test]
+    fn test_undefined_function_in_unreached_code() {
+        // a(0) terminates immediately, so expansion would never call b
+        let (_, errors) = resolve("a(X):sb(X) a(0)");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::E002));
+        assert_eq!(errors[0].column, 7);
+    }
+
+    #[test]
+    fn test_reports_all_errors() {
+        assert_eq!(
+            codes("f(X,Y):XY f(s) g(X):sg(X-1) g(s) h"),
+            vec![
+                Some(ErrorCode::E003),
+                Some(ErrorCode::E008),
+                Some(ErrorCode::E001)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_call_binds_defaults() {
+        assert!(codes("a(X):Xrra(sX) a()").is_empty());
+    }
+
+    #[test]
+    fn test_pass_through_is_typed() {
+        let (program, errors) = resolve("a(X,Y):sra(X-1,Y) a(3,2)");
+        assert!(errors.is_empty());
+
+        // Y is unconstrained, so it stays a pass-through; X-1 is numeric
+        let Definition::Function(def) = &program.agents[0].definitions[0];
+        let Expr::Sequence(terms) = &def.body else {
+            panic!("Expected sequence body");
+        };
+        let Expr::FuncCall { args, .. } = &terms[2] else {
+            panic!("Expected recursive call");
+        };
+        assert!(matches!(args[1], Arg::Command(Expr::Param('Y', _))));
+    }
+
+    #[test]
+    fn test_int_pass_through_rewritten() {
+        let (program, errors) = resolve("f(X):g(X) g(Y):sg(Y-1) f(3)");
+        assert!(errors.is_empty());
+
+        let Definition::Function(f) = &program.agents[0].definitions[0];
+        assert_eq!(f.param_types[&'X'], ParamType::Int);
+        let Expr::FuncCall { args, .. } = &f.body else {
+            panic!("Expected call body");
+        };
+        assert!(matches!(
+            &args[0],
+            Arg::NumExpr {
+                first: NumAtom::Param('X'),
+                rest,
+                ..
+            } if rest.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_pass_through_type_mismatch() {
+        assert_eq!(
+            codes("f(X):g(X) g(Y):sg(Y-1) f(s)"),
+            vec![Some(ErrorCode::E008)]
+        );
+        assert_eq!(
+            codes("f(X):Xg(X) g(Y):sg(Y-1) f(s)"),
+            vec![Some(ErrorCode::E008)]
+        );
+    }
+
+    #[test]
+    fn test_inherited_parameter_is_bound() {
+        // g sees X through f's bindings (dynamic scope)
+        assert!(codes("f(X):g(s) g(Y):XY f(r)").is_empty());
+    }
+
+    #[test]
+    fn test_unbound_parameter() {
+        let (_, errors) = resolve("f(X):g(s) g(Y):XY g(r) f(s)");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Undefined parameter 'X'");
+        assert_eq!(errors[0].column, 16);
+    }
+
+    #[test]
+    fn test_unbound_parameter_in_main_expression() {
+        let (_, errors) = resolve("a(X):sa(X-1) a(Y-1) sZ");
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|e| e.code.is_none()));
+    }
+}
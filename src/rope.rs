@@ -0,0 +1,336 @@
+//! Shared, immutable command sequences.
+//!
+//! A [`CommandRope`] is a straight-line grammar over [`Command`]s: each node is
+//! either a flat run of commands or a concatenation of other ropes. Nodes are
+//! reference-counted, so a sequence that appears many times in an expansion
+//! (e.g. the argument of `f(X):XXXX`) is stored once and shared.
+//!
+//! All operations walk the tree iteratively, so very deep ropes (one level per
+//! recursive call) are safe to build, query and drop.
+
+use crate::expander::Command;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// A shared command sequence.
+///
+/// Cloning is cheap (a reference-count increment). Lengths are `u64` because a
+/// compressed expansion can describe far more commands than fit in memory.
+#[derive(Debug, Clone)]
+pub struct CommandRope(Rc<RopeNode>);
+
+#[derive(Debug)]
+enum RopeNode {
+    /// A flat run of commands
+    Leaf(Vec<Command>),
+    /// Concatenation of non-empty parts
+    Concat { len: u64, parts: Vec<CommandRope> },
+}
+
+impl CommandRope {
+    /// Create an empty rope.
+    pub fn empty() -> Self {
+        Self::from(Vec::new())
+    }
+
+    /// Concatenate ropes, skipping empty parts.
+    pub fn concat(parts: Vec<CommandRope>) -> Self {
+        let mut parts: Vec<CommandRope> = parts.into_iter().filter(|p| !p.is_empty()).collect();
+        match parts.len() {
+            0 => Self::empty(),
+            1 => parts.pop().unwrap(),
+            _ => {
+                let len = parts
+                    .iter()
+                    .fold(0u64, |acc, p| acc.saturating_add(p.len()));
+                Self(Rc::new(RopeNode::Concat { len, parts }))
+            }
+        }
+    }
+
+    /// Number of commands in the sequence.
+    pub fn len(&self) -> u64 {
+        match &*self.0 {
+            RopeNode::Leaf(cmds) => cmds.len() as u64,
+            RopeNode::Concat { len, .. } => *len,
+        }
+    }
+
+    /// Whether the sequence has no commands.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the command at `index` without flattening the rope.
+    pub fn get(&self, mut index: u64) -> Option<Command> {
+        let mut node = self;
+        loop {
+            match &*node.0 {
+                RopeNode::Leaf(cmds) => return cmds.get(usize::try_from(index).ok()?).copied(),
+                RopeNode::Concat { parts, .. } => {
+                    let mut next = None;
+                    for part in parts {
+                        if index < part.len() {
+                            next = Some(part);
+                            break;
+                        }
+                        index -= part.len();
+                    }
+                    node = next?;
+                }
+            }
+        }
+    }
+
+    /// Get a sub-sequence sharing structure with this rope.
+    ///
+    /// The range is clamped to the rope's length.
+    pub fn slice(&self, range: Range<u64>) -> CommandRope {
+        let end = range.end.min(self.len());
+        let start = range.start.min(end);
+        self.prefix(end).suffix(start)
+    }
+
+    /// Iterate over the commands in order.
+    pub fn iter(&self) -> RopeIter {
+        RopeIter {
+            stack: vec![(self.clone(), 0)],
+        }
+    }
+
+    /// Flatten the rope into a list of commands.
+    pub fn to_vec(&self) -> Vec<Command> {
+        self.iter().collect()
+    }
+
+    /// Number of distinct nodes in the rope (a measure of its compressed size).
+    pub fn node_count(&self) -> usize {
+        let mut seen = HashSet::new();
+        let mut pending = vec![self];
+        while let Some(rope) = pending.pop() {
+            if !seen.insert(Rc::as_ptr(&rope.0)) {
+                continue;
+            }
+            if let RopeNode::Concat { parts, .. } = &*rope.0 {
+                pending.extend(parts);
+            }
+        }
+        seen.len()
+    }
+
+    /// Whether two ropes are the same shared node.
+    pub fn ptr_eq(&self, other: &CommandRope) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Address of the shared node, for identity-based hashing.
+    pub(crate) fn node_addr(&self) -> usize {
+        Rc::as_ptr(&self.0) as usize
+    }
+
+    /// The first `n` commands.
+    fn prefix(&self, n: u64) -> CommandRope {
+        if n >= self.len() {
+            return self.clone();
+        }
+
+        // Walk down the cut, remembering the whole parts to its left
+        let mut levels: Vec<Vec<CommandRope>> = Vec::new();
+        let mut node = self.clone();
+        let mut n = n;
+        let mut cut = loop {
+            if n >= node.len() {
+                break node;
+            }
+            match &*node.0 {
+                RopeNode::Leaf(cmds) => break CommandRope::from(cmds[..n as usize].to_vec()),
+                RopeNode::Concat { parts, .. } => {
+                    let mut left = Vec::new();
+                    let mut next = None;
+                    for part in parts {
+                        if n < part.len() {
+                            next = Some(part.clone());
+                            break;
+                        }
+                        n -= part.len();
+                        left.push(part.clone());
+                    }
+                    levels.push(left);
+                    node = next.expect("cut lies within the rope");
+                }
+            }
+        };
+
+        while let Some(mut left) = levels.pop() {
+            left.push(cut);
+            cut = CommandRope::concat(left);
+        }
+        cut
+    }
+
+    /// Everything after the first `n` commands.
+    fn suffix(&self, n: u64) -> CommandRope {
+        if n == 0 {
+            return self.clone();
+        }
+        if n >= self.len() {
+            return CommandRope::empty();
+        }
+
+        // Walk down the cut, remembering the whole parts to its right
+        let mut levels: Vec<Vec<CommandRope>> = Vec::new();
+        let mut node = self.clone();
+        let mut n = n;
+        let mut cut = loop {
+            match &*node.0 {
+                RopeNode::Leaf(cmds) => break CommandRope::from(cmds[n as usize..].to_vec()),
+                RopeNode::Concat { parts, .. } => {
+                    let mut i = 0;
+                    while n >= parts[i].len() {
+                        n -= parts[i].len();
+                        i += 1;
+                    }
+                    levels.push(parts[i + 1..].to_vec());
+                    node = parts[i].clone();
+                }
+            }
+        };
+
+        while let Some(right) = levels.pop() {
+            let mut parts = vec![cut];
+            parts.extend(right);
+            cut = CommandRope::concat(parts);
+        }
+        cut
+    }
+}
+
+impl Default for CommandRope {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl From<Vec<Command>> for CommandRope {
+    fn from(cmds: Vec<Command>) -> Self {
+        Self(Rc::new(RopeNode::Leaf(cmds)))
+    }
+}
+
+impl Drop for RopeNode {
+    fn drop(&mut self) {
+        // Dismantle nested concatenations iteratively to avoid deep recursion
+        let RopeNode::Concat { parts, .. } = self else {
+            return;
+        };
+        let mut pending = std::mem::take(parts);
+        while let Some(rope) = pending.pop() {
+            // Shared nodes are still referenced elsewhere and must stay intact
+            let Ok(mut node) = Rc::try_unwrap(rope.0) else {
+                continue;
+            };
+            if let RopeNode::Concat { parts, .. } = &mut node {
+                pending.append(parts);
+            }
+        }
+    }
+}
+
+/// Iterator over the commands of a [`CommandRope`].
+pub struct RopeIter {
+    /// Nodes being walked, with the index of the next part or command
+    stack: Vec<(CommandRope, usize)>,
+}
+
+impl Iterator for RopeIter {
+    type Item = Command;
+
+    fn next(&mut self) -> Option<Command> {
+        loop {
+            let (rope, index) = self.stack.last_mut()?;
+            match &*rope.0 {
+                RopeNode::Leaf(cmds) => {
+                    if let Some(cmd) = cmds.get(*index) {
+                        *index += 1;
+                        return Some(*cmd);
+                    }
+                    self.stack.pop();
+                }
+                RopeNode::Concat { parts, .. } => {
+                    if let Some(part) = parts.get(*index) {
+                        let part = part.clone();
+                        *index += 1;
+                        self.stack.push((part, 0));
+                    } else {
+                        self.stack.pop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rope(s: &str) -> CommandRope {
+        CommandRope::from(
+            s.chars()
+                .map(|c| match c {
+                    's' => Command::Straight,
+                    'r' => Command::Right,
+                    _ => Command::Left,
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn text(rope: &CommandRope) -> String {
+        rope.iter().map(|c| c.as_char()).collect()
+    }
+
+    #[test]
+    fn test_concat_and_len() {
+        let r = CommandRope::concat(vec![rope("ss"), CommandRope::empty(), rope("rl")]);
+        assert_eq!(r.len(), 4);
+        assert_eq!(text(&r), "ssrl");
+        assert_eq!(r.get(2), Some(Command::Right));
+        assert_eq!(r.get(4), None);
+    }
+
+    #[test]
+    fn test_shared_nodes() {
+        let x = rope("sr");
+        let xx = CommandRope::concat(vec![x.clone(), x.clone()]);
+        let r = CommandRope::concat(vec![xx.clone(), xx.clone(), xx]);
+        assert_eq!(r.len(), 12);
+        assert_eq!(r.node_count(), 3);
+    }
+
+    #[test]
+    fn test_slice() {
+        let r = CommandRope::concat(vec![
+            rope("ss"),
+            CommandRope::concat(vec![rope("rr"), rope("ll")]),
+            rope("s"),
+        ]);
+        assert_eq!(text(&r.slice(0..7)), "ssrrlls");
+        assert_eq!(text(&r.slice(1..6)), "srrll");
+        assert_eq!(text(&r.slice(3..5)), "rl");
+        assert_eq!(text(&r.slice(5..100)), "ls");
+        assert!(r.slice(4..4).is_empty());
+    }
+
+    #[test]
+    fn test_deep_rope_drop() {
+        let mut r = rope("s");
+        for _ in 0..200_000 {
+            r = CommandRope::concat(vec![rope("s"), r]);
+        }
+        assert_eq!(r.len(), 200_001);
+        assert_eq!(r.get(200_000), Some(Command::Straight));
+        assert_eq!(r.slice(100_000..100_003).len(), 3);
+    }
+}
@@ -1,6 +1,16 @@
 //! Parallel scheduling for multiple agents.
+//!
+//! [`Scheduler::schedule`] runs every agent in lockstep.
+//! [`Scheduler::schedule_with_waits`] delays placed agents with wait steps so
+//! they never collide (`ON_COLLISION=WAIT`).
 
+use crate::ast::Priority;
+use crate::error::{CompileError, ErrorCode, PoseOverflow, Severity};
 use crate::expander::Command;
+use crate::output::{AgentPose, CompiledAgent, ToioCommand};
+use crate::token::Span;
+use crate::world::Position;
+use std::cmp::Reverse;
 
 /// A command for a specific agent at a specific step.
 #[derive(Debug, Clone)]
@@ -20,6 +30,73 @@ pub struct TimelineStep {
     pub agent_commands: Vec<AgentCommand>,
 }
 
+/// An agent that cannot avoid a collision by waiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadlock {
+    /// Agent that is stuck
+    pub agent_id: u32,
+    /// Agent with the right of way that it would collide with
+    pub blocked_by: u32,
+    /// Step at which neither moving nor waiting is safe
+    pub step: usize,
+    /// Cell the stuck agent is on
+    pub position: Position,
+}
+
+impl Deadlock {
+    /// Human-readable description.
+    pub fn message(&self) -> String {
+        format!(
+            "Agent {} cannot avoid agent {} by waiting at ({}, {}) at step {}",
+            self.agent_id, self.blocked_by, self.position.x, self.position.y, self.step
+        )
+    }
+
+    /// E014 error located at `span` (normally the stuck agent's line).
+    pub fn to_compile_error(&self, span: Span) -> CompileError {
+        CompileError::coded(ErrorCode::E014, Severity::Error, self.message(), span)
+    }
+}
+
+/// Why [`Scheduler::schedule_with_waits`] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// An agent can neither move nor wait safely
+    Deadlock(Deadlock),
+    /// An agent's path leaves the grid
+    PoseOverflow(PoseOverflow),
+}
+
+impl ScheduleError {
+    /// The agent that could not be planned.
+    pub fn agent_id(&self) -> u32 {
+        match self {
+            ScheduleError::Deadlock(deadlock) => deadlock.agent_id,
+            ScheduleError::PoseOverflow(overflow) => overflow.agent_id,
+        }
+    }
+
+    /// E014 or E018 error located at `span` (normally the agent's line).
+    pub fn to_compile_error(&self, span: Span) -> CompileError {
+        match self {
+            ScheduleError::Deadlock(deadlock) => deadlock.to_compile_error(span),
+            ScheduleError::PoseOverflow(overflow) => overflow.to_compile_error(span),
+        }
+    }
+}
+
+impl From<Deadlock> for ScheduleError {
+    fn from(deadlock: Deadlock) -> Self {
+        ScheduleError::Deadlock(deadlock)
+    }
+}
+
+impl From<PoseOverflow> for ScheduleError {
+    fn from(overflow: PoseOverflow) -> Self {
+        ScheduleError::PoseOverflow(overflow)
+    }
+}
+
 /// Scheduler for parallel execution of multiple agents.
 pub struct Scheduler;
 
@@ -28,7 +105,9 @@ impl Scheduler {
     ///
     /// Each step in the timeline contains all agents' commands for that step.
     /// If an agent has fewer commands than the maximum, it won't have a command
-    /// for the remaining steps (wait state).
+    /// for the remaining steps (wait state). See
+    /// [`CompiledProgram::pad_timeline`](crate::output::CompiledProgram::pad_timeline)
+    /// for explicit waits.
     pub fn schedule(agents: &[(u32, Vec<Command>)]) -> Vec<TimelineStep> {
         if agents.is_empty() {
             return Vec::new();
@@ -65,11 +144,268 @@ impl Scheduler {
     pub fn max_steps(agents: &[(u32, Vec<Command>)]) -> usize {
         agents.iter().map(|(_, cmds)| cmds.len()).max().unwrap_or(0)
     }
+
+    /// Insert wait steps so that placed agents never collide.
+    ///
+    /// Agents with a pose in `starts` are planned one at a time in `priority`
+    /// order. Each keeps its commands but waits whenever its next command
+    /// would collide (as in [`collision`](crate::collision)) with an agent
+    /// planned before it. A command runs as soon as it is safe; the last one
+    /// also waits until no earlier agent will enter its final cell again.
+    /// Agents without a start pose are returned unchanged. Finished agents are
+    /// assumed to stay on their last cell, so the plan does not hold for
+    /// agents that loop ([`OnEnd::Loop`](crate::ast::OnEnd::Loop)).
+    ///
+    /// Returns the agents in their original order, or the first [`Deadlock`]
+    /// where an agent can neither move nor wait safely. The search is greedy,
+    /// so a different priority may still succeed. An agent whose path leaves
+    /// the grid fails with [`ScheduleError::PoseOverflow`].
+    pub fn schedule_with_waits(
+        agents: &[CompiledAgent],
+        starts: &[AgentPose],
+        priority: Priority,
+    ) -> Result<Vec<CompiledAgent>, ScheduleError> {
+        let start_of = |id: u32| starts.iter().find(|s| s.agent_id == id).copied();
+        let mut order: Vec<usize> = (0..agents.len())
+            .filter(|&i| start_of(agents[i].id).is_some())
+            .collect();
+        match priority {
+            Priority::LowId => order.sort_by_key(|&i| agents[i].id),
+            Priority::HighId => order.sort_by_key(|&i| Reverse(agents[i].id)),
+            Priority::Longest => {
+                order.sort_by_key(|&i| (Reverse(agents[i].commands.len()), agents[i].id))
+            }
+        }
+
+        let mut scheduled = agents.to_vec();
+        let mut planned: Vec<(u32, Vec<Position>)> = Vec::with_capacity(order.len());
+        for i in order {
+            let start = start_of(agents[i].id).expect("only placed agents are ordered");
+            let (commands, cells) = plan_with_waits(&agents[i], start, &planned)?;
+            scheduled[i].commands = commands;
+            planned.push((agents[i].id, cells));
+        }
+        Ok(scheduled)
+    }
+}
+
+/// Delay one agent around the `planned` agents (ID and cell at every step,
+/// starting with the start cell).
+///
+/// Returns the agent's commands with waits and its cell at every step.
+fn plan_with_waits(
+    agent: &CompiledAgent,
+    start: AgentPose,
+    planned: &[(u32, Vec<Position>)],
+) -> Result<(Vec<ToioCommand>, Vec<Position>), ScheduleError> {
+    // Finished agents stay on their last cell
+    let at = |cells: &[Position], t: usize| cells[t.min(cells.len() - 1)];
+    let horizon = planned.iter().map(|(_, c)| c.len() - 1).max().unwrap_or(0);
+
+    // First planned agent hit by moving from `from` to `to` during step `t`
+    let blocker = |t: usize, from: Position, to: Position| {
+        planned
+            .iter()
+            .find(|(_, cells)| {
+                at(cells, t + 1) == to
+                    || (from != to && at(cells, t) == to && at(cells, t + 1) == from)
+            })
+            .map(|(id, _)| *id)
+    };
+
+    let poses = agent.path(start)?;
+    let n = agent.commands.len();
+
+    // The final cell must stay clear from the arrival step on: find the
+    // first step from which no planned agent enters it again
+    let final_cell = poses[n].position();
+    let (mut final_clear, mut final_blocker) = (0, None);
+    for (id, cells) in planned {
+        let clear = if cells[cells.len() - 1] == final_cell {
+            usize::MAX
+        } else {
+            cells
+                .iter()
+                .rposition(|&c| c == final_cell)
+                .map_or(0, |u| u + 1)
+        };
+        if clear > final_clear {
+            (final_clear, final_blocker) = (clear, Some(*id));
+        }
+    }
+
+    let mut commands = Vec::with_capacity(n);
+    let mut cells = Vec::with_capacity(n + 1);
+    cells.push(start.position());
+    let mut i = 0;
+    while i < n || cells.len() <= horizon {
+        let t = cells.len() - 1;
+        let from = poses[i].position();
+        let blocked = if i < n {
+            let to = poses[i + 1].position();
+            let too_early = i + 1 == n && t + 1 < final_clear;
+            blocker(t, from, to).or(final_blocker.filter(|_| too_early))
+        } else {
+            // Done: only check that nobody runs into it
+            None
+        };
+
+        match blocked {
+            None if i < n => {
+                commands.push(agent.commands[i].clone());
+                cells.push(poses[i + 1].position());
+                i += 1;
+            }
+            _ => {
+                let stuck = blocker(t, from, from);
+                if stuck.is_some() || t >= horizon {
+                    return Err(Deadlock {
+                        agent_id: agent.id,
+                        blocked_by: stuck.or(blocked).expect("some agent blocks the move"),
+                        step: t,
+                        position: from,
+                    }
+                    .into());
+                }
+                if i < n {
+                    commands.push(ToioCommand::wait());
+                }
+                cells.push(from);
+            }
+        }
+    }
+
+    cells.truncate(commands.len() + 1);
+    Ok((commands, cells))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::collision::detect_collisions;
+    use crate::compile_native;
+    use crate::output::{CommandType, CompileResult, CompiledProgram};
+
+    fn pose(agent_id: u32, x: i32, y: i32, heading: u8) -> AgentPose {
+        AgentPose {
+            agent_id,
+            x,
+            y,
+            heading,
+        }
+    }
+
+    /// Schedule `source` with waits; checks that no collision is left.
+    fn with_waits(
+        source: &str,
+        starts: &[AgentPose],
+        priority: Priority,
+    ) -> Result<Vec<CompiledAgent>, ScheduleError> {
+        let CompileResult::Success { program } = compile_native(source) else {
+            panic!("{} does not compile", source);
+        };
+        let agents = Scheduler::schedule_with_waits(&program.agents, starts, priority)?;
+        let paths = CompiledProgram::from_agents(agents.clone())
+            .agent_paths(starts)
+            .unwrap();
+        assert!(detect_collisions(&paths).is_empty());
+        Ok(agents)
+    }
+
+    /// Commands as a string, with `w` for waits.
+    fn letters(agent: &CompiledAgent) -> String {
+        agent
+            .commands
+            .iter()
+            .map(|c| match c.command_type {
+                CommandType::Straight => 's',
+                CommandType::RotateRight => 'r',
+                CommandType::RotateLeft => 'l',
+                CommandType::Wait => 'w',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_wait_at_crossing() {
+        // Both reach (1, 1) at step 0
+        let starts = [pose(0, 0, 1, 1), pose(1, 1, 0, 2)];
+        let agents = with_waits("0: ss\n1: ss", &starts, Priority::LowId).unwrap();
+        assert_eq!(letters(&agents[0]), "ss");
+        assert_eq!(letters(&agents[1]), "wss");
+
+        let agents = with_waits("0: ss\n1: ss", &starts, Priority::HighId).unwrap();
+        assert_eq!(letters(&agents[0]), "wss");
+        assert_eq!(letters(&agents[1]), "ss");
+    }
+
+    #[test]
+    fn test_longest_goes_first() {
+        let starts = [pose(0, 0, 1, 1), pose(1, 1, 0, 2)];
+        let agents = with_waits("0: ss\n1: sss", &starts, Priority::Longest).unwrap();
+        assert_eq!(letters(&agents[0]), "wss");
+        assert_eq!(letters(&agents[1]), "sss");
+    }
+
+    #[test]
+    fn test_final_cell_waits_until_clear() {
+        // Agent 0 crosses (2, 0) at step 1; agent 1 must arrive after that
+        let starts = [pose(0, 0, 0, 1), pose(1, 2, 1, 0)];
+        let agents = with_waits("0: sss\n1: s", &starts, Priority::LowId).unwrap();
+        assert_eq!(letters(&agents[1]), "wws");
+    }
+
+    #[test]
+    fn test_unplaced_agents_unchanged() {
+        let starts = [pose(0, 0, 1, 1), pose(1, 1, 0, 2)];
+        let agents = with_waits("0: ss\n1: ss\n2: ss", &starts, Priority::LowId).unwrap();
+        assert_eq!(letters(&agents[2]), "ss");
+        assert_eq!(agents[2].id, 2);
+    }
+
+    #[test]
+    fn test_deadlock() {
+        // Agent 0 stops on the cell agent 1 has to enter
+        let starts = [pose(0, 0, 0, 1), pose(1, 2, 0, 3)];
+        let error = with_waits("0: s\n1: s", &starts, Priority::LowId).unwrap_err();
+        let deadlock = Deadlock {
+            agent_id: 1,
+            blocked_by: 0,
+            step: 1,
+            position: Position::new(2, 0),
+        };
+        assert_eq!(error, ScheduleError::Deadlock(deadlock));
+        let error = error.to_compile_error(Span::new(5, 9, 2, 1));
+        assert_eq!(error.code, Some(ErrorCode::E014));
+        assert_eq!(
+            error.message,
+            "[E014] Agent 1 cannot avoid agent 0 by waiting at (2, 0) at step 1"
+        );
+    }
+
+    #[test]
+    fn test_idle_agent_in_the_way() {
+        // Agent 1 has no commands and sits on agent 0's path
+        let starts = [pose(0, 0, 0, 1), pose(1, 1, 0, 0)];
+        let error = with_waits("0: ss\n1: ", &starts, Priority::LowId).unwrap_err();
+        let ScheduleError::Deadlock(deadlock) = error else {
+            panic!("expected a deadlock, got {:?}", error);
+        };
+        assert_eq!((deadlock.agent_id, deadlock.step), (1, 0));
+    }
+
+    #[test]
+    fn test_schedule_pose_overflow() {
+        let starts = [pose(0, 0, i32::MIN, 0)];
+        let error = with_waits("0: s", &starts, Priority::LowId).unwrap_err();
+        assert_eq!(error.agent_id(), 0);
+        let error = error.to_compile_error(Span::new(0, 4, 1, 1));
+        assert_eq!(error.code, Some(ErrorCode::E018));
+        assert_eq!(
+            error.message,
+            "[E018] Agent 0 moves to (0, -2147483649) at step 0, outside the grid"
+        );
+    }
 
     #[test]
     fn test_single_agent() {
@@ -0,0 +1,296 @@
+//! Physical-time timelines.
+//!
+//! The step-indexed timeline assumes every command takes one tick. Real
+//! robots take longer to drive a cell than to turn, so agents running in
+//! lockstep drift apart. [`timed_timeline`] gives each command a duration
+//! from [`Durations`] (globally, or per agent) and lays the commands out in
+//! milliseconds instead.
+//!
+//! Without barriers every agent runs its commands back to back. A barrier at
+//! step `k` makes every agent start its step `k` command together, once all
+//! agents have finished their commands before step `k`.
+
+use crate::output::{CommandType, CompiledAgent, ToioCommand};
+use serde::{Deserialize, Serialize};
+
+/// Duration of each kind of command, in milliseconds.
+///
+/// Missing fields deserialize to their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Durations {
+    /// Time to drive one grid step
+    pub straight_ms: u32,
+    /// Time to turn 90°
+    pub turn_ms: u32,
+    /// Time a wait lasts
+    pub wait_ms: u32,
+}
+
+impl Default for Durations {
+    fn default() -> Self {
+        Self {
+            straight_ms: 500,
+            turn_ms: 250,
+            wait_ms: 500,
+        }
+    }
+}
+
+impl Durations {
+    /// Duration of `command`; multi-step moves and multi-quarter turns take
+    /// proportionally longer.
+    pub fn of(&self, command: &ToioCommand) -> u64 {
+        match command.command_type {
+            CommandType::Straight => {
+                u64::from(self.straight_ms) * u64::from(command.steps.unwrap_or(1))
+            }
+            CommandType::RotateRight | CommandType::RotateLeft => {
+                let turns = (command.turn_degrees() / 90).unsigned_abs();
+                u64::from(self.turn_ms) * u64::from(turns)
+            }
+            CommandType::Wait => u64::from(self.wait_ms),
+        }
+    }
+}
+
+/// Durations for one agent; unset fields fall back to the global ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentDurations {
+    /// Agent ID
+    pub agent_id: u32,
+    /// Time to drive one grid step, if not the global one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub straight_ms: Option<u32>,
+    /// Time to turn 90°, if not the global one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn_ms: Option<u32>,
+    /// Time a wait lasts, if not the global one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait_ms: Option<u32>,
+}
+
+/// Options for [`timed_timeline`].
+///
+/// Missing fields deserialize to their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimingOptions {
+    /// Durations for every agent
+    pub durations: Durations,
+    /// Per-agent overrides
+    pub agents: Vec<AgentDurations>,
+    /// Steps at which all agents wait for each other before going on
+    pub barriers: Vec<usize>,
+}
+
+impl TimingOptions {
+    /// Durations that apply to `agent_id`.
+    pub fn durations_for(&self, agent_id: u32) -> Durations {
+        let global = self.durations;
+        match self.agents.iter().find(|a| a.agent_id == agent_id) {
+            Some(agent) => Durations {
+                straight_ms: agent.straight_ms.unwrap_or(global.straight_ms),
+                turn_ms: agent.turn_ms.unwrap_or(global.turn_ms),
+                wait_ms: agent.wait_ms.unwrap_or(global.wait_ms),
+            },
+            None => global,
+        }
+    }
+}
+
+/// An agent's command with its start and end time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimedCommand {
+    /// Agent ID
+    pub agent_id: u32,
+    /// Index of the command (0-based, matches the step-indexed timeline)
+    pub step: usize,
+    /// Command to execute
+    pub command: ToioCommand,
+    /// Start time in milliseconds
+    pub start_ms: u64,
+    /// End time in milliseconds
+    pub end_ms: u64,
+}
+
+/// Commands starting at the same time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimedEntry {
+    /// Start time in milliseconds
+    pub time_ms: u64,
+    /// Commands starting at `time_ms`, in agent order
+    pub agent_commands: Vec<TimedCommand>,
+}
+
+/// Lay every agent's commands out in physical time.
+///
+/// Entries are ordered by time; an agent that has run out of commands (or is
+/// held at a barrier) has no entry until it moves again.
+pub fn timed_timeline(agents: &[CompiledAgent], options: &TimingOptions) -> Vec<TimedEntry> {
+    let max_steps = agents.iter().map(|a| a.commands.len()).max().unwrap_or(0);
+    let mut barriers: Vec<usize> = options
+        .barriers
+        .iter()
+        .copied()
+        .filter(|&step| step > 0 && step < max_steps)
+        .collect();
+    barriers.sort_unstable();
+    barriers.dedup();
+    barriers.push(max_steps);
+
+    let durations: Vec<Durations> = agents
+        .iter()
+        .map(|agent| options.durations_for(agent.id))
+        .collect();
+    let mut commands = Vec::new();
+    let mut segment_start = 0;
+    let mut first_step = 0;
+    for end_step in barriers {
+        let mut segment_end = segment_start;
+        for (agent, durations) in agents.iter().zip(&durations) {
+            let mut time = segment_start;
+            let len = agent.commands.len();
+            let steps = first_step.min(len)..end_step.min(len);
+            for (command, step) in agent.commands[steps.clone()].iter().zip(steps) {
+                let end = time + durations.of(command);
+                commands.push(TimedCommand {
+                    agent_id: agent.id,
+                    step,
+                    command: command.clone(),
+                    start_ms: time,
+                    end_ms: end,
+                });
+                time = end;
+            }
+            segment_end = segment_end.max(time);
+        }
+        segment_start = segment_end;
+        first_step = end_step;
+    }
+
+    // Stable: agents keep their order among commands starting together
+    commands.sort_by_key(|c| c.start_ms);
+    let mut timeline: Vec<TimedEntry> = Vec::new();
+    for command in commands {
+        match timeline.last_mut() {
+            Some(entry) if entry.time_ms == command.start_ms => entry.agent_commands.push(command),
+            _ => timeline.push(TimedEntry {
+                time_ms: command.start_ms,
+                agent_commands: vec![command],
+            }),
+        }
+    }
+    timeline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_program;
+
+    fn agents(source: &str) -> Vec<CompiledAgent> {
+        compile_program(source).agents
+    }
+
+    /// `(time_ms, [(agent_id, step, end_ms)])` of a timeline entry.
+    type Summary = (u64, Vec<(u32, usize, u64)>);
+
+    fn summary(timeline: &[TimedEntry]) -> Vec<Summary> {
+        timeline
+            .iter()
+            .map(|entry| {
+                let commands = entry
+                    .agent_commands
+                    .iter()
+                    .map(|c| (c.agent_id, c.step, c.end_ms))
+                    .collect();
+                (entry.time_ms, commands)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_default_durations() {
+        let timeline = timed_timeline(&agents("0: srs\n1: rs"), &TimingOptions::default());
+        assert_eq!(
+            summary(&timeline),
+            vec![
+                (0, vec![(0, 0, 500), (1, 0, 250)]),
+                (250, vec![(1, 1, 750)]),
+                (500, vec![(0, 1, 750)]),
+                (750, vec![(0, 2, 1250)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_agent_overrides() {
+        let options = TimingOptions {
+            durations: Durations {
+                straight_ms: 1000,
+                ..Durations::default()
+            },
+            agents: vec![AgentDurations {
+                agent_id: 1,
+                straight_ms: None,
+                turn_ms: Some(100),
+                wait_ms: None,
+            }],
+            ..TimingOptions::default()
+        };
+        assert_eq!(
+            options.durations_for(1),
+            Durations {
+                straight_ms: 1000,
+                turn_ms: 100,
+                wait_ms: 500,
+            }
+        );
+        let timeline = timed_timeline(&agents("0: rs\n1: rs"), &options);
+        assert_eq!(
+            summary(&timeline),
+            vec![
+                (0, vec![(0, 0, 250), (1, 0, 100)]),
+                (100, vec![(1, 1, 1100)]),
+                (250, vec![(0, 1, 1250)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_barriers() {
+        let options = TimingOptions {
+            barriers: vec![1, 1, 0, 9],
+            ..TimingOptions::default()
+        };
+        // Agent 1 turns quickly but waits for agent 0's move before step 1
+        let timeline = timed_timeline(&agents("0: ss\n1: rr"), &options);
+        assert_eq!(
+            summary(&timeline),
+            vec![
+                (0, vec![(0, 0, 500), (1, 0, 250)]),
+                (500, vec![(0, 1, 1000), (1, 1, 750)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compacted_commands() {
+        let commands = [
+            ToioCommand {
+                steps: Some(3),
+                ..ToioCommand::straight()
+            },
+            ToioCommand {
+                angle: Some(180),
+                ..ToioCommand::rotate_right()
+            },
+            ToioCommand::rotate_left(),
+            ToioCommand::wait(),
+        ];
+        let durations = Durations::default();
+        let times: Vec<u64> = commands.iter().map(|c| durations.of(c)).collect();
+        assert_eq!(times, vec![1500, 500, 250, 500]);
+    }
+}
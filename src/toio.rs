@@ -0,0 +1,563 @@
+//! BLE payloads for the toio Core Cube.
+//!
+//! [`encode_program`] turns each agent's commands into the raw bytes written
+//! to the cube's motor control characteristic ([`MOTOR_CHARACTERISTIC`]), one
+//! payload per write. Two encodings are available:
+//!
+//! - [`ToioMode::Timed`]: motor control with a specified duration (control
+//!   type `0x02`). `s` drives both motors forward, turns drive them in
+//!   opposite directions, waits stop them, each for a configured time.
+//! - [`ToioMode::Target`]: motor control with a target position (control type
+//!   `0x03`). Each command becomes the absolute mat position and angle the
+//!   cube should reach, so the cube corrects its own drift.
+//!
+//! Grid cells map to mat coordinates through `origin` (the mat point of cell
+//! `(0, 0)`) and `step_distance`. Mat `y` grows downwards like grid `y`, and
+//! mat angles count clockwise from the positive `x` axis, so grid north is
+//! 270°. [`mat_targets`] gives the same mapping as JSON-friendly
+//! [`MatTarget`]s, and rejects paths that leave the mat's [`MatBounds`].
+
+use crate::error::ToioError;
+use crate::output::{AgentPose, CommandType, CompiledProgram, ToioCommand};
+use serde::{Deserialize, Serialize};
+
+/// UUID of the motor control characteristic the payloads are written to.
+pub const MOTOR_CHARACTERISTIC: &str = "10b20102-5b3b-4571-9508-cf3efcd7bbae";
+
+/// Control type: motor control with specified duration.
+const TIMED_CONTROL: u8 = 0x02;
+/// Control type: motor control with target specified.
+const TARGET_CONTROL: u8 = 0x03;
+const LEFT_MOTOR: u8 = 0x01;
+const RIGHT_MOTOR: u8 = 0x02;
+const FORWARD: u8 = 0x01;
+const BACKWARD: u8 = 0x02;
+/// Longest duration a single timed payload can hold, in 10 ms units.
+const MAX_DURATION: u64 = 0xFF;
+/// Slowest and fastest speed the cube accepts for driving.
+const SPEED_RANGE: std::ops::RangeInclusive<u8> = 10..=115;
+/// Shortest and longest `step_ms`/`turn_ms`, in milliseconds.
+const DURATION_RANGE: std::ops::RangeInclusive<u32> = 10..=60_000;
+
+/// How commands are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToioMode {
+    /// Motor control with a duration per command (default)
+    #[default]
+    Timed,
+    /// Motor control towards an absolute mat position and angle
+    Target,
+}
+
+/// Options for [`encode_program`].
+///
+/// Missing fields deserialize to their defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToioOptions {
+    /// Timed or target-position control
+    pub mode: ToioMode,
+    /// Motor speed indication value (10..=115); the maximum speed in target mode
+    pub speed: u8,
+    /// Time to drive one grid step at `speed`, in milliseconds (timed mode);
+    /// a wait also takes this long
+    pub step_ms: u32,
+    /// Time to turn 90° at `speed`, in milliseconds (timed mode)
+    pub turn_ms: u32,
+    /// Mat units per grid step (target mode)
+    pub step_distance: u16,
+    /// Mat position of grid cell `(0, 0)` (target mode)
+    pub origin: [u16; 2],
+    /// Mat area the cubes must stay on (target mode)
+    pub mat: MatBounds,
+    /// Start poses; other agents start at grid `(0, 0)` facing north
+    pub start_poses: Vec<AgentPose>,
+}
+
+impl Default for ToioOptions {
+    fn default() -> Self {
+        Self {
+            mode: ToioMode::Timed,
+            speed: 50,
+            step_ms: 500,
+            turn_ms: 250,
+            step_distance: 40,
+            origin: [250, 250],
+            mat: MatBounds::default(),
+            start_poses: Vec::new(),
+        }
+    }
+}
+
+/// Readable area of a play mat, in mat units (inclusive).
+///
+/// The default is the toio collection play mat (ring side), 45..=455 on both
+/// axes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatBounds {
+    /// Leftmost mat `x` on the mat
+    pub min_x: u16,
+    /// Topmost mat `y` on the mat
+    pub min_y: u16,
+    /// Rightmost mat `x` on the mat
+    pub max_x: u16,
+    /// Bottommost mat `y` on the mat
+    pub max_y: u16,
+}
+
+impl Default for MatBounds {
+    fn default() -> Self {
+        Self {
+            min_x: 45,
+            min_y: 45,
+            max_x: 455,
+            max_y: 455,
+        }
+    }
+}
+
+impl MatBounds {
+    /// Check if a mat position lies on the mat.
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        (i64::from(self.min_x)..=i64::from(self.max_x)).contains(&x)
+            && (i64::from(self.min_y)..=i64::from(self.max_y)).contains(&y)
+    }
+}
+
+/// Absolute mat pose a cube moves to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatTarget {
+    /// Mat `x` coordinate
+    pub x: u16,
+    /// Mat `y` coordinate
+    pub y: u16,
+    /// Degrees clockwise from the mat's positive `x` axis (0..360)
+    pub angle: u16,
+}
+
+/// Mat poses for one cube.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentTargets {
+    /// Agent ID
+    pub agent_id: u32,
+    /// Pose to place the cube at before it starts
+    pub start: MatTarget,
+    /// Pose after each command, one per command (a wait keeps the pose)
+    pub targets: Vec<MatTarget>,
+}
+
+/// Payloads for one cube.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentPayloads {
+    /// Agent ID
+    pub agent_id: u32,
+    /// Motor control writes, in order
+    pub payloads: Vec<Vec<u8>>,
+}
+
+/// Encode every agent's commands as motor control payloads.
+///
+/// In timed mode a command longer than 2.55 s (the longest duration one
+/// payload holds) is split into several payloads. In target mode each
+/// command gives one payload, and its control identification value is the
+/// command index modulo 256.
+pub fn encode_program(
+    program: &CompiledProgram,
+    options: &ToioOptions,
+) -> Result<Vec<AgentPayloads>, ToioError> {
+    if !SPEED_RANGE.contains(&options.speed) {
+        return Err(ToioError::InvalidOption(format!(
+            "speed {} out of range (10..115)",
+            options.speed
+        )));
+    }
+    for (name, ms) in [("step_ms", options.step_ms), ("turn_ms", options.turn_ms)] {
+        if !DURATION_RANGE.contains(&ms) {
+            return Err(ToioError::InvalidOption(format!(
+                "{} {} out of range (10..60000)",
+                name, ms
+            )));
+        }
+    }
+
+    let targets = match options.mode {
+        ToioMode::Timed => Vec::new(),
+        ToioMode::Target => mat_targets(program, options)?,
+    };
+    let agents = program.agents.iter().enumerate().map(|(i, agent)| {
+        let mut payloads = Vec::with_capacity(agent.commands.len());
+        for (index, command) in agent.commands.iter().enumerate() {
+            match options.mode {
+                ToioMode::Timed => payloads.extend(timed(command, options)),
+                ToioMode::Target => match command.command_type {
+                    // Waiting needs no target
+                    CommandType::Wait => payloads.extend(timed(command, options)),
+                    _ => payloads.push(target(index, targets[i].targets[index], options)),
+                },
+            }
+        }
+        AgentPayloads {
+            agent_id: agent.id,
+            payloads,
+        }
+    });
+    Ok(agents.collect())
+}
+
+/// Map every agent's path onto absolute mat poses.
+///
+/// Each agent starts at its pose in `options.start_poses` (grid `(0, 0)`
+/// facing north otherwise). Returns [`ToioError::OutOfRange`] for the first
+/// pose, start included, that lies outside `options.mat`.
+pub fn mat_targets(
+    program: &CompiledProgram,
+    options: &ToioOptions,
+) -> Result<Vec<AgentTargets>, ToioError> {
+    let mat = options.mat;
+    if mat.min_x > mat.max_x || mat.min_y > mat.max_y {
+        return Err(ToioError::InvalidOption(format!(
+            "empty mat bounds ({}..{}, {}..{})",
+            mat.min_x, mat.max_x, mat.min_y, mat.max_y
+        )));
+    }
+
+    let paths = program
+        .agent_paths(&options.start_poses)
+        .map_err(|overflow| {
+            // Off the grid is off the mat too
+            let (x, y) = mat_position(overflow.x, overflow.y, options);
+            ToioError::OutOfRange {
+                agent_id: overflow.agent_id,
+                step: Some(overflow.step),
+                x,
+                y,
+            }
+        })?;
+    paths
+        .iter()
+        .map(|path| {
+            let mut poses = path
+                .iter()
+                .enumerate()
+                .map(|(index, &pose)| mat_pose(index.checked_sub(1), pose, options));
+            let start = poses.next().expect("paths start with the start pose")?;
+            Ok(AgentTargets {
+                agent_id: path[0].agent_id,
+                start,
+                targets: poses.collect::<Result<_, _>>()?,
+            })
+        })
+        .collect()
+}
+
+/// Mat pose of a grid `pose` reached at `step` (`None` for the start).
+fn mat_pose(
+    step: Option<usize>,
+    pose: AgentPose,
+    options: &ToioOptions,
+) -> Result<MatTarget, ToioError> {
+    let (x, y) = mat_position(i64::from(pose.x), i64::from(pose.y), options);
+    if !options.mat.contains(x, y) {
+        return Err(ToioError::OutOfRange {
+            agent_id: pose.agent_id,
+            step,
+            x,
+            y,
+        });
+    }
+    Ok(MatTarget {
+        // In range: the mat bounds are u16
+        x: x as u16,
+        y: y as u16,
+        angle: (270 + 90 * u16::from(pose.heading)) % 360,
+    })
+}
+
+/// Mat position of grid cell `(x, y)`.
+///
+/// Grid coordinates stay within a few times the `i32` range, so the result
+/// cannot overflow.
+fn mat_position(x: i64, y: i64, options: &ToioOptions) -> (i64, i64) {
+    let distance = i64::from(options.step_distance);
+    (
+        i64::from(options.origin[0]) + x * distance,
+        i64::from(options.origin[1]) + y * distance,
+    )
+}
+
+/// Timed payloads for `command`.
+fn timed(command: &ToioCommand, options: &ToioOptions) -> Vec<Vec<u8>> {
+    let speed = options.speed;
+    let (left, right, speed, ms) = match command.command_type {
+        CommandType::Straight => {
+            let steps = u64::from(command.steps.unwrap_or(1));
+            (FORWARD, FORWARD, speed, u64::from(options.step_ms) * steps)
+        }
+        CommandType::RotateRight | CommandType::RotateLeft => {
+            let turns = command.turn_degrees() / 90;
+            let ms = u64::from(options.turn_ms) * u64::from(turns.unsigned_abs());
+            if turns >= 0 {
+                (FORWARD, BACKWARD, speed, ms)
+            } else {
+                (BACKWARD, FORWARD, speed, ms)
+            }
+        }
+        CommandType::Wait => (FORWARD, FORWARD, 0, u64::from(options.step_ms)),
+    };
+
+    // Round to 10 ms units, then split into payload-sized pieces
+    let mut units = (ms + 5) / 10;
+    let mut payloads = Vec::new();
+    while units > 0 {
+        let duration = units.min(MAX_DURATION);
+        units -= duration;
+        payloads.push(vec![
+            TIMED_CONTROL,
+            LEFT_MOTOR,
+            left,
+            speed,
+            RIGHT_MOTOR,
+            right,
+            speed,
+            duration as u8,
+        ]);
+    }
+    payloads
+}
+
+/// Target payload driving to `pose`.
+fn target(index: usize, pose: MatTarget, options: &ToioOptions) -> Vec<u8> {
+    let mut payload = vec![
+        TARGET_CONTROL,
+        (index % 256) as u8,
+        0x00, // timeout: cube default (10 s)
+        0x00, // movement type: move while rotating
+        options.speed,
+        0x00, // speed change type: constant
+        0x00, // reserved
+    ];
+    payload.extend(pose.x.to_le_bytes());
+    payload.extend(pose.y.to_le_bytes());
+    // Absolute angle (angle type 0 in the top three bits)
+    payload.extend(pose.angle.to_le_bytes());
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_program;
+
+    fn encode(source: &str, options: &ToioOptions) -> Result<Vec<AgentPayloads>, ToioError> {
+        encode_program(&compile_program(source), options)
+    }
+
+    #[test]
+    fn test_timed_payloads() {
+        let agents = encode("0: srl", &ToioOptions::default()).unwrap();
+        assert_eq!(
+            agents[0].payloads,
+            vec![
+                vec![0x02, 0x01, 0x01, 50, 0x02, 0x01, 50, 50],
+                vec![0x02, 0x01, 0x01, 50, 0x02, 0x02, 50, 25],
+                vec![0x02, 0x01, 0x02, 50, 0x02, 0x01, 50, 25],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timed_wait_and_split() {
+        let options = ToioOptions {
+            step_ms: 3000,
+            ..ToioOptions::default()
+        };
+        let agents = encode("EXTENSION=WAIT\n0: w", &options).unwrap();
+        assert_eq!(
+            agents[0].payloads,
+            vec![
+                vec![0x02, 0x01, 0x01, 0, 0x02, 0x01, 0, 255],
+                vec![0x02, 0x01, 0x01, 0, 0x02, 0x01, 0, 45],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_target_payloads() {
+        let options = ToioOptions {
+            mode: ToioMode::Target,
+            ..ToioOptions::default()
+        };
+        let agents = encode("0: sr\n1: l", &options).unwrap();
+        // (0, -1) facing north: x = 250, y = 210, 270°
+        assert_eq!(
+            agents[0].payloads[0],
+            vec![0x03, 0, 0, 0, 50, 0, 0, 0xFA, 0x00, 0xD2, 0x00, 0x0E, 0x01]
+        );
+        // Turned east in place: 0°
+        assert_eq!(
+            agents[0].payloads[1],
+            vec![0x03, 1, 0, 0, 50, 0, 0, 0xFA, 0x00, 0xD2, 0x00, 0x00, 0x00]
+        );
+        // Agent 1 turned west: 180°
+        assert_eq!(agents[1].payloads[0][11..], [0xB4, 0x00]);
+    }
+
+    #[test]
+    fn test_target_out_of_range() {
+        let options = ToioOptions {
+            mode: ToioMode::Target,
+            origin: [250, 60],
+            ..ToioOptions::default()
+        };
+        assert_eq!(
+            encode("0: s", &options),
+            Err(ToioError::OutOfRange {
+                agent_id: 0,
+                step: Some(0),
+                x: 250,
+                y: 20,
+            })
+        );
+    }
+
+    #[test]
+    fn test_target_far_out_of_range() {
+        // 250 + 40000 * 65535 does not fit in an i32
+        let options = ToioOptions {
+            step_distance: 65535,
+            start_poses: vec![AgentPose {
+                agent_id: 0,
+                x: 40000,
+                y: 0,
+                heading: 0,
+            }],
+            ..ToioOptions::default()
+        };
+        let program = compile_program("0: s");
+        assert_eq!(
+            mat_targets(&program, &options),
+            Err(ToioError::OutOfRange {
+                agent_id: 0,
+                step: None,
+                x: 2_621_400_250,
+                y: 250,
+            })
+        );
+
+        // A move off the grid is off the mat
+        let options = ToioOptions {
+            start_poses: vec![AgentPose {
+                agent_id: 0,
+                x: 0,
+                y: i32::MIN,
+                heading: 0,
+            }],
+            ..ToioOptions::default()
+        };
+        assert_eq!(
+            mat_targets(&program, &options),
+            Err(ToioError::OutOfRange {
+                agent_id: 0,
+                step: Some(0),
+                x: 250,
+                y: 250 + 40 * (i64::from(i32::MIN) - 1),
+            })
+        );
+    }
+
+    #[test]
+    fn test_mat_targets() {
+        let options = ToioOptions {
+            start_poses: vec![AgentPose {
+                agent_id: 0,
+                x: 1,
+                y: 0,
+                heading: 1,
+            }],
+            ..ToioOptions::default()
+        };
+        let program = compile_program("EXTENSION=WAIT\n0: swr");
+        let agents = mat_targets(&program, &options).unwrap();
+        let at = |x, y, angle| MatTarget { x, y, angle };
+        assert_eq!(agents[0].start, at(290, 250, 0));
+        assert_eq!(
+            agents[0].targets,
+            vec![at(330, 250, 0), at(330, 250, 0), at(330, 250, 90)]
+        );
+    }
+
+    #[test]
+    fn test_mat_bounds() {
+        let options = ToioOptions {
+            mode: ToioMode::Target,
+            ..ToioOptions::default()
+        };
+        // 250 - 5 * 40 = 50 is on the mat, 10 is not
+        assert!(encode("0: sssss", &options).is_ok());
+        assert!(matches!(
+            encode("0: ssssss", &options),
+            Err(ToioError::OutOfRange {
+                step: Some(5),
+                y: 10,
+                ..
+            })
+        ));
+        // The start pose is checked too
+        let offset = ToioOptions {
+            origin: [20, 250],
+            ..options.clone()
+        };
+        assert!(matches!(
+            encode("0: r", &offset),
+            Err(ToioError::OutOfRange {
+                step: None,
+                x: 20,
+                ..
+            })
+        ));
+        let empty = ToioOptions {
+            mat: MatBounds {
+                min_x: 300,
+                max_x: 200,
+                ..MatBounds::default()
+            },
+            ..options
+        };
+        assert!(matches!(
+            encode("0: s", &empty),
+            Err(ToioError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let slow = ToioOptions {
+            speed: 5,
+            ..ToioOptions::default()
+        };
+        assert!(matches!(
+            encode("0: s", &slow),
+            Err(ToioError::InvalidOption(_))
+        ));
+        let instant = ToioOptions {
+            turn_ms: 0,
+            ..ToioOptions::default()
+        };
+        assert!(matches!(
+            encode("0: r", &instant),
+            Err(ToioError::InvalidOption(_))
+        ));
+        let endless = ToioOptions {
+            step_ms: u32::MAX,
+            ..ToioOptions::default()
+        };
+        assert_eq!(
+            encode("0: s", &endless),
+            Err(ToioError::InvalidOption(
+                "step_ms 4294967295 out of range (10..60000)".to_string()
+            ))
+        );
+    }
+}
@@ -113,6 +113,7 @@ impl fmt::Display for Span {
 /// - [`Straight`](TokenKind::Straight): `s` command
 /// - [`Right`](TokenKind::Right): `r` command
 /// - [`Left`](TokenKind::Left): `l` command
+/// - [`Wait`](TokenKind::Wait): `w` command (with `EXTENSION=WAIT`)
 ///
 /// ## Punctuation
 /// - [`Colon`](TokenKind::Colon): `:`
@@ -159,6 +160,12 @@ pub enum TokenKind {
     /// `l` - Left (rotate 90° counter-clockwise).
     Left,
 
+    /// `w` - Wait (stand still for one step).
+    ///
+    /// Only produced after an `EXTENSION=WAIT` directive; otherwise `w` is an
+    /// [`Ident`](TokenKind::Ident).
+    Wait,
+
     // -------------------------------------------------------------------------
     // Numeric Literals
     // -------------------------------------------------------------------------
@@ -234,6 +241,7 @@ impl TokenKind {
             TokenKind::Straight => "'s'",
             TokenKind::Right => "'r'",
             TokenKind::Left => "'l'",
+            TokenKind::Wait => "'w'",
             TokenKind::Number(_) => "number",
             TokenKind::Colon => "':'",
             TokenKind::LParen => "'('",
@@ -250,7 +258,7 @@ impl TokenKind {
         }
     }
 
-    /// Returns `true` if this token is a command (`s`, `r`, or `l`).
+    /// Returns `true` if this token is a command (`s`, `r`, `l`, or `w`).
     ///
     /// # Example
     ///
@@ -264,7 +272,7 @@ impl TokenKind {
     pub fn is_command(&self) -> bool {
         matches!(
             self,
-            TokenKind::Straight | TokenKind::Right | TokenKind::Left
+            TokenKind::Straight | TokenKind::Right | TokenKind::Left | TokenKind::Wait
         )
     }
 
@@ -294,6 +302,7 @@ impl fmt::Display for TokenKind {
             TokenKind::Straight => write!(f, "s"),
             TokenKind::Right => write!(f, "r"),
             TokenKind::Left => write!(f, "l"),
+            TokenKind::Wait => write!(f, "w"),
             TokenKind::Number(n) => write!(f, "{}", n),
             TokenKind::Colon => write!(f, ":"),
             TokenKind::LParen => write!(f, "("),
@@ -0,0 +1,498 @@
+//! Grid world simulation.
+//!
+//! Runs compiled commands on a Herbert-style board: a rectangular grid of
+//! cells, some of which are walls. `s` moves the robot one cell forward, `r`
+//! and `l` turn it in place. A robot that would walk into a wall or off the
+//! board stays where it is.
+//!
+//! Coordinates are `(x, y)` with `(0, 0)` in the top-left corner; `x` grows to
+//! the right (east) and `y` grows downwards (south).
+
+use crate::output::{CommandType, CompiledProgram, ToioCommand};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Side length of the classic Herbert board.
+pub const HERBERT_SIZE: u32 = 25;
+
+/// A cell on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Position {
+    /// Column (0 = west edge)
+    pub x: i32,
+    /// Row (0 = north edge)
+    pub y: i32,
+}
+
+impl Position {
+    /// Create a position.
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// The neighbouring cell in direction `heading`.
+    pub fn step(self, heading: Heading) -> Self {
+        let (dx, dy) = heading.delta();
+        Self::new(self.x + dx, self.y + dy)
+    }
+}
+
+/// Direction the robot is facing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Heading {
+    /// Up (towards y = 0)
+    #[default]
+    North,
+    /// Right
+    East,
+    /// Down
+    South,
+    /// Left
+    West,
+}
+
+impl Heading {
+    /// Heading after a 90° clockwise turn.
+    pub fn turn_right(self) -> Self {
+        match self {
+            Heading::North => Heading::East,
+            Heading::East => Heading::South,
+            Heading::South => Heading::West,
+            Heading::West => Heading::North,
+        }
+    }
+
+    /// Heading after a 90° counter-clockwise turn.
+    pub fn turn_left(self) -> Self {
+        match self {
+            Heading::North => Heading::West,
+            Heading::West => Heading::South,
+            Heading::South => Heading::East,
+            Heading::East => Heading::North,
+        }
+    }
+
+    /// Clockwise quarter turns from north (0 = north, 1 = east, ...).
+    pub fn quarter_turns(self) -> u8 {
+        match self {
+            Heading::North => 0,
+            Heading::East => 1,
+            Heading::South => 2,
+            Heading::West => 3,
+        }
+    }
+
+    /// Heading `turns` clockwise quarter turns from north (taken modulo 4).
+    pub fn from_quarter_turns(turns: u8) -> Self {
+        match turns % 4 {
+            0 => Heading::North,
+            1 => Heading::East,
+            2 => Heading::South,
+            _ => Heading::West,
+        }
+    }
+
+    /// `(dx, dy)` of one step forward.
+    pub fn delta(self) -> (i32, i32) {
+        match self {
+            Heading::North => (0, -1),
+            Heading::East => (1, 0),
+            Heading::South => (0, 1),
+            Heading::West => (-1, 0),
+        }
+    }
+}
+
+/// Position and heading of a robot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Pose {
+    /// Cell the robot occupies
+    pub position: Position,
+    /// Direction the robot faces
+    pub heading: Heading,
+}
+
+impl Pose {
+    /// Create a pose.
+    pub fn new(x: i32, y: i32, heading: Heading) -> Self {
+        Self {
+            position: Position::new(x, y),
+            heading,
+        }
+    }
+}
+
+/// Robot state after one command, or after one cell of a multi-cell move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepState {
+    /// Step number (0-based, matches the timeline)
+    pub step: usize,
+    /// Command executed at this step
+    pub command: CommandType,
+    /// Position after the command
+    pub position: Position,
+    /// Heading after the command
+    pub heading: Heading,
+    /// Whether the robot changed cells
+    pub moved: bool,
+}
+
+impl StepState {
+    /// Pose after the command.
+    pub fn pose(&self) -> Pose {
+        Pose {
+            position: self.position,
+            heading: self.heading,
+        }
+    }
+}
+
+/// Simulated run of one agent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentTrace {
+    /// Agent ID
+    pub agent_id: u32,
+    /// Pose before the first command
+    pub start: Pose,
+    /// State after each command; a multi-cell move has one per cell it enters
+    pub states: Vec<StepState>,
+}
+
+impl AgentTrace {
+    /// Pose after the last command (the start pose if there are none).
+    pub fn final_pose(&self) -> Pose {
+        self.states.last().map_or(self.start, StepState::pose)
+    }
+}
+
+/// A rectangular board with walls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct World {
+    width: u32,
+    height: u32,
+    walls: HashSet<Position>,
+}
+
+impl World {
+    /// Create an empty board.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            walls: HashSet::new(),
+        }
+    }
+
+    /// Create an empty 25×25 board, as in Herbert.
+    pub fn herbert() -> Self {
+        Self::new(HERBERT_SIZE, HERBERT_SIZE)
+    }
+
+    /// Board width in cells.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Board height in cells.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Place a wall on a cell.
+    pub fn add_wall(&mut self, position: Position) {
+        self.walls.insert(position);
+    }
+
+    /// Whether a cell is a wall.
+    pub fn is_wall(&self, position: Position) -> bool {
+        self.walls.contains(&position)
+    }
+
+    /// Whether a cell lies on the board.
+    pub fn contains(&self, position: Position) -> bool {
+        position.x >= 0
+            && position.y >= 0
+            && (position.x as u32) < self.width
+            && (position.y as u32) < self.height
+    }
+
+    /// Whether a robot can stand on a cell.
+    pub fn is_open(&self, position: Position) -> bool {
+        self.contains(position) && !self.is_wall(position)
+    }
+
+    /// Centre cell facing north, where Herbert starts.
+    pub fn default_start(&self) -> Pose {
+        Pose::new(
+            (self.width / 2) as i32,
+            (self.height / 2) as i32,
+            Heading::North,
+        )
+    }
+
+    /// Apply one command to a pose, returning the new pose and whether the
+    /// robot changed cells.
+    pub fn apply(&self, pose: Pose, command: CommandType) -> (Pose, bool) {
+        match command {
+            CommandType::Straight => {
+                let next = pose.position.step(pose.heading);
+                if self.is_open(next) {
+                    (
+                        Pose {
+                            position: next,
+                            ..pose
+                        },
+                        true,
+                    )
+                } else {
+                    (pose, false)
+                }
+            }
+            CommandType::RotateRight => (
+                Pose {
+                    heading: pose.heading.turn_right(),
+                    ..pose
+                },
+                false,
+            ),
+            CommandType::RotateLeft => (
+                Pose {
+                    heading: pose.heading.turn_left(),
+                    ..pose
+                },
+                false,
+            ),
+            CommandType::Wait => (pose, false),
+        }
+    }
+
+    /// Run a command list from `start`, returning the state after each command.
+    ///
+    /// Accepts expander [`Command`](crate::expander::Command)s as well as [`CommandType`]s.
+    pub fn run<C: Into<CommandType>>(
+        &self,
+        start: Pose,
+        commands: impl IntoIterator<Item = C>,
+    ) -> Vec<StepState> {
+        let mut pose = start;
+        commands
+            .into_iter()
+            .enumerate()
+            .map(|(step, command)| {
+                let command = command.into();
+                let (next, moved) = self.apply(pose, command);
+                pose = next;
+                StepState {
+                    step,
+                    command,
+                    position: pose.position,
+                    heading: pose.heading,
+                    moved,
+                }
+            })
+            .collect()
+    }
+
+    /// Run every agent of a compiled program.
+    ///
+    /// Agents start at their pose in `starts`, or at
+    /// [`default_start`](Self::default_start) if they have none. Agents are
+    /// simulated independently: they do not block each other.
+    pub fn run_program(
+        &self,
+        program: &CompiledProgram,
+        starts: &HashMap<u32, Pose>,
+    ) -> Vec<AgentTrace> {
+        program
+            .agents
+            .iter()
+            .map(|agent| {
+                let start = starts
+                    .get(&agent.id)
+                    .copied()
+                    .unwrap_or_else(|| self.default_start());
+                AgentTrace {
+                    agent_id: agent.id,
+                    start,
+                    states: self.run_toio(start, &agent.commands),
+                }
+            })
+            .collect()
+    }
+
+    /// Run output commands, honouring `angle` on rotations and multi-cell
+    /// `steps` on straight moves.
+    ///
+    /// A multi-cell move records a state for every cell it enters, all with
+    /// the command's step number, so buttons along the way are pressed too.
+    fn run_toio(&self, start: Pose, commands: &[ToioCommand]) -> Vec<StepState> {
+        let mut pose = start;
+        let mut states = Vec::with_capacity(commands.len());
+        for (step, command) in commands.iter().enumerate() {
+            let state = |pose: Pose, moved| StepState {
+                step,
+                command: command.command_type,
+                position: pose.position,
+                heading: pose.heading,
+                moved,
+            };
+            match command.command_type {
+                CommandType::Straight => {
+                    let entered = states.len();
+                    for _ in 0..command.steps.unwrap_or(1) {
+                        let (next, moved) = self.apply(pose, CommandType::Straight);
+                        if !moved {
+                            break;
+                        }
+                        pose = next;
+                        states.push(state(pose, true));
+                    }
+                    if states.len() == entered {
+                        states.push(state(pose, false));
+                    }
+                }
+                CommandType::RotateRight | CommandType::RotateLeft => {
+                    // Angles are multiples of 90°
+                    let turns = command.turn_degrees().div_euclid(90);
+                    let heading = i32::from(pose.heading.quarter_turns()) + turns;
+                    pose.heading = Heading::from_quarter_turns(heading.rem_euclid(4) as u8);
+                    states.push(state(pose, false));
+                }
+                CommandType::Wait => states.push(state(pose, false)),
+            }
+        }
+        states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_program;
+    use crate::expander::Command;
+
+    fn commands(s: &str) -> Vec<Command> {
+        s.chars()
+            .map(|c| match c {
+                's' => Command::Straight,
+                'r' => Command::Right,
+                _ => Command::Left,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_turns() {
+        let h = Heading::North;
+        assert_eq!(h.turn_right().turn_right().turn_right().turn_right(), h);
+        assert_eq!(h.turn_left(), Heading::West);
+        assert_eq!(h.turn_right().turn_left(), h);
+        for turns in 0..4 {
+            assert_eq!(Heading::from_quarter_turns(turns).quarter_turns(), turns);
+        }
+        assert_eq!(Heading::from_quarter_turns(7), Heading::West);
+    }
+
+    #[test]
+    fn test_run_moves_and_turns() {
+        let world = World::new(5, 5);
+        let states = world.run(Pose::new(2, 2, Heading::North), commands("srs"));
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[0].position, Position::new(2, 1));
+        assert!(states[0].moved);
+        assert_eq!(states[1].heading, Heading::East);
+        assert!(!states[1].moved);
+        assert_eq!(states[2].position, Position::new(3, 1));
+        assert_eq!(states[2].step, 2);
+    }
+
+    #[test]
+    fn test_board_edge_blocks() {
+        let world = World::new(3, 3);
+        let states = world.run(Pose::new(1, 1, Heading::North), commands("sss"));
+        let moved: Vec<bool> = states.iter().map(|s| s.moved).collect();
+        assert_eq!(moved, vec![true, false, false]);
+        assert_eq!(states[2].position, Position::new(1, 0));
+    }
+
+    #[test]
+    fn test_wall_blocks() {
+        let mut world = World::new(5, 5);
+        world.add_wall(Position::new(3, 2));
+        let states = world.run(Pose::new(2, 2, Heading::East), commands("srsl"));
+        assert!(!states[0].moved);
+        assert_eq!(states[0].position, Position::new(2, 2));
+        assert_eq!(states[2].position, Position::new(2, 3));
+        assert_eq!(states[3].heading, Heading::East);
+    }
+
+    #[test]
+    fn test_wait_keeps_pose() {
+        let world = World::new(3, 3);
+        let start = Pose::new(0, 0, Heading::South);
+        let states = world.run(start, [CommandType::Wait]);
+        assert_eq!(states[0].pose(), start);
+        assert!(!states[0].moved);
+    }
+
+    #[test]
+    fn test_run_program() {
+        let program = compile_program("0: ssrs\n1: l");
+        let world = World::herbert();
+        let starts = HashMap::from([(0, Pose::new(0, 24, Heading::North))]);
+        let traces = world.run_program(&program, &starts);
+
+        assert_eq!(traces.len(), 2);
+        assert_eq!(traces[0].final_pose(), Pose::new(1, 22, Heading::East));
+        // Agent 1 has no start entry and begins at the centre
+        assert_eq!(traces[1].start, Pose::new(12, 12, Heading::North));
+        assert_eq!(traces[1].final_pose(), Pose::new(12, 12, Heading::West));
+    }
+
+    #[test]
+    fn test_multi_cell_straight_stops_at_wall() {
+        let mut world = World::new(5, 1);
+        world.add_wall(Position::new(3, 0));
+        let mut program = compile_program("s");
+        program.agents[0].commands[0].steps = Some(3);
+        let starts = HashMap::from([(0, Pose::new(0, 0, Heading::East))]);
+        let traces = world.run_program(&program, &starts);
+        // One state per cell entered, both for step 0
+        let cells: Vec<_> = traces[0]
+            .states
+            .iter()
+            .map(|s| (s.step, s.position))
+            .collect();
+        assert_eq!(
+            cells,
+            vec![(0, Position::new(1, 0)), (0, Position::new(2, 0))]
+        );
+        assert!(traces[0].states.iter().all(|s| s.moved));
+        assert_eq!(traces[0].final_pose(), Pose::new(2, 0, Heading::East));
+    }
+
+    #[test]
+    fn test_multi_cell_straight_blocked() {
+        let mut world = World::new(5, 1);
+        world.add_wall(Position::new(1, 0));
+        let mut program = compile_program("s");
+        program.agents[0].commands[0].steps = Some(3);
+        let starts = HashMap::from([(0, Pose::new(0, 0, Heading::East))]);
+        let traces = world.run_program(&program, &starts);
+        assert_eq!(traces[0].states.len(), 1);
+        assert!(!traces[0].states[0].moved);
+    }
+
+    #[test]
+    fn test_rotation_angle() {
+        let world = World::herbert();
+        let mut program = compile_program("rl");
+        program.agents[0].commands[0].angle = Some(180);
+        program.agents[0].commands[1].angle = Some(-270);
+        let starts = HashMap::from([(0, Pose::new(0, 0, Heading::North))]);
+        let traces = world.run_program(&program, &starts);
+        assert_eq!(traces[0].states[0].heading, Heading::South);
+        assert_eq!(traces[0].states[1].heading, Heading::West);
+    }
+}
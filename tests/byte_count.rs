@@ -233,6 +233,12 @@ fn test_directive_with_truncate() {
     assert_bytes!(source, 8);
 }
 
+#[test]
+fn test_wait_primitive_counted() {
+    // `w` counts 1 byte, like the other commands
+    assert_bytes!("EXTENSION=WAIT\nf(X):wf(X-1) f(10)", 8);
+}
+
 // =============================================================================
 // Agent ID (Not Counted)
 // =============================================================================
@@ -256,6 +262,13 @@ fn test_agent_with_definitions() {
     assert_bytes!(source, 12); // x+s+s+x+r+x + y+r+r+y+l+y
 }
 
+#[test]
+fn test_shared_definitions_counted_once() {
+    // f+X+X+X+X+X + f+s + f+r + f+l: the shared f counts once for three agents
+    let source = "f(X):XXXX\n0: f(s)\n1: f(r)\n2: f(l)";
+    assert_bytes!(source, 12);
+}
+
 // =============================================================================
 // Complex Examples
 // =============================================================================
@@ -372,3 +385,49 @@ fn test_only_punctuation_error() {
     // Just punctuation without valid program structure
     assert_syntax_error!(":(),+-");
 }
+
+// =============================================================================
+// Scoring
+// =============================================================================
+
+#[test]
+fn test_score_at_or_under_limit() {
+    let score = h2lang::score_native("f(X):sf(X-1) f(10)", 200, 8, true).unwrap();
+    assert_eq!(score.bytes, 8);
+    assert_eq!(score.points, 200);
+    assert_eq!(
+        h2lang::score_native("a:sa a", 200, 8, true).unwrap().points,
+        200
+    );
+}
+
+#[test]
+fn test_score_over_limit() {
+    // 8 bytes against a limit of 6: 120 × 6 / 8 = 90
+    let score = h2lang::score_native("f(X):sf(X-1) f(10)", 120, 6, true).unwrap();
+    assert_eq!(score.points, 90);
+    assert_eq!(score.max_points, 120);
+}
+
+#[test]
+fn test_score_unsolved_and_invalid() {
+    assert_eq!(
+        h2lang::score_native("a:sa a", 100, 10, false)
+            .unwrap()
+            .points,
+        0
+    );
+    assert!(h2lang::score_native("f(X:X", 100, 10, true).is_err());
+    // Parses, but calls an undefined function
+    let error = h2lang::score_native("f(X):sa(X-1) f(10)", 100, 10, true).unwrap_err();
+    assert!(error.contains("[E002]"), "{}", error);
+}
+
+#[test]
+fn test_score_json() {
+    let score = h2lang::score_native("ss", 10, 1, true).unwrap();
+    assert_eq!(
+        serde_json::to_value(score).unwrap(),
+        serde_json::json!({"bytes": 2, "limit": 1, "solved": true, "points": 5, "max_points": 10})
+    );
+}
@@ -1024,12 +1024,9 @@ mod edge_cases {
 
     #[test]
     fn test_macro_shadowing_in_sequence() {
-        // Multiple definitions of same macro - last one wins? Or first?
-        // In HOJ, typically first definition is used
-        // Our implementation might differ - let's test
+        // Same-name collision is forbidden (SPEC §4.2): E011 at the second definition
         let result = compile_to_string("0: a:s a:r a");
-        // This behavior may vary; just ensure it doesn't crash
-        assert!(result.is_ok());
+        assert!(result.unwrap_err().contains("E011"));
     }
 
     #[test]
@@ -0,0 +1,123 @@
+//! Text rendering snapshot tests
+//!
+//! Each test draws a program with `render_text_native` and compares it with
+//! the expected picture, so a change in movement semantics shows up as a
+//! readable diff instead of a changed `srl` string.
+//!
+//! Pictures run north to south. Start cells show the agent ID, end cells an
+//! arrow in the final heading.
+
+use h2lang::output::AgentPose;
+use h2lang::render::{TextOptions, TextStyle};
+use h2lang::render_text_native;
+
+// =============================================================================
+// Test Helpers
+// =============================================================================
+
+/// Render `source` and compare it with `expected`, ignoring the leading newline.
+fn assert_renders(source: &str, options: &TextOptions, expected: &str) {
+    let actual = render_text_native(source, options)
+        .unwrap_or_else(|errors| panic!("{:?} failed to compile: {:?}", source, errors));
+    let expected = expected.strip_prefix('\n').unwrap_or(expected);
+    assert_eq!(
+        actual, expected,
+        "\nsource: {}\nactual:\n{}\nexpected:\n{}",
+        source, actual, expected
+    );
+}
+
+// =============================================================================
+// Single Agent Patterns
+// =============================================================================
+
+/// Square spiral with arms 1, 2, ..., 8
+#[test]
+fn snapshot_square_spiral() {
+    assert_renders(
+        "a(X,N):Xra(sX,N-1) a(s,8)",
+        &TextOptions::default(),
+        "
+..┌─────┐
+..│.....│
+..│.┌─┐.│
+..│.0.│.│
+..│...│.│
+..└───┘.│
+........│
+▲───────┘
+",
+    );
+}
+
+/// Folding recursion: f(A,N) = f(Ar,N-1) A f(Al,N-1)
+#[test]
+fn snapshot_folding_recursion() {
+    assert_renders(
+        "f(A,N):f(Ar,N-1)Af(Al,N-1) f(s,6)",
+        &TextOptions::default(),
+        "
+...........┌┐
+..........┌┴┘
+.........┌0..
+........┌┴┘..
+........│....
+......┌┬┘....
+.....┌┼┘.....
+....┌┴┘......
+....│........
+..┌┬┘........
+..├┘.........
+┌┬▲..........
+└┘...........
+",
+    );
+}
+
+/// The same square in ASCII
+#[test]
+fn snapshot_ascii_square() {
+    let options = TextOptions {
+        style: TextStyle::Ascii,
+        ..TextOptions::default()
+    };
+    assert_renders(
+        "a(X):sa(X-1) b(N):a(3)rb(N-1) b(4)",
+        &options,
+        "
++--+
+|..|
+|..|
+0--+
+",
+    );
+}
+
+// =============================================================================
+// Multiple Agents
+// =============================================================================
+
+/// Two agents whose paths cross; the shared cell shows `*`
+#[test]
+fn snapshot_crossing_agents() {
+    let options = TextOptions {
+        start_poses: vec![AgentPose {
+            agent_id: 1,
+            x: -2,
+            y: -2,
+            heading: 1,
+        }],
+        ..TextOptions::default()
+    };
+    assert_renders(
+        "0: ssss\n1: ssss",
+        &options,
+        "
+..▲..
+..│..
+1─*─▶
+..│..
+..0..
+",
+    );
+}
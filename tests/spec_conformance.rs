@@ -9,12 +9,26 @@
 //! (directives, type checking, etc.) is implemented.
 
 use h2lang::compile_native;
-use h2lang::output::CompileResult;
+use h2lang::output::{CompileResult, CompiledAgent, CompiledProgram};
 
 // =============================================================================
 // Test Helpers
 // =============================================================================
 
+/// Command sequence of an agent as a string of `s`, `r`, `l` and `w`
+fn command_letters(agent: &CompiledAgent) -> String {
+    agent
+        .commands
+        .iter()
+        .map(|c| match c.command_type {
+            h2lang::output::CommandType::Straight => 's',
+            h2lang::output::CommandType::RotateRight => 'r',
+            h2lang::output::CommandType::RotateLeft => 'l',
+            h2lang::output::CommandType::Wait => 'w',
+        })
+        .collect()
+}
+
 /// Compile source and return command sequence as string for agent 0
 fn compile_to_string(source: &str) -> Result<String, String> {
     match compile_native(source) {
@@ -22,17 +36,7 @@ fn compile_to_string(source: &str) -> Result<String, String> {
             if program.agents.is_empty() {
                 return Ok(String::new());
             }
-            let commands: String = program.agents[0]
-                .commands
-                .iter()
-                .map(|c| match c.command_type {
-                    h2lang::output::CommandType::Straight => 's',
-                    h2lang::output::CommandType::RotateRight => 'r',
-                    h2lang::output::CommandType::RotateLeft => 'l',
-                    h2lang::output::CommandType::Wait => 'w',
-                })
-                .collect();
-            Ok(commands)
+            Ok(command_letters(&program.agents[0]))
         }
         CompileResult::Error { errors } => {
             let msg = errors
@@ -45,6 +49,19 @@ fn compile_to_string(source: &str) -> Result<String, String> {
     }
 }
 
+/// Unwrap a successful compile result, panicking with the errors otherwise
+fn expect_success(result: CompileResult, test_id: &str) -> CompiledProgram {
+    match result {
+        CompileResult::Success { program } => program,
+        CompileResult::Error { errors } => panic!("[{}] {:?}", test_id, errors),
+    }
+}
+
+/// Compile source and return the whole program
+fn compile_program(source: &str, test_id: &str) -> CompiledProgram {
+    expect_success(compile_native(source), test_id)
+}
+
 /// Check if error message contains expected error code pattern
 fn error_contains(result: &Result<String, String>, pattern: &str) -> bool {
     match result {
@@ -419,3 +436,576 @@ fn additional_deep_recursion() {
         "Additional-DeepRecursion",
     );
 }
+
+/// Additional: MAX_MEMORY exceeded with ON_LIMIT=ERROR
+/// Input: MAX_MEMORY=5\nssssss
+/// Expected: Error E006
+#[test]
+fn additional_max_memory_exceeded_error() {
+    assert_compile_error("MAX_MEMORY=5\nssssss", "Additional-MaxMemory");
+    let result = compile_to_string("MAX_MEMORY=5\nssssss");
+    assert!(
+        error_contains(&result, "E006"),
+        "[Additional-MaxMemory] Error should mention E006"
+    );
+}
+
+/// Additional: MAX_MEMORY exceeded with ON_LIMIT=TRUNCATE
+/// Input: MAX_MEMORY=20\nON_LIMIT=TRUNCATE\nf(X):XX ssf(ssssss)
+/// Expected: Agent0 = ss (the argument does not fit)
+#[test]
+fn additional_max_memory_exceeded_truncate() {
+    assert_compiles_to(
+        "MAX_MEMORY=20\nON_LIMIT=TRUNCATE\nf(X):XX ssf(ssssss)",
+        "ss",
+        "Additional-MaxMemoryTruncate",
+    );
+}
+
+/// Additional: every error in the program is reported
+/// Input: 0: s)\n1: x\n2: srl@
+/// Expected: Parse error (agent 0), undefined function (agent 1), lexer error (agent 2)
+#[test]
+fn additional_multiple_errors_reported() {
+    match compile_native("0: s)\n1: x\n2: srl@") {
+        CompileResult::Error { errors } => {
+            let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+            assert_eq!(lines, vec![1, 2, 3], "[Additional-MultiError] {:?}", errors);
+        }
+        CompileResult::Success { .. } => panic!("[Additional-MultiError] Expected errors"),
+    }
+}
+
+/// Additional: every specification error carries its machine-readable code
+/// Expected: `code` matches the E-code, and the message uses the `[Exxx]` prefix
+#[test]
+fn additional_structured_error_codes() {
+    use h2lang::error::{ErrorCode, Severity};
+
+    let cases = [
+        ("a", ErrorCode::E001),
+        ("a(s)", ErrorCode::E002),
+        ("f(X,Y):XY f(s)", ErrorCode::E003),
+        ("MAX_STEP=2\nsss", ErrorCode::E004),
+        ("MAX_DEPTH=2\na:sas a", ErrorCode::E005),
+        ("MAX_MEMORY=5\nssssss", ErrorCode::E006),
+        ("f(X):sf(X-1) f(300)", ErrorCode::E007),
+        ("f(X):XX f(3)", ErrorCode::E008),
+        ("MAX_STEPS=10\ns", ErrorCode::E009),
+        ("ON_LIMIT=ABORT\ns", ErrorCode::E009),
+        ("MAX_STEP=0\ns", ErrorCode::E009),
+        ("f(X):Xf(X-1) f(s)", ErrorCode::E010),
+    ];
+
+    for (src, code) in cases {
+        match compile_native(src) {
+            CompileResult::Error { errors } => {
+                let err = &errors[0];
+                assert_eq!(err.code, Some(code), "[Additional-Codes] {:?}", src);
+                assert_eq!(err.severity, Severity::Error);
+                assert!(
+                    err.message.starts_with(&format!("[{}] ", code)),
+                    "[Additional-Codes] {:?}: {}",
+                    src,
+                    err.message
+                );
+            }
+            CompileResult::Success { .. } => panic!("[Additional-Codes] {:?} should fail", src),
+        }
+    }
+}
+
+/// Additional: parse errors keep their byte range and expected/found tokens
+/// Input: 0: s)
+/// Expected: code null, start..end covers ')', found = "')'"
+#[test]
+fn additional_parse_error_fields() {
+    match compile_native("0: s)") {
+        CompileResult::Error { errors } => {
+            let err = &errors[0];
+            assert_eq!(err.code, None);
+            assert_eq!((err.start, err.end), (4, 5));
+            assert_eq!(err.found.as_deref(), Some("')'"));
+            assert!(err.expected.is_some());
+        }
+        CompileResult::Success { .. } => panic!("[Additional-ParseFields] Expected error"),
+    }
+}
+
+/// Additional: duplicate definitions and agent IDs (SPEC §4.2)
+/// Input: x:ss x:rr x / 0: s\n0: r
+/// Expected: E011 / E012 at the second occurrence, related to the first
+#[test]
+fn additional_duplicate_definitions() {
+    use h2lang::error::ErrorCode;
+
+    let cases = [
+        ("x:ss x:rr x", ErrorCode::E011, (1, 6), (1, 1)),
+        ("0: s\n0: r", ErrorCode::E012, (2, 1), (1, 1)),
+    ];
+    for (src, code, at, first) in cases {
+        match compile_native(src) {
+            CompileResult::Error { errors } => {
+                assert_eq!(errors.len(), 1, "[Additional-Duplicate] {:?}", src);
+                let err = &errors[0];
+                assert_eq!(err.code, Some(code));
+                assert_eq!((err.line, err.column), at);
+                let related = err.related.expect("points to the first occurrence");
+                assert_eq!((related.line, related.column), first);
+            }
+            CompileResult::Success { .. } => panic!("[Additional-Duplicate] {:?} should fail", src),
+        }
+    }
+}
+
+/// Additional: call sites are checked statically, even where expansion stops early
+/// Input: a(X):sb(X) a(0) / MAX_STEP=2, ON_LIMIT=TRUNCATE, sss f(s)
+/// Expected: E002 for the undefined `b`/`f`, although neither call is ever expanded
+#[test]
+fn additional_static_checks_on_unreached_calls() {
+    for src in ["a(X):sb(X) a(0)", "MAX_STEP=2\nON_LIMIT=TRUNCATE\nsss f(s)"] {
+        let result = compile_to_string(src);
+        assert!(
+            error_contains(&result, "E002"),
+            "[Additional-Static] {:?}: {:?}",
+            src,
+            result
+        );
+    }
+}
+
+/// Additional: pass-through arguments take the type of the parameter they feed
+/// Input: f(X):g(X) g(Y):sg(Y-1) f(3)
+/// Expected: sss (X is inferred Int through g)
+#[test]
+fn additional_pass_through_typing() {
+    assert_compiles_to(
+        "f(X):g(X) g(Y):sg(Y-1) f(3)",
+        "sss",
+        "Additional-PassThrough",
+    );
+    let result = compile_to_string("f(X):g(X) g(Y):sg(Y-1) f(s)");
+    assert!(
+        error_contains(&result, "E008"),
+        "[Additional-PassThrough] CmdSeq argument for Int parameter should be E008"
+    );
+}
+
+/// Additional: lint warnings accompany a successful compile
+/// Input: x:ss a(X):sa(X-1) a(2)
+/// Expected: ss with one W001 warning for `x`; none with lints disabled
+#[test]
+fn additional_lint_warnings() {
+    use h2lang::compile_native_with_lints;
+    use h2lang::error::{ErrorCode, Severity};
+    use h2lang::lint::LintConfig;
+
+    let source = "x:ss a(X):sa(X-1) a(2)";
+    assert_compiles_to(source, "ss", "Additional-Lint");
+    let program = compile_program(source, "Additional-Lint");
+    assert_eq!(program.warnings.len(), 1, "[Additional-Lint]");
+    assert_eq!(program.warnings[0].code, Some(ErrorCode::W001));
+    assert_eq!(program.warnings[0].severity, Severity::Warning);
+    let result = compile_native_with_lints(source, &LintConfig::none());
+    assert!(expect_success(result, "Additional-Lint")
+        .warnings
+        .is_empty());
+}
+
+/// Additional: opt-in per-step poses in the timeline
+/// Input: 0: ssr / 1: l, agent 1 starting at (3, 3) facing east
+/// Expected: poses for both agents after every step; none without the option
+#[test]
+fn additional_timeline_poses() {
+    use h2lang::output::AgentPose;
+    use h2lang::{compile_native_with_options, CompileOptions};
+
+    let source = "0: ssr\n1: l";
+    let options = CompileOptions {
+        poses: true,
+        start_poses: vec![AgentPose {
+            agent_id: 1,
+            x: 3,
+            y: 3,
+            heading: 1,
+        }],
+        ..CompileOptions::default()
+    };
+    let program = expect_success(
+        compile_native_with_options(source, &options),
+        "Additional-Poses",
+    );
+    let last = program.timeline[2].poses.as_ref().unwrap();
+    assert_eq!((last[0].x, last[0].y, last[0].heading), (0, -2, 1));
+    assert_eq!((last[1].x, last[1].y, last[1].heading), (3, 3, 0));
+
+    let program = compile_program(source, "Additional-Poses");
+    assert!(program.timeline.iter().all(|entry| entry.poses.is_none()));
+}
+
+/// Additional: collisions between placed agents
+/// Input: 0: s / 1: s, facing each other one cell apart; then ON_COLLISION=ERROR
+/// Expected: W005 warning (edge collision); E013 error with the directive
+#[test]
+fn additional_collisions() {
+    use h2lang::error::{ErrorCode, Severity};
+    use h2lang::output::AgentPose;
+    use h2lang::{compile_native_with_options, CompileOptions};
+
+    let options = CompileOptions {
+        start_poses: vec![
+            AgentPose {
+                agent_id: 0,
+                x: 0,
+                y: 0,
+                heading: 1,
+            },
+            AgentPose {
+                agent_id: 1,
+                x: 1,
+                y: 0,
+                heading: 3,
+            },
+        ],
+        ..CompileOptions::default()
+    };
+    let result = compile_native_with_options("0: s\n1: s", &options);
+    let program = expect_success(result, "Additional-Collision");
+    assert_eq!(program.warnings.len(), 1, "[Additional-Collision]");
+    assert_eq!(program.warnings[0].code, Some(ErrorCode::W005));
+    assert_eq!(program.warnings[0].severity, Severity::Warning);
+    match compile_native_with_options("ON_COLLISION=ERROR\n0: s\n1: s", &options) {
+        CompileResult::Error { errors } => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, Some(ErrorCode::E013));
+            assert_eq!(errors[0].line, 2);
+        }
+        CompileResult::Success { .. } => panic!("[Additional-Collision] expected E013"),
+    }
+    // Without start poses nothing can be checked, and each agent says so
+    let program = compile_program("ON_COLLISION=ERROR\n0: s\n1: s", "Additional-Collision");
+    let codes: Vec<_> = program.warnings.iter().map(|w| w.code).collect();
+    assert_eq!(
+        codes,
+        vec![Some(ErrorCode::W006); 2],
+        "[Additional-Collision]"
+    );
+    assert_eq!(program.warnings[1].line, 3);
+    // Without the directive, unplaced agents are expected and not reported
+    let program = compile_program("0: s\n1: s", "Additional-Collision");
+    assert!(program.warnings.is_empty());
+}
+
+/// Additional: an agent that leaves the i32 grid
+/// Input: 0: ss / 1: l, agent 0 starting one cell from the east edge facing east
+/// Expected: E018 at agent 0's line
+#[test]
+fn additional_pose_overflow() {
+    use h2lang::error::ErrorCode;
+    use h2lang::output::AgentPose;
+    use h2lang::{compile_native_with_options, CompileOptions};
+
+    let options = CompileOptions {
+        start_poses: vec![AgentPose {
+            agent_id: 0,
+            x: i32::MAX - 1,
+            y: 0,
+            heading: 1,
+        }],
+        ..CompileOptions::default()
+    };
+    match compile_native_with_options("0: ss\n1: l", &options) {
+        CompileResult::Error { errors } => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, Some(ErrorCode::E018));
+            assert_eq!(errors[0].line, 1);
+            assert!(errors[0].message.contains("at step 1"));
+        }
+        CompileResult::Success { .. } => panic!("[Additional-PoseOverflow] expected E018"),
+    }
+}
+
+/// Additional: ON_COLLISION=WAIT delays the lower-priority agent
+/// Input: 0: ss / 1: ss crossing at (1, 1); then PRIORITY=HIGH_ID; then a deadlock
+/// Expected: waits inserted into the later agent's commands; E014 for the deadlock
+#[test]
+fn additional_wait_scheduling() {
+    use h2lang::error::ErrorCode;
+    use h2lang::output::{AgentPose, CommandType, ToioCommand};
+    use h2lang::{compile_native_with_options, CompileOptions};
+
+    let pose = |agent_id, x, y, heading| AgentPose {
+        agent_id,
+        x,
+        y,
+        heading,
+    };
+    let options = CompileOptions {
+        start_poses: vec![pose(0, 0, 1, 1), pose(1, 1, 0, 2)],
+        ..CompileOptions::default()
+    };
+    let waits = |source: &str| {
+        let result = compile_native_with_options(source, &options);
+        let program = expect_success(result, "Additional-Wait");
+        assert!(program.warnings.is_empty(), "[Additional-Wait]");
+        program
+            .agents
+            .iter()
+            .map(|a| {
+                let is_wait = |c: &&ToioCommand| c.command_type == CommandType::Wait;
+                a.commands.iter().filter(is_wait).count()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(waits("ON_COLLISION=WAIT\n0: ss\n1: ss"), vec![0, 1]);
+    assert_eq!(
+        waits("ON_COLLISION=WAIT\nPRIORITY=HIGH_ID\n0: ss\n1: ss"),
+        vec![1, 0]
+    );
+
+    // Agent 0 stops where agent 1 is heading
+    let options = CompileOptions {
+        start_poses: vec![pose(0, 0, 0, 1), pose(1, 2, 0, 3)],
+        ..CompileOptions::default()
+    };
+    match compile_native_with_options("ON_COLLISION=WAIT\n0: s\n1: s", &options) {
+        CompileResult::Error { errors } => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, Some(ErrorCode::E014));
+            assert_eq!(errors[0].line, 3);
+        }
+        CompileResult::Success { .. } => panic!("[Additional-Wait] expected E014"),
+    }
+}
+
+/// Additional: ON_END pads the timeline for finished agents
+/// Input: 0: sss / 1: s with ON_END=STOP, ON_END=LOOP, and the on_end option;
+/// then placed looping agents with ON_COLLISION=ERROR and ON_COLLISION=WAIT
+/// Expected: every step lists both agents; end markers on last commands;
+/// E013 for the looped collision; E009 for WAIT with LOOP
+#[test]
+fn additional_on_end() {
+    use h2lang::ast::OnEnd;
+    use h2lang::error::ErrorCode;
+    use h2lang::output::AgentPose;
+    use h2lang::{compile_native_with_options, CompileOptions};
+
+    let step2 = |source: &str, on_end: Option<OnEnd>| {
+        let options = CompileOptions {
+            on_end,
+            ..CompileOptions::default()
+        };
+        let result = compile_native_with_options(source, &options);
+        let program = expect_success(result, "Additional-OnEnd");
+        assert_eq!(program.max_steps, 3, "[Additional-OnEnd]");
+        serde_json::to_value(&program.timeline[2].agent_commands).unwrap()
+    };
+
+    let stop = step2("ON_END=STOP\n0: sss\n1: s", None);
+    assert_eq!(stop[1]["command"]["type"], "wait");
+    assert_eq!(stop[0]["end"], true);
+    assert!(stop[1].get("end").is_none());
+
+    // The option overrides the directive
+    let looped = step2("ON_END=STOP\n0: sss\n1: s", Some(OnEnd::Loop));
+    assert_eq!(looped[1]["command"]["type"], "straight");
+    assert_eq!(looped[1]["end"], true);
+
+    let omitted = step2("0: sss\n1: s", None);
+    assert_eq!(omitted.as_array().unwrap().len(), 1);
+
+    // Looping agents keep moving and are checked for collisions: agent 0
+    // drives into agent 1, which turns on the spot two cells ahead
+    let pose = |agent_id, y| AgentPose {
+        agent_id,
+        x: 0,
+        y,
+        heading: 0,
+    };
+    let options = CompileOptions {
+        start_poses: vec![pose(0, 0), pose(1, -2)],
+        ..CompileOptions::default()
+    };
+    match compile_native_with_options("ON_END=LOOP\nON_COLLISION=ERROR\n0: s\n1: rrr", &options) {
+        CompileResult::Error { errors } => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, Some(ErrorCode::E013));
+            assert!(
+                errors[0].message.contains("at step 1"),
+                "{}",
+                errors[0].message
+            );
+        }
+        CompileResult::Success { .. } => panic!("[Additional-OnEnd] expected E013"),
+    }
+    // Waits cannot be planned for agents that never stop
+    match compile_native_with_options("ON_END=LOOP\nON_COLLISION=WAIT\n0: s\n1: rrr", &options) {
+        CompileResult::Error { errors } => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, Some(ErrorCode::E009));
+            assert_eq!(errors[0].line, 2);
+        }
+        CompileResult::Success { .. } => panic!("[Additional-OnEnd] expected E009"),
+    }
+}
+
+/// Additional: wait primitive (EXTENSION=WAIT) and strict HOJ mode
+/// Input: EXTENSION=WAIT / 0: srws; then the same in strict HOJ mode
+/// Expected: a wait command between r and s; E015 in strict mode
+#[test]
+fn additional_wait_primitive() {
+    use h2lang::error::ErrorCode;
+    use h2lang::output::CommandType;
+    use h2lang::{compile_native_with_options, CompileOptions};
+
+    let source = "EXTENSION=WAIT\n0: srws";
+    let program = compile_program(source, "Additional-WaitPrimitive");
+    let commands = &program.agents[0].commands;
+    assert_eq!(commands.len(), 4, "[Additional-WaitPrimitive]");
+    assert_eq!(commands[2].command_type, CommandType::Wait);
+
+    let strict = CompileOptions {
+        strict_hoj: true,
+        ..CompileOptions::default()
+    };
+    match compile_native_with_options(source, &strict) {
+        CompileResult::Error { errors } => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, Some(ErrorCode::E015));
+            assert_eq!(errors[0].line, 1);
+        }
+        CompileResult::Success { .. } => panic!("[Additional-WaitPrimitive] expected E015"),
+    }
+    // Without the extension, `w` is a function name in either mode
+    assert!(matches!(
+        compile_native_with_options("0: w:sr ww", &strict),
+        CompileResult::Success { .. }
+    ));
+}
+
+/// Additional: command compaction
+/// Input: EXTENSION=WAIT / 0: sssrrrwlrsss (with compaction enabled)
+/// Expected: straight ×3, one left turn, a wait, straight ×3; the cancelling
+/// `lr` is covered by no compacted command
+#[test]
+fn additional_compaction() {
+    use h2lang::compact::command_at;
+    use h2lang::output::CommandType;
+    use h2lang::{compile_native_with_options, CompileOptions};
+
+    let options = CompileOptions {
+        compact: true,
+        ..CompileOptions::default()
+    };
+    let result = compile_native_with_options("EXTENSION=WAIT\n0: sssrrrwlrsss", &options);
+    let program = expect_success(result, "Additional-Compaction");
+    assert_eq!(program.timeline.len(), 12, "[Additional-Compaction]");
+    let compacted = program.agents[0].compacted.as_ref().unwrap();
+    let summary: Vec<_> = compacted
+        .iter()
+        .map(|c| (c.command.command_type, c.start_step, c.end_step))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (CommandType::Straight, 0, 3),
+            (CommandType::RotateLeft, 3, 6),
+            (CommandType::Wait, 6, 7),
+            (CommandType::Straight, 9, 12),
+        ],
+        "[Additional-Compaction]"
+    );
+    assert_eq!(compacted[3].command.steps, Some(3));
+    assert_eq!(command_at(compacted, 4), Some(1));
+    assert_eq!(command_at(compacted, 8), None);
+}
+
+/// Additional: physical-time timeline
+/// Input: 0: sss / 1: r, timing with 1000 ms moves, agent 1 turning in 400 ms
+/// and a barrier at step 2
+/// Expected: agent 0 at 0, 1000 and 2000 ms; agent 1 from 0 to 400 ms
+#[test]
+fn additional_timed_timeline() {
+    use h2lang::timing::{AgentDurations, Durations, TimingOptions};
+    use h2lang::{compile_native_with_options, CompileOptions};
+
+    let timing = TimingOptions {
+        durations: Durations {
+            straight_ms: 1000,
+            ..Durations::default()
+        },
+        agents: vec![AgentDurations {
+            agent_id: 1,
+            straight_ms: None,
+            turn_ms: Some(400),
+            wait_ms: None,
+        }],
+        barriers: vec![2],
+    };
+    let options = CompileOptions {
+        timing: Some(timing),
+        ..CompileOptions::default()
+    };
+    let result = compile_native_with_options("0: sss\n1: r", &options);
+    let program = expect_success(result, "Additional-Timing");
+    let json = serde_json::to_value(program.timed_timeline.unwrap()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {
+                "time_ms": 0,
+                "agent_commands": [
+                    { "agent_id": 0, "step": 0, "command": { "type": "straight", "steps": 1 }, "start_ms": 0, "end_ms": 1000 },
+                    { "agent_id": 1, "step": 0, "command": { "type": "rotate_right", "angle": 90 }, "start_ms": 0, "end_ms": 400 },
+                ],
+            },
+            {
+                "time_ms": 1000,
+                "agent_commands": [
+                    { "agent_id": 0, "step": 1, "command": { "type": "straight", "steps": 1 }, "start_ms": 1000, "end_ms": 2000 },
+                ],
+            },
+            {
+                "time_ms": 2000,
+                "agent_commands": [
+                    { "agent_id": 0, "step": 2, "command": { "type": "straight", "steps": 1 }, "start_ms": 2000, "end_ms": 3000 },
+                ],
+            },
+        ]),
+        "[Additional-Timing]"
+    );
+    // The step-indexed timeline is unchanged
+    assert_eq!(program.timeline.len(), 3);
+}
+
+/// Additional: shared definitions before the first agent line
+/// Input: f(X):XX g:s / 0: f(g) / 1: g:r f(g)
+/// Expected: agent 0 runs ss; agent 1's own g shadows the shared one (rr);
+/// an error in a shared body is reported once; an expression there is E017
+#[test]
+fn additional_shared_definitions() {
+    use h2lang::error::ErrorCode;
+
+    let agents = |source: &str| -> Vec<String> {
+        let program = compile_program(source, "Additional-Shared");
+        program.agents.iter().map(command_letters).collect()
+    };
+
+    assert_eq!(agents("f(X):XX g:s\n0: f(g)\n1: g:r f(g)"), ["ss", "rr"]);
+    // The shared body calls the agent's own `h`
+    assert_eq!(agents("f:hh\n0: h:s f\n1: h:l f"), ["ss", "ll"]);
+
+    let errors = match compile_native("f:x\n0: f\n1: f") {
+        CompileResult::Error { errors } => errors,
+        CompileResult::Success { .. } => panic!("[Additional-Shared] expected E001"),
+    };
+    assert_eq!(errors.len(), 1, "[Additional-Shared] {:?}", errors);
+    assert_eq!(errors[0].code, Some(ErrorCode::E001));
+    assert_eq!(errors[0].line, 1);
+
+    let errors = match compile_native("f:s f\n0: f") {
+        CompileResult::Error { errors } => errors,
+        CompileResult::Success { .. } => panic!("[Additional-Shared] expected E017"),
+    };
+    assert_eq!(errors[0].code, Some(ErrorCode::E017));
+}
@@ -0,0 +1,195 @@
+//! toio payload golden tests
+//!
+//! Each test encodes a program with `toio_payloads_native` and compares every
+//! payload, written as hex bytes, with the expected writes to the motor
+//! control characteristic.
+
+use h2lang::output::AgentPose;
+use h2lang::toio::{MatBounds, ToioMode, ToioOptions};
+use h2lang::{toio_payloads_native, toio_targets_native};
+
+// =============================================================================
+// Test Helpers
+// =============================================================================
+
+/// Encode `source` and compare each agent's payloads with `expected`: one
+/// line per payload, agents separated by a `#<id>` line.
+fn assert_payloads(source: &str, options: &ToioOptions, expected: &str) {
+    let agents = toio_payloads_native(source, options)
+        .unwrap_or_else(|errors| panic!("{:?} failed to encode: {:?}", source, errors));
+    let mut actual = String::new();
+    for agent in &agents {
+        actual.push_str(&format!("#{}\n", agent.agent_id));
+        for payload in &agent.payloads {
+            let bytes: Vec<String> = payload.iter().map(|b| format!("{:02x}", b)).collect();
+            actual.push_str(&bytes.join(" "));
+            actual.push('\n');
+        }
+    }
+    let expected = expected.strip_prefix('\n').unwrap_or(expected);
+    assert_eq!(
+        actual, expected,
+        "\nsource: {}\nactual:\n{}\nexpected:\n{}",
+        source, actual, expected
+    );
+}
+
+// =============================================================================
+// Timed Motor Control
+// =============================================================================
+
+/// Square: forward and right turn, four times
+#[test]
+fn golden_timed_square() {
+    assert_payloads(
+        "a:sr aaaa",
+        &ToioOptions::default(),
+        "
+#0
+02 01 01 32 02 01 32 32
+02 01 01 32 02 02 32 19
+02 01 01 32 02 01 32 32
+02 01 01 32 02 02 32 19
+02 01 01 32 02 01 32 32
+02 01 01 32 02 02 32 19
+02 01 01 32 02 01 32 32
+02 01 01 32 02 02 32 19
+",
+    );
+}
+
+/// Custom speed and timing, with a wait primitive
+#[test]
+fn golden_timed_custom() {
+    let options = ToioOptions {
+        speed: 100,
+        step_ms: 800,
+        turn_ms: 420,
+        ..ToioOptions::default()
+    };
+    assert_payloads(
+        "EXTENSION=WAIT\n0: slw\n1: r",
+        &options,
+        "
+#0
+02 01 01 64 02 01 64 50
+02 01 02 64 02 01 64 2a
+02 01 01 00 02 01 00 50
+#1
+02 01 01 64 02 02 64 2a
+",
+    );
+}
+
+// =============================================================================
+// Target Position Control
+// =============================================================================
+
+/// Two placed cubes: positions and absolute angles on the mat
+#[test]
+fn golden_target_two_cubes() {
+    let options = ToioOptions {
+        mode: ToioMode::Target,
+        speed: 80,
+        step_distance: 30,
+        origin: [100, 100],
+        start_poses: vec![
+            AgentPose {
+                agent_id: 0,
+                x: 0,
+                y: 0,
+                heading: 1,
+            },
+            AgentPose {
+                agent_id: 1,
+                x: 2,
+                y: 2,
+                heading: 0,
+            },
+        ],
+        ..ToioOptions::default()
+    };
+    // Agent 0: (1, 0) east, then south; agent 1: (2, 1) north, (2, 0) north
+    assert_payloads(
+        "0: sr\n1: ss",
+        &options,
+        "
+#0
+03 00 00 00 50 00 00 82 00 64 00 00 00
+03 01 00 00 50 00 00 82 00 64 00 5a 00
+#1
+03 00 00 00 50 00 00 a0 00 82 00 0e 01
+03 01 00 00 50 00 00 a0 00 64 00 0e 01
+",
+    );
+}
+
+/// Leaving the mat is an E016 error at the agent's line
+#[test]
+fn target_out_of_range() {
+    let options = ToioOptions {
+        mode: ToioMode::Target,
+        ..ToioOptions::default()
+    };
+    let errors = toio_payloads_native("0: s\n1: ssssss", &options).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, Some(h2lang::error::ErrorCode::E016));
+    assert_eq!(errors[0].line, 2);
+    assert_eq!(
+        errors[0].message,
+        "[E016] Agent 1 moves to mat position (250, 10) at step 5, outside the mat"
+    );
+}
+
+// =============================================================================
+// Mat Targets
+// =============================================================================
+
+/// Each command becomes an absolute mat pose, serialized as JSON
+#[test]
+fn golden_mat_targets_json() {
+    let options = ToioOptions {
+        step_distance: 50,
+        origin: [100, 400],
+        start_poses: vec![AgentPose {
+            agent_id: 0,
+            x: 0,
+            y: 0,
+            heading: 1,
+        }],
+        ..ToioOptions::default()
+    };
+    let agents = toio_targets_native("0: srs", &options).unwrap();
+    assert_eq!(
+        serde_json::to_value(&agents).unwrap(),
+        serde_json::json!([{
+            "agent_id": 0,
+            "start": { "x": 100, "y": 400, "angle": 0 },
+            "targets": [
+                { "x": 150, "y": 400, "angle": 0 },
+                { "x": 150, "y": 400, "angle": 90 },
+                { "x": 150, "y": 450, "angle": 90 },
+            ],
+        }])
+    );
+}
+
+/// Custom mat bounds apply to the start pose as well
+#[test]
+fn mat_targets_custom_bounds() {
+    let options = ToioOptions {
+        origin: [100, 100],
+        mat: MatBounds {
+            min_x: 98,
+            min_y: 142,
+            max_x: 402,
+            max_y: 358,
+        },
+        ..ToioOptions::default()
+    };
+    let errors = toio_targets_native("0: l", &options).unwrap_err();
+    assert_eq!(
+        errors[0].message,
+        "[E016] Agent 0 starts at mat position (100, 100), outside the mat"
+    );
+}